@@ -0,0 +1,58 @@
+/// RGBA color with f32 channels, nominally in 0..=1. Both renderers and the
+/// `Framebuffer` trait use this, `macroquad_adapter` converts it for the window.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Color {
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+}
+
+// the same palette as macroquad, so scenes look the same as when they used its colors
+pub const WHITE: Color = Color::new(1.00, 1.00, 1.00, 1.00);
+pub const BLACK: Color = Color::new(0.00, 0.00, 0.00, 1.00);
+pub const GRAY: Color = Color::new(0.51, 0.51, 0.51, 1.00);
+pub const RED: Color = Color::new(0.90, 0.16, 0.22, 1.00);
+pub const GREEN: Color = Color::new(0.00, 0.89, 0.19, 1.00);
+pub const BLUE: Color = Color::new(0.00, 0.47, 0.95, 1.00);
+pub const YELLOW: Color = Color::new(0.99, 0.98, 0.00, 1.00);
+pub const ORANGE: Color = Color::new(1.00, 0.63, 0.00, 1.00);
+pub const PURPLE: Color = Color::new(0.78, 0.48, 1.00, 1.00);
+
+/// 8 bits per channel, out of range values saturate to 0 or 255
+impl From<Color> for [u8; 4] {
+    fn from(color: Color) -> Self {
+        [
+            (color.r * 255.) as u8,
+            (color.g * 255.) as u8,
+            (color.b * 255.) as u8,
+            (color.a * 255.) as u8,
+        ]
+    }
+}
+
+impl From<[u8; 4]> for Color {
+    fn from(bytes: [u8; 4]) -> Self {
+        let [r, g, b, a] = bytes.map(|c| c as f32 / 255.);
+        Color::new(r, g, b, a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_saturate() {
+        let bytes: [u8; 4] = Color::new(2.0, -1.0, 0.5, 1.0).into();
+        assert_eq!(bytes, [255, 0, 127, 255]);
+        assert_eq!(Color::from([255, 0, 255, 255]), Color::new(1., 0., 1., 1.));
+        assert_eq!(Color::from(<[u8; 4]>::from(ORANGE)).r, 1.0);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::color::Color;
+
+/// Render target shared by both renderers. Coordinates start at the top left.
+pub trait Framebuffer {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    /// writes outside the buffer are ignored and return false
+    fn set_pixel(&mut self, x: usize, y: usize, color: Color) -> bool;
+    fn get_pixel(&self, x: usize, y: usize) -> Option<Color>;
+    fn clear(&mut self, color: Color);
+}
+
+/// Storage format of a single pixel
+pub trait Pixel: Copy + Default {
+    fn from_color(color: Color) -> Self;
+    fn to_color(self) -> Color;
+}
+
+/// 8 bits per channel, values are clamped to 0..=1 on write
+impl Pixel for [u8; 4] {
+    fn from_color(color: Color) -> Self {
+        color.into()
+    }
+
+    fn to_color(self) -> Color {
+        self.into()
+    }
+}
+
+/// 32 bit float per channel, stored as written with no clamping or transfer function
+impl Pixel for [f32; 4] {
+    fn from_color(color: Color) -> Self {
+        [color.r, color.g, color.b, color.a]
+    }
+
+    fn to_color(self) -> Color {
+        Color::new(self[0], self[1], self[2], self[3])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Row major pixel grid
+pub struct PixelBuffer<P> {
+    width: usize,
+    height: usize,
+    data: Vec<P>,
+}
+
+pub type Rgba8Buffer = PixelBuffer<[u8; 4]>;
+pub type LinearBuffer = PixelBuffer<[f32; 4]>;
+
+impl<P: Pixel> PixelBuffer<P> {
+    pub fn new(width: usize, height: usize, fill: Color) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![P::from_color(fill); width * height],
+        }
+    }
+
+    pub fn pixels(&self) -> &[P] {
+        &self.data
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [P] {
+        &mut self.data
+    }
+
+    /// panics when `y` is out of range
+    pub fn row(&self, y: usize) -> &[P] {
+        &self.data[y * self.width..(y + 1) * self.width]
+    }
+
+    /// panics when `y` is out of range
+    pub fn row_mut(&mut self, y: usize) -> &mut [P] {
+        &mut self.data[y * self.width..(y + 1) * self.width]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[P]> {
+        self.data.chunks_exact(self.width.max(1))
+    }
+
+    fn offset(&self, x: usize, y: usize) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y * self.width + x)
+        } else {
+            None
+        }
+    }
+}
+
+impl<P: Pixel> Framebuffer for PixelBuffer<P> {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, color: Color) -> bool {
+        match self.offset(x, y) {
+            Some(i) => {
+                self.data[i] = P::from_color(color);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn get_pixel(&self, x: usize, y: usize) -> Option<Color> {
+        self.offset(x, y).map(|i| self.data[i].to_color())
+    }
+
+    fn clear(&mut self, color: Color) {
+        self.data.fill(P::from_color(color));
+    }
+}
+
+impl Rgba8Buffer {
+    pub fn as_bytes(&self) -> &[u8] {
+        self.data.as_flattened()
+    }
+}
+
+impl LinearBuffer {
+    /// quantize to 8 bits per channel, see `Pixel` for [u8; 4]
+    pub fn to_rgba8(&self) -> Rgba8Buffer {
+        Rgba8Buffer {
+            width: self.width,
+            height: self.height,
+            data: self.data.iter().map(|p| p.to_color().into()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::{BLACK, WHITE};
+
+    const RED: Color = Color::new(1.0, 0.0, 0.0, 1.0);
+
+    #[test]
+    fn writes_are_bounds_checked() {
+        let mut fb = Rgba8Buffer::new(4, 3, BLACK);
+        assert!(fb.set_pixel(3, 2, RED));
+        assert!(!fb.set_pixel(4, 0, RED));
+        assert!(!fb.set_pixel(0, 3, RED));
+        assert!(!fb.set_pixel(usize::MAX, 0, RED));
+        assert_eq!(fb.get_pixel(3, 2), Some(RED));
+        assert_eq!(fb.get_pixel(4, 2), None);
+        assert_eq!(
+            fb.pixels().iter().filter(|p| **p != [0, 0, 0, 255]).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn rows() {
+        let mut fb = Rgba8Buffer::new(2, 2, BLACK);
+        fb.set_pixel(1, 1, WHITE);
+        assert_eq!(fb.row(1), &[[0, 0, 0, 255], [255, 255, 255, 255]]);
+        fb.row_mut(0)[0] = [1, 2, 3, 4];
+        assert_eq!(&fb.as_bytes()[..4], &[1, 2, 3, 4]);
+        assert_eq!(fb.rows().count(), 2);
+    }
+
+    #[test]
+    fn linear_keeps_out_of_range_values() {
+        let mut fb = LinearBuffer::new(1, 1, BLACK);
+        fb.set_pixel(0, 0, Color::new(2.0, -1.0, 0.5, 1.0));
+        assert_eq!(fb.get_pixel(0, 0), Some(Color::new(2.0, -1.0, 0.5, 1.0)));
+        assert_eq!(fb.to_rgba8().pixels(), &[[255, 0, 127, 255]]);
+    }
+}
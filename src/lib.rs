@@ -0,0 +1,5 @@
+pub mod color;
+pub mod framebuffer;
+pub mod macroquad_adapter;
+pub mod rasterizer;
+pub mod raytracer;
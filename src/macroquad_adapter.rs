@@ -0,0 +1,83 @@
+//! Conversions between the crate's types and macroquad's, for the window in the
+//! binary. The renderers themselves never touch macroquad.
+
+use crate::{
+    color::Color,
+    framebuffer::{Framebuffer, Rgba8Buffer},
+    rasterizer::texture::Texture,
+};
+use macroquad::{
+    color::Color as MqColor,
+    texture::{Image, Texture2D},
+};
+
+impl From<Color> for MqColor {
+    fn from(color: Color) -> Self {
+        MqColor::new(color.r, color.g, color.b, color.a)
+    }
+}
+
+impl From<MqColor> for Color {
+    fn from(color: MqColor) -> Self {
+        Color::new(color.r, color.g, color.b, color.a)
+    }
+}
+
+impl From<&Rgba8Buffer> for Image {
+    fn from(buffer: &Rgba8Buffer) -> Self {
+        Image {
+            bytes: buffer.as_bytes().to_vec(),
+            width: buffer.width() as u16,
+            height: buffer.height() as u16,
+        }
+    }
+}
+
+impl From<&Image> for Texture {
+    fn from(image: &Image) -> Self {
+        let (width, height) = (image.width(), image.height());
+        let texels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| image.get_pixel(x as u32, y as u32).into())
+            .collect();
+        Texture::new(width, height, texels)
+    }
+}
+
+/// upload to the gpu, needs a running macroquad window
+pub fn to_texture(buffer: &Rgba8Buffer) -> Texture2D {
+    Texture2D::from_rgba8(
+        buffer.width() as u16,
+        buffer.height() as u16,
+        buffer.as_bytes(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::{BLACK, RED};
+
+    #[test]
+    fn image_adapter() {
+        let mut fb = Rgba8Buffer::new(3, 2, BLACK);
+        fb.clear(RED);
+        let image = Image::from(&fb);
+        assert_eq!((image.width(), image.height()), (3, 2));
+        assert_eq!(
+            Color::from(image.get_pixel(2, 1)),
+            fb.get_pixel(2, 1).unwrap()
+        );
+
+        let texture = Texture::from(&image);
+        assert_eq!((texture.width, texture.height), (3, 2));
+        assert_eq!(texture.texels[5], fb.get_pixel(2, 1).unwrap());
+    }
+
+    #[test]
+    fn colors_round_trip() {
+        let mq = MqColor::from(RED);
+        assert_eq!(mq, macroquad::color::RED);
+        assert_eq!(Color::from(mq), RED);
+    }
+}
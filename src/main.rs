@@ -1,8 +1,18 @@
+use graphics_from_scratch::{
+    framebuffer::Rgba8Buffer,
+    macroquad_adapter,
+    rasterizer::{
+        self,
+        antialiasing::{AntiAliasing, SampleCount},
+        culling::CullStats,
+        light::ShadingModel,
+        main::{City, CityModels, RenderContext},
+        shadows::ShadowSettings,
+    },
+    raytracer,
+};
 use macroquad::{prelude::*, window::Conf};
 
-mod rasterizer;
-mod raytracer;
-
 fn window_conf() -> Conf {
     Conf {
         window_title: "Graphics From Scratch".to_owned(),
@@ -17,11 +27,30 @@ enum Program {
     Rasterizer,
 }
 
+/// which rasterizer scene the viewer shows
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Scene {
+    Cubes,
+    Textured,
+    City,
+}
+
+impl Scene {
+    /// `scene`, or back to the cubes when it's already showing
+    fn toggle(self, scene: Scene) -> Scene {
+        if self == scene {
+            Scene::Cubes
+        } else {
+            scene
+        }
+    }
+}
+
 async fn rt(init_width: f32, init_height: f32) {
     let mut width = init_width;
     let mut height = init_height;
     let mut resize_texture = false;
-    let mut texture = render_raytracer_scene(width, height, BLACK);
+    let (mut texture, mut stats) = render_raytracer_scene(width, height, BLACK);
 
     loop {
         if width != screen_width() || height != screen_height() {
@@ -32,10 +61,11 @@ async fn rt(init_width: f32, init_height: f32) {
             width = screen_width();
             height = screen_height();
             texture.delete();
-            texture = render_raytracer_scene(width, height, BLACK);
+            (texture, stats) = render_raytracer_scene(width, height, BLACK);
         }
         draw_screen(texture, BLACK);
         draw_stats(WHITE);
+        draw_lines(&stats.lines(), WHITE);
         next_frame().await
     }
 }
@@ -44,13 +74,52 @@ async fn rast(init_width: f32, init_height: f32) {
     let mut width = init_width;
     let mut height = init_height;
     let mut resize_texture = false;
+    let mut scene = Scene::Cubes;
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut ctx = RenderContext::new(width as usize, height as usize).with_threads(threads);
+    // built up front so toggling to the city and re-rendering it stays quick
+    let city_models = CityModels::default();
+    let city = City::new(&city_models);
 
-    let mut texture = render_rasterizer_scene(width, height, BLACK);
+    let (mut texture, mut cull_stats) = render_rasterizer_scene(&ctx, BLACK, scene, &city);
     loop {
         clear_background(BLACK);
         if is_key_pressed(KeyCode::Escape) {
             return;
         }
+        // set by every setting that changes the image, which is then rendered once
+        let mut dirty = false;
+        if let Some(shading) = shading_model_key() {
+            ctx.shading_model = shading;
+            dirty = true;
+        }
+        if let Some(anti_aliasing) = anti_aliasing_key() {
+            ctx.anti_aliasing = anti_aliasing;
+            dirty = true;
+        }
+        if is_key_pressed(KeyCode::W) {
+            ctx.render_mode = ctx.render_mode.next();
+            dirty = true;
+        }
+        if is_key_pressed(KeyCode::L) {
+            ctx.anti_aliased_lines = !ctx.anti_aliased_lines;
+            dirty = true;
+        }
+        if is_key_pressed(KeyCode::H) {
+            ctx.shadows = match ctx.shadows {
+                Some(_) => None,
+                None => Some(ShadowSettings::default()),
+            };
+            dirty = true;
+        }
+        if is_key_pressed(KeyCode::T) {
+            scene = scene.toggle(Scene::Textured);
+            dirty = true;
+        }
+        if is_key_pressed(KeyCode::C) {
+            scene = scene.toggle(Scene::City);
+            dirty = true;
+        }
         if width != screen_width() || height != screen_height() {
             resize_texture = !resize_texture;
         }
@@ -58,11 +127,19 @@ async fn rast(init_width: f32, init_height: f32) {
             resize_texture = !resize_texture;
             width = screen_width();
             height = screen_height();
+            ctx.width = width as usize;
+            ctx.height = height as usize;
+            dirty = true;
+        }
+        if dirty {
             texture.delete();
-            texture = render_rasterizer_scene(width, height, BLACK);
+            (texture, cull_stats) = render_rasterizer_scene(&ctx, BLACK, scene, &city);
         }
         draw_screen(texture, BLACK);
         draw_stats(WHITE);
+        if let Some(cull_stats) = &cull_stats {
+            draw_lines(&cull_stats.lines(), WHITE);
+        }
 
         next_frame().await
     }
@@ -72,7 +149,12 @@ async fn rast(init_width: f32, init_height: f32) {
 async fn main() {
     let width = screen_width();
     let height = screen_height();
-    let program = Program::Rasterizer;
+    // `cargo run -- raytracer` shows the raytracer instead
+    let program = if std::env::args().skip(1).any(|arg| arg == "raytracer") {
+        Program::RayTracer
+    } else {
+        Program::Rasterizer
+    };
 
     match program {
         Program::RayTracer => rt(width, height).await,
@@ -99,6 +181,44 @@ fn draw_stats(color: Color) {
     );
 }
 
+/// F, G and P pick flat, Gouraud and Phong shading
+fn shading_model_key() -> Option<ShadingModel> {
+    if is_key_pressed(KeyCode::F) {
+        Some(ShadingModel::Flat)
+    } else if is_key_pressed(KeyCode::G) {
+        Some(ShadingModel::Gouraud)
+    } else if is_key_pressed(KeyCode::P) {
+        Some(ShadingModel::Phong)
+    } else {
+        None
+    }
+}
+
+fn anti_aliasing_key() -> Option<AntiAliasing> {
+    if is_key_pressed(KeyCode::N) {
+        Some(AntiAliasing::Off)
+    } else if is_key_pressed(KeyCode::M) {
+        Some(AntiAliasing::Multisample(SampleCount::X4))
+    } else if is_key_pressed(KeyCode::S) {
+        Some(AntiAliasing::Supersample(SampleCount::X4))
+    } else {
+        None
+    }
+}
+
+/// below the frame stats, like `RenderStats::lines` and `CullStats::lines`
+fn draw_lines(lines: &[String], color: Color) {
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(
+            line.as_str(),
+            screen_width() * 0.01,
+            (screen_height() * 0.03) + 20.0 * (i + 2) as f32,
+            24.0,
+            color,
+        );
+    }
+}
+
 fn draw_screen(buffer: Texture2D, color: Color) {
     clear_background(color);
     draw_texture(
@@ -109,16 +229,34 @@ fn draw_screen(buffer: Texture2D, color: Color) {
     );
 }
 
-fn render_raytracer_scene(width: f32, height: f32, fill_color: Color) -> Texture2D {
-    let mut image = Image::gen_image_color(width as u16, height as u16, fill_color);
-    raytracer::render::run(&mut image, width, height);
-    Texture2D::from_image(&image)
+fn render_raytracer_scene(
+    width: f32,
+    height: f32,
+    fill_color: Color,
+) -> (Texture2D, raytracer::stats::RenderStats) {
+    let mut buffer = Rgba8Buffer::new(width as usize, height as usize, fill_color.into());
+    let stats = raytracer::render::run(&mut buffer, width, height);
+    (macroquad_adapter::to_texture(&buffer), stats)
 }
 
-fn render_rasterizer_scene(width: f32, height: f32, fill_color: Color) -> Texture2D {
-    let mut image = Image::gen_image_color(width as u16, height as u16, fill_color);
-    let width = image.width();
-    let height = image.height();
-    rasterizer::main::run(&mut image, width, height);
-    Texture2D::from_image(&image)
+/// the scene's image, and what was culled when it's the city
+fn render_rasterizer_scene(
+    ctx: &RenderContext,
+    fill_color: Color,
+    scene: Scene,
+    city: &City,
+) -> (Texture2D, Option<CullStats>) {
+    let mut buffer = Rgba8Buffer::new(ctx.width, ctx.height, fill_color.into());
+    let stats = match scene {
+        Scene::Cubes => {
+            rasterizer::main::run(&mut buffer, ctx);
+            None
+        }
+        Scene::Textured => {
+            rasterizer::main::run_textured(&mut buffer, ctx);
+            None
+        }
+        Scene::City => Some(city.render(&mut buffer, ctx)),
+    };
+    (macroquad_adapter::to_texture(&buffer), stats)
 }
@@ -0,0 +1,303 @@
+//! Several coverage samples per pixel, resolved to one color at the end of a render.
+//! Supersampling shades every sample, multisampling tests coverage and depth
+//! for every sample but shades once per pixel and copies the color to the
+//! samples it covered. Either way the samples' colors are averaged on resolve.
+
+use super::{
+    blending::BlendMode,
+    coverage::{FixedPoint, Rect, SUBPIXEL},
+    main::RenderContext,
+};
+use crate::color::Color;
+use crate::framebuffer::Framebuffer;
+
+/// offset in sixteenths of a pixel to fixed point
+const fn sixteenths(x: i64, y: i64) -> FixedPoint {
+    [x * SUBPIXEL / 16, y * SUBPIXEL / 16]
+}
+
+// the standard D3D sample positions, no two share a row or column
+const CENTER: [FixedPoint; 1] = [[0, 0]];
+const PATTERN_2: [FixedPoint; 2] = [sixteenths(4, 4), sixteenths(-4, -4)];
+const PATTERN_4: [FixedPoint; 4] = [
+    sixteenths(-2, -6),
+    sixteenths(6, -2),
+    sixteenths(-6, 2),
+    sixteenths(2, 6),
+];
+const PATTERN_8: [FixedPoint; 8] = [
+    sixteenths(1, -3),
+    sixteenths(-1, 3),
+    sixteenths(5, 1),
+    sixteenths(-3, -5),
+    sixteenths(-5, 5),
+    sixteenths(-7, -1),
+    sixteenths(3, 7),
+    sixteenths(7, -7),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleCount {
+    X2,
+    X4,
+    X8,
+}
+
+impl SampleCount {
+    pub fn count(self) -> usize {
+        self.offsets().len()
+    }
+
+    /// sample positions relative to the pixel center
+    pub fn offsets(self) -> &'static [FixedPoint] {
+        match self {
+            SampleCount::X2 => &PATTERN_2,
+            SampleCount::X4 => &PATTERN_4,
+            SampleCount::X8 => &PATTERN_8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AntiAliasing {
+    /// one sample at the pixel center
+    #[default]
+    Off,
+    /// shade every sample
+    Supersample(SampleCount),
+    /// shade once per pixel, coverage and depth for every sample
+    Multisample(SampleCount),
+}
+
+impl AntiAliasing {
+    pub fn samples(self) -> usize {
+        self.offsets().len()
+    }
+
+    /// sample positions relative to the pixel center
+    pub fn offsets(self) -> &'static [FixedPoint] {
+        match self {
+            AntiAliasing::Off => &CENTER,
+            AntiAliasing::Supersample(count) | AntiAliasing::Multisample(count) => count.offsets(),
+        }
+    }
+}
+
+/// Colors of every sample of the pixels in `rect`, row major with the samples of a
+/// pixel next to each other. It takes canvas coordinates and ignores writes outside
+/// `rect`. Writing a whole pixel with `Framebuffer::set_pixel` sets all of its samples.
+pub struct SampleBuffer {
+    width: usize,
+    height: usize,
+    rect: Rect,
+    samples: usize,
+    /// `None` for samples that weren't drawn, they keep the color underneath on resolve
+    colors: Vec<Option<Color>>,
+    /// what the framebuffer had under each pixel, for blending onto samples that weren't drawn
+    background: Vec<Color>,
+}
+
+impl SampleBuffer {
+    /// `rect` of `fb`, with `ctx.anti_aliasing`'s samples
+    pub fn new(ctx: &RenderContext, rect: Rect, fb: &impl Framebuffer) -> Self {
+        let samples = ctx.anti_aliasing.samples();
+        let clear = Color::new(0., 0., 0., 0.);
+        let background = (rect.y0..rect.y1)
+            .flat_map(|y| (rect.x0..rect.x1).map(move |x| (x, y)))
+            .map(|(x, y)| fb.get_pixel(x, y).unwrap_or(clear))
+            .collect();
+        Self {
+            width: ctx.width,
+            height: ctx.height,
+            rect,
+            samples,
+            colors: vec![None; (rect.x1 - rect.x0) * (rect.y1 - rect.y0) * samples],
+            background,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// index of the pixel's first sample
+    fn offset(&self, x: usize, y: usize) -> Option<usize> {
+        let rect = self.rect;
+        let inside = (rect.x0..rect.x1).contains(&x) && (rect.y0..rect.y1).contains(&y);
+        inside.then(|| ((x - rect.x0) + (rect.x1 - rect.x0) * (y - rect.y0)) * self.samples)
+    }
+
+    pub fn set_sample(&mut self, x: usize, y: usize, sample: usize, color: Color) -> bool {
+        match self.offset(x, y) {
+            Some(offset) => {
+                self.colors[offset + sample] = Some(color);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// the sample's color, or the background when nothing was drawn to it
+    pub fn sample(&self, x: usize, y: usize, sample: usize) -> Option<Color> {
+        let offset = self.offset(x, y)?;
+        Some(self.colors[offset + sample].unwrap_or(self.background[offset / self.samples]))
+    }
+
+    /// Mixes `color` into the sample, `coverage` of 1 replaces it.
+    pub fn blend_sample(
+        &mut self,
+        x: usize,
+        y: usize,
+        sample: usize,
+        color: Color,
+        coverage: f32,
+    ) -> bool {
+        let Some(under) = self.sample(x, y, sample) else {
+            return false;
+        };
+        let mix = |under: f32, over: f32| under * (1. - coverage) + over * coverage;
+        let mixed = Color::new(
+            mix(under.r, color.r),
+            mix(under.g, color.g),
+            mix(under.b, color.b),
+            mix(under.a, color.a),
+        );
+        self.set_sample(x, y, sample, mixed)
+    }
+
+    /// Draws `color` onto the sample with `mode`, opaque replaces it.
+    pub fn composite_sample(
+        &mut self,
+        x: usize,
+        y: usize,
+        sample: usize,
+        color: Color,
+        mode: BlendMode,
+    ) -> bool {
+        let Some(under) = self.sample(x, y, sample) else {
+            return false;
+        };
+        self.set_sample(x, y, sample, mode.apply(under, color))
+    }
+
+    /// Writes every pixel that was drawn to into `fb`, averaging its samples.
+    /// Samples that weren't drawn count as the background.
+    pub fn resolve(&self, fb: &mut impl Framebuffer) {
+        let rect = self.rect;
+        let mut pixels = self.colors.chunks(self.samples).zip(&self.background);
+        for y in rect.y0..rect.y1 {
+            for x in rect.x0..rect.x1 {
+                let (pixel, background) = pixels.next().unwrap();
+                if pixel.iter().all(Option::is_none) {
+                    continue;
+                }
+                let mut sum = [0.; 4];
+                for color in pixel {
+                    let c = color.unwrap_or(*background);
+                    for (s, c) in sum.iter_mut().zip([c.r, c.g, c.b, c.a]) {
+                        *s += c;
+                    }
+                }
+                let n = self.samples as f32;
+                fb.set_pixel(
+                    x,
+                    y,
+                    Color::new(sum[0] / n, sum[1] / n, sum[2] / n, sum[3] / n),
+                );
+            }
+        }
+    }
+}
+
+impl Framebuffer for SampleBuffer {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, color: Color) -> bool {
+        match self.offset(x, y) {
+            Some(offset) => {
+                self.colors[offset..offset + self.samples].fill(Some(color));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// the first sample
+    fn get_pixel(&self, x: usize, y: usize) -> Option<Color> {
+        self.offset(x, y).and_then(|offset| self.colors[offset])
+    }
+
+    fn clear(&mut self, color: Color) {
+        self.colors.fill(Some(color));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffer::LinearBuffer;
+
+    #[test]
+    fn patterns_are_inside_the_pixel_and_distinct() {
+        for count in [SampleCount::X2, SampleCount::X4, SampleCount::X8] {
+            let offsets = count.offsets();
+            for (i, o) in offsets.iter().enumerate() {
+                assert!(o.iter().all(|c| c.abs() < SUBPIXEL / 2));
+                // no two samples in the same row or column
+                for other in &offsets[i + 1..] {
+                    assert!(o[0] != other[0] && o[1] != other[1]);
+                }
+            }
+        }
+        assert_eq!(AntiAliasing::Multisample(SampleCount::X8).samples(), 8);
+        assert_eq!(AntiAliasing::Off.samples(), 1);
+    }
+
+    #[test]
+    fn resolve_averages_with_the_background() {
+        let ctx =
+            RenderContext::new(4, 4).with_anti_aliasing(AntiAliasing::Supersample(SampleCount::X4));
+        let mut fb = LinearBuffer::new(4, 4, Color::new(0., 0., 1., 1.));
+        let mut samples = SampleBuffer::new(&ctx, Rect::new(1, 1, 3, 3), &fb);
+        let red = Color::new(1., 0., 0., 1.);
+        samples.set_sample(1, 1, 0, red);
+        samples.set_sample(1, 1, 3, red);
+        samples.set_pixel(2, 2, red);
+        assert!(!samples.set_pixel(3, 3, red));
+        samples.resolve(&mut fb);
+
+        assert_eq!(fb.get_pixel(1, 1), Some(Color::new(0.5, 0., 0.5, 1.)));
+        assert_eq!(fb.get_pixel(2, 2), Some(red));
+        assert_eq!(fb.get_pixel(2, 1), Some(Color::new(0., 0., 1., 1.)));
+        assert_eq!(fb.get_pixel(3, 3), Some(Color::new(0., 0., 1., 1.)));
+    }
+
+    #[test]
+    fn blending_starts_from_the_background() {
+        let ctx =
+            RenderContext::new(2, 2).with_anti_aliasing(AntiAliasing::Multisample(SampleCount::X2));
+        let mut fb = LinearBuffer::new(2, 2, Color::new(0., 0., 1., 1.));
+        let mut samples = SampleBuffer::new(&ctx, Rect::canvas(2, 2), &fb);
+        let red = Color::new(1., 0., 0., 1.);
+        samples.blend_sample(0, 0, 0, red, 0.25);
+        samples.blend_sample(0, 0, 1, red, 1.);
+        assert_eq!(
+            samples.sample(0, 0, 0),
+            Some(Color::new(0.25, 0., 0.75, 1.))
+        );
+        assert_eq!(samples.sample(0, 0, 1), Some(red));
+        assert_eq!(samples.sample(1, 0, 0), Some(Color::new(0., 0., 1., 1.)));
+        samples.resolve(&mut fb);
+        assert_eq!(fb.get_pixel(0, 0), Some(Color::new(0.625, 0., 0.375, 1.)));
+    }
+}
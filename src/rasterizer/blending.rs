@@ -0,0 +1,76 @@
+//! How a triangle's color is combined with what's already under it. Opaque
+//! triangles replace it and write depth. The others are drawn after all the opaque
+//! ones, farthest first, and are tested against the depth buffer without writing
+//! to it, so they don't hide each other or anything drawn after them.
+
+use crate::color::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// replaces what's under it, alpha is ignored
+    #[default]
+    Opaque,
+    /// mixed with what's under it by alpha, for glass and overlays
+    Alpha,
+    /// added to what's under it, scaled by alpha, for glows
+    Additive,
+    /// tints what's under it, alpha of 0 leaves it unchanged
+    Multiply,
+}
+
+impl BlendMode {
+    pub fn is_opaque(self) -> bool {
+        self == BlendMode::Opaque
+    }
+
+    /// `over` drawn on `under`
+    pub fn apply(self, under: Color, over: Color) -> Color {
+        let a = over.a;
+        let channels = |f: fn(f32, f32, f32) -> f32| {
+            Color::new(
+                f(under.r, over.r, a),
+                f(under.g, over.g, a),
+                f(under.b, over.b, a),
+                under.a,
+            )
+        };
+        match self {
+            BlendMode::Opaque => over,
+            BlendMode::Alpha => Color {
+                a: a + under.a * (1. - a),
+                ..channels(|d, s, a| d * (1. - a) + s * a)
+            },
+            BlendMode::Additive => channels(|d, s, a| (d + s * a).min(1.)),
+            BlendMode::Multiply => channels(|d, s, a| d * (1. - a + s * a)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blend_modes() {
+        let under = Color::new(0.5, 0.5, 1., 1.);
+        let over = Color::new(1., 0., 0.5, 0.5);
+        assert_eq!(BlendMode::Opaque.apply(under, over), over);
+        assert_eq!(
+            BlendMode::Alpha.apply(under, over),
+            Color::new(0.75, 0.25, 0.75, 1.)
+        );
+        assert_eq!(
+            BlendMode::Additive.apply(under, over),
+            Color::new(1., 0.5, 1., 1.)
+        );
+        assert_eq!(
+            BlendMode::Multiply.apply(under, over),
+            Color::new(0.5, 0.25, 0.75, 1.)
+        );
+        // fully transparent leaves it alone
+        let clear = Color { a: 0., ..over };
+        for mode in [BlendMode::Alpha, BlendMode::Additive, BlendMode::Multiply] {
+            assert_eq!(mode.apply(under, clear), under);
+        }
+    }
+}
@@ -1,41 +1,264 @@
-use super::{data_types::{Mat4x4, Vertex3, Plane}, utils::math};
+use super::{data_types::{Mat4x4, Vertex3, Vertex4, Plane}, utils::{self, math}};
+
+/// How camera space maps onto the canvas. Distances are along the camera's z axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// `fov_y` is the vertical field of view in degrees, `aspect` is width / height
+    Perspective { fov_y: f32, aspect: f32, near: f32, far: f32 },
+    /// `width` by `height` world units are visible at any distance
+    Orthographic { width: f32, height: f32, near: f32, far: f32 },
+}
+
+impl Default for Projection {
+    /// the book's 1x1 viewport at distance 1, which is about 53 degrees
+    fn default() -> Self {
+        Projection::Perspective {
+            fov_y: 2. * math::atan(0.5) * 180. / math::PI,
+            aspect: 1.,
+            near: 1.,
+            far: 1000.,
+        }
+    }
+}
+
+impl Projection {
+    /// Same projection for a canvas of a different shape, keeping the vertical extent.
+    pub fn with_aspect(self, aspect: f32) -> Self {
+        match self {
+            Projection::Perspective { fov_y, near, far, .. } => Projection::Perspective { fov_y, aspect, near, far },
+            Projection::Orthographic { height, near, far, .. } => Projection::Orthographic { width: height * aspect, height, near, far },
+        }
+    }
+
+    /// Size of the visible area, at distance 1 for perspective.
+    pub fn view_size(&self) -> (f32, f32) {
+        match *self {
+            Projection::Perspective { fov_y, aspect, .. } => {
+                let height = 2. * math::tan(fov_y * math::PI / 360.);
+                (height * aspect, height)
+            }
+            Projection::Orthographic { width, height, .. } => (width, height),
+        }
+    }
+
+    /// near, far, left, right, top, bottom
+    pub fn clipping_planes(&self) -> [Plane; 6] {
+        let (width, height) = self.view_size();
+        let (w, h) = (width / 2., height / 2.);
+        match *self {
+            Projection::Perspective { near, far, .. } => [
+                Plane::new(Vertex3::new(0., 0., 1.), -near),
+                Plane::new(Vertex3::new(0., 0., -1.), far),
+                Plane::new(Vertex3::new(1., 0., w).normalize(), 0.),
+                Plane::new(Vertex3::new(-1., 0., w).normalize(), 0.),
+                Plane::new(Vertex3::new(0., 1., h).normalize(), 0.),
+                Plane::new(Vertex3::new(0., -1., h).normalize(), 0.),
+            ],
+            Projection::Orthographic { near, far, .. } => [
+                Plane::new(Vertex3::new(0., 0., 1.), -near),
+                Plane::new(Vertex3::new(0., 0., -1.), far),
+                Plane::new(Vertex3::new(1., 0., 0.), w),
+                Plane::new(Vertex3::new(-1., 0., 0.), w),
+                Plane::new(Vertex3::new(0., 1., 0.), h),
+                Plane::new(Vertex3::new(0., -1., 0.), h),
+            ],
+        }
+    }
+
+    /// Camera space to clip space. `x` and `y` are scaled so the visible area is
+    /// -w to w, and `z` goes from w at the near plane to 0 at the far one, so the
+    /// depth buffer keeps larger values closer. `w` is the distance for perspective
+    /// and 1 for orthographic, the perspective divide by it gives the viewport.
+    pub fn matrix(&self) -> Mat4x4 {
+        let (width, height) = self.view_size();
+        let (sx, sy) = (2. / width, 2. / height);
+        match *self {
+            Projection::Perspective { near, far, .. } => Mat4x4::from_cols(
+                Vertex4::new(sx, 0., 0., 0.),
+                Vertex4::new(0., sy, 0., 0.),
+                Vertex4::new(0., 0., -near / (far - near), 1.),
+                Vertex4::new(0., 0., near * far / (far - near), 0.),
+            ),
+            Projection::Orthographic { near, far, .. } => Mat4x4::from_cols(
+                Vertex4::new(sx, 0., 0., 0.),
+                Vertex4::new(0., sy, 0., 0.),
+                Vertex4::new(0., 0., -1. / (far - near), 0.),
+                Vertex4::new(0., 0., far / (far - near), 1.),
+            ),
+        }
+    }
+
+    /// Depth buffer value at distance `z`, the clip space `z / w`. It's 1 at the near
+    /// plane and 0 at the far one, and linear in screen space so it can be
+    /// interpolated across triangles.
+    pub fn depth(&self, z: f32) -> f32 {
+        match *self {
+            Projection::Perspective { near, far, .. } => (near * far / z - near) / (far - near),
+            Projection::Orthographic { near, far, .. } => (far - z) / (far - near),
+        }
+    }
+
+    /// inverse of `depth`
+    pub fn depth_to_z(&self, depth: f32) -> f32 {
+        match *self {
+            Projection::Perspective { near, far, .. } => near * far / (depth * (far - near) + near),
+            Projection::Orthographic { near, far, .. } => far - depth * (far - near),
+        }
+    }
+
+    /// Direction from camera space point `p` towards the camera, not normalized.
+    pub fn to_camera(&self, p: Vertex3) -> Vertex3 {
+        match self {
+            Projection::Perspective { .. } => -p,
+            Projection::Orthographic { .. } => Vertex3::new(0., 0., -1.),
+        }
+    }
+
+    /// Homogeneous clip space position of camera space `p`, see `matrix`.
+    pub fn to_clip(&self, p: Vertex3) -> Vertex4 {
+        self.matrix() * p.extend(1.)
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct Camera {
     pub pos: Vertex3,
+    /// Any rotation, see `utils::make_euler_rotation_mat` and friends. A scale in
+    /// it stretches the view.
     pub orientation: Mat4x4,
-    pub clipping_planes: [Plane; 5]
-}
-
-fn clipping_planes(s2: f32) -> [Plane; 5] {
-    [
-        Plane::new(Vertex3::new(0., 0., 1.), -1.), // near
-        Plane::new(Vertex3::new(s2, 0., s2), 0.),   // left
-        Plane::new(Vertex3::new(-s2, 0., s2), 0.),  // right
-        Plane::new(Vertex3::new(0., -s2, s2), 0.),  // top
-        Plane::new(Vertex3::new(0., s2, s2), 0.),   // bottom
-    ]
+    /// set with `with_projection` so the clipping planes follow
+    pub projection: Projection,
+    pub clipping_planes: [Plane; 6]
 }
 
 impl Camera {
+    /// default projection, see `Projection::default`
     pub fn new(pos: Vertex3, orientation: Mat4x4) -> Self {
-        let s2 = math::sqrt_f(2.);
+        let projection = Projection::default();
         Self {
             pos,
             orientation,
-            clipping_planes: clipping_planes(s2)
+            projection,
+            clipping_planes: projection.clipping_planes()
+        }
+    }
+
+    pub fn with_projection(self, projection: Projection) -> Self {
+        Self {
+            projection,
+            clipping_planes: projection.clipping_planes(),
+            ..self
         }
     }
+
+    /// match a canvas of `aspect` width / height, usually `RenderContext::aspect`
+    pub fn with_aspect(self, aspect: f32) -> Self {
+        self.with_projection(self.projection.with_aspect(aspect))
+    }
+
+    /// `fov_y` in degrees, `aspect` is width / height
+    pub fn with_perspective(self, fov_y: f32, aspect: f32, near: f32, far: f32) -> Self {
+        self.with_projection(Projection::Perspective { fov_y, aspect, near, far })
+    }
+
+    pub fn with_orthographic(self, width: f32, height: f32, near: f32, far: f32) -> Self {
+        self.with_projection(Projection::Orthographic { width, height, near, far })
+    }
+
+    /// moves world space into camera space, the inverse of placing the camera
+    pub fn world_to_camera(&self) -> Mat4x4 {
+        utils::mul_mm(utils::make_translation_mat(self.pos), self.orientation).inverse()
+    }
 }
 
 impl Default for Camera {
     fn default() -> Self {
-        let s2 = math::sqrt_f(2.);
-        Self {
-            pos: Vertex3::default(),
-            orientation: Mat4x4::default(),
-            clipping_planes: clipping_planes(s2)
+        Self::new(Vertex3::default(), Mat4x4::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inside(planes: &[Plane], p: Vertex3) -> bool {
+        planes.iter().all(|plane| plane.normal.dot(p) + plane.dist >= -1e-5)
+    }
+
+    #[test]
+    fn perspective_planes_follow_fov_and_aspect() {
+        let camera = Camera::default().with_perspective(90., 2., 0.5, 10.);
+        assert_eq!(camera.projection.view_size().1, 2.);
+        let planes = &camera.clipping_planes;
+        // the frustum is twice as wide as it is high
+        assert!(inside(planes, Vertex3::new(3.9, 0., 2.)));
+        assert!(!inside(planes, Vertex3::new(4.1, 0., 2.)));
+        assert!(inside(planes, Vertex3::new(0., -1.9, 2.)));
+        assert!(!inside(planes, Vertex3::new(0., -2.1, 2.)));
+        assert!(!inside(planes, Vertex3::new(0., 0., 0.4)));
+        assert!(!inside(planes, Vertex3::new(0., 0., 10.1)));
+    }
+
+    #[test]
+    fn orthographic_planes_are_a_box() {
+        let camera = Camera::default().with_orthographic(4., 2., 1., 5.);
+        let planes = &camera.clipping_planes;
+        assert!(inside(planes, Vertex3::new(1.9, 0.9, 4.9)));
+        assert!(!inside(planes, Vertex3::new(2.1, 0., 2.)));
+        assert!(!inside(planes, Vertex3::new(0., 1.1, 2.)));
+        assert!(!inside(planes, Vertex3::new(0., 0., 5.1)));
+    }
 
+    #[test]
+    fn depth_is_larger_closer() {
+        for projection in [
+            Projection::default(),
+            Projection::Orthographic { width: 1., height: 1., near: 1., far: 10. },
+        ] {
+            assert!(projection.depth(2.) > projection.depth(3.));
+            assert!((projection.depth_to_z(projection.depth(4.)) - 4.).abs() < 1e-4);
         }
     }
+
+    #[test]
+    fn matrix_maps_the_frustum_to_clip_space() {
+        for projection in [
+            Projection::Perspective { fov_y: 90., aspect: 2., near: 0.5, far: 10. },
+            Projection::Orthographic { width: 4., height: 2., near: 0.5, far: 10. },
+        ] {
+            let (width, height) = projection.view_size();
+            let scale = |z: f32| match projection {
+                Projection::Perspective { .. } => z,
+                Projection::Orthographic { .. } => 1.,
+            };
+            // the near top left and far bottom right corners
+            let near = projection.to_clip(Vertex3::new(-width / 2. * scale(0.5), -height / 2. * scale(0.5), 0.5));
+            assert!((near / near.w).abs_diff_eq(Vertex4::new(-1., -1., 1., 1.), 1e-5));
+            let far = projection.to_clip(Vertex3::new(width / 2. * scale(10.), height / 2. * scale(10.), 10.));
+            assert!((far / far.w).abs_diff_eq(Vertex4::new(1., 1., 0., 1.), 1e-5));
+            // clip space z over w is the depth
+            let p = projection.to_clip(Vertex3::new(0.3, 0.2, 3.));
+            assert!((p.z / p.w - projection.depth(3.)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn world_to_camera_undoes_the_camera_placement() {
+        // looking straight down from above
+        let camera = Camera::new(
+            Vertex3::new(1., -5., 2.),
+            utils::make_euler_rotation_mat(0., -90., 0.),
+        );
+        let below = camera.world_to_camera().transform_point3(Vertex3::new(1., 0., 2.));
+        assert!(below.abs_diff_eq(Vertex3::new(0., 0., 5.), 1e-5));
+    }
+
+    #[test]
+    fn aspect_keeps_the_vertical_extent() {
+        let (width, height) = Projection::default().with_aspect(2.).view_size();
+        assert!((height - 1.).abs() < 1e-6);
+        assert_eq!(width, 2. * height);
+        let ortho = Projection::Orthographic { width: 1., height: 3., near: 1., far: 10. };
+        assert_eq!(ortho.with_aspect(0.5).view_size(), (1.5, 3.));
+    }
 }
@@ -1,44 +1,257 @@
-use super::{data_types::{Model, Mat4x4, Plane, Vertex4, Triangle, Vertex3}, utils::mul_mv};
+use super::{data_types::{Model, Mat4x4, Plane, Vertex4, Triangle, Vertex3}, shader::{Varyings, VertexOutput}, utils::{make_normal_mat, mul_mv}};
 
-pub fn transform_and_clip(planes: &[Plane], model: &Model, transform: Mat4x4) -> Option<Model> {
+/// Moves `model` into the space `transform` maps to, usually camera space. `None`
+/// when its bounding sphere is completely outside one of `planes`, otherwise the
+/// triangles are kept whole and clipped in clip space, see `clip_triangle`.
+pub fn transform_and_cull(planes: &[Plane], model: &Model, transform: Mat4x4) -> Option<Model> {
 	let center = mul_mv(transform, model.bounds_center);
-	let radius_sq = model.bounds_radius * model.bounds_radius;
+	let radius = model.transformed_bounds_radius(transform);
+
 	for p in planes {
-		let dist = p.normal.dot(center.truncate()) + p.dist;
-		if dist < -radius_sq {
+		if p.normal.dot(center.truncate()) + p.dist < -radius {
 			return None;
 		}
 	}
-	
+
 	let verticies: Vec<Vertex3> = model.verticies
 		.iter()
 		.map(|v| mul_mv(transform, Vertex4::new(v.x, v.y, v.z, 1.)).truncate() )
 		.collect();
-	let mut triangles: Vec<Triangle> = model.triangles.clone();
+	let normal_mat = make_normal_mat(transform);
+	let triangles: Vec<Triangle> = model.triangles
+		.iter()
+		.map(|t| Triangle {
+			normals: t.normals.map(|n| n.map(|n| normal_mat.transform_vector3(n).normalize_or_zero())),
+			..*t
+		})
+		.collect();
 
-	for p in planes {
-		let mut new_triangles: Vec<Triangle> = vec![];
-		for t in &triangles {
-			clip_triangle(t, p, &mut new_triangles, &verticies);
+	Some(Model {
+		textures: model.textures.clone(),
+		shader: model.shader.clone(),
+		attributes: model.attributes.transformed(transform, normal_mat),
+		..Model::new(triangles, verticies, center, radius)
+	})
+}
+
+/// The clip space volume, -w <= x <= w, -w <= y <= w and 0 <= z <= w, as the planes
+/// whose dot product with a position is positive inside them: far (z >= 0, with
+/// reversed depth), near (z <= w), left, right, top and bottom. See `Projection::matrix`.
+pub const CLIP_PLANES: [[f32; 4]; 6] = [
+	[0., 0., 1., 0.],
+	[0., 0., -1., 1.],
+	[1., 0., 0., 1.],
+	[-1., 0., 0., 1.],
+	[0., 1., 0., 1.],
+	[0., -1., 0., 1.],
+];
+
+/// Sutherland-Hodgman clipping of a triangle's vertex shader outputs against
+/// `CLIP_PLANES`, before the perspective divide so nothing is divided by a `w` at
+/// or behind the camera. Calls `emit` with nothing when the triangle is outside,
+/// with the triangle itself when it's inside, and with a fan of triangles when it
+/// crosses the planes. Winding order is preserved for backface culling and the
+/// varyings are interpolated along with the positions, linearly in clip space, the
+/// perspective correction happens when rasterizing.
+pub fn clip_triangle(corners: [VertexOutput; 3], mut emit: impl FnMut([VertexOutput; 3])) {
+	let mut inside = true;
+	for plane in CLIP_PLANES.map(Vertex4::from) {
+		let d = corners.map(|c| plane.dot(c.position));
+		if d.iter().all(|d| *d < 0.) {
+			return;
+		}
+		inside &= d.iter().all(|d| *d >= 0.);
+	}
+	if inside {
+		emit(corners);
+		return;
+	}
+
+	// walk the edges, keeping inside vertices and adding one where an edge crosses the plane
+	let mut polygon = corners.to_vec();
+	for plane in CLIP_PLANES.map(Vertex4::from) {
+		let mut clipped = Vec::with_capacity(polygon.len() + 1);
+		for (i, &a) in polygon.iter().enumerate() {
+			let b = polygon[(i + 1) % polygon.len()];
+			let (da, db) = (plane.dot(a.position), plane.dot(b.position));
+			if da >= 0. {
+				clipped.push(a);
+			}
+			if (da >= 0.) != (db >= 0.) {
+				clipped.push(interpolate_output(a, b, da / (da - db)));
+			}
+		}
+		polygon = clipped;
+		if polygon.len() < 3 {
+			return;
 		}
-		triangles = new_triangles.clone()
 	}
-	Some(Model::new(triangles, verticies, center, model.bounds_radius))
 
+	for k in 1..polygon.len() - 1 {
+		emit([polygon[0], polygon[k], polygon[k + 1]]);
+	}
+}
+
+fn interpolate_output(a: VertexOutput, b: VertexOutput, t: f32) -> VertexOutput {
+	VertexOutput {
+		position: a.position.lerp(b.position, t),
+		varyings: Varyings::lerp(a.varyings, b.varyings, t),
+	}
 }
 
-pub fn clip_triangle(triangle: &Triangle, plane: &Plane, tris: &mut Vec<Triangle>, verts: &Vec<Vertex3>) {
-	let v0 = verts[triangle.ind[0]];
-	let v1 = verts[triangle.ind[0]];
-	let v2 = verts[triangle.ind[0]];
-	
-	let in0 = plane.normal.dot(v0) + plane.dist > 0.;
-	let in1 = plane.normal.dot(v1) + plane.dist > 0.;
-	let in2 = plane.normal.dot(v2) + plane.dist > 0.;
-
-	let in_count = i32::from(in0) + i32::from(in1) + i32::from(in2);
-	match in_count {
-		3 => tris.push(triangle.clone()),
-		_ => {}
-	}
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::rasterizer::{color::RED, camera::Projection, data_types::Vec2};
+	use crate::color::Color;
+
+	/// camera space `p` through the default projection, with `varyings`
+	fn output(p: Vertex3, varyings: &[f32]) -> VertexOutput {
+		VertexOutput { position: Projection::default().to_clip(p), varyings: Varyings::new(varyings) }
+	}
+
+	fn clip(corners: [VertexOutput; 3]) -> Vec<[VertexOutput; 3]> {
+		let mut triangles = vec![];
+		clip_triangle(corners, |t| triangles.push(t));
+		triangles
+	}
+
+	fn is_inside(o: &VertexOutput) -> bool {
+		CLIP_PLANES.iter().all(|plane| Vertex4::from(*plane).dot(o.position) >= -1e-5)
+	}
+
+	/// signed area on the viewport
+	fn winding(t: &[VertexOutput; 3]) -> f32 {
+		let [a, b, c] = t.map(|o| (o.position / o.position.w).truncate());
+		(b - a).cross(c - a).z
+	}
+
+	#[test]
+	fn keeps_inside_and_drops_outside() {
+		let inside = [Vertex3::new(0., 0., 2.), Vertex3::new(0.5, 0., 2.), Vertex3::new(0., 0.5, 3.)].map(|p| output(p, &[]));
+		let triangles = clip(inside);
+		assert_eq!(triangles.len(), 1);
+		assert_eq!(triangles[0].map(|o| o.position), inside.map(|o| o.position));
+
+		// in front of the near plane, and behind the camera where w is negative
+		for z in [0.5, -2.] {
+			let outside = [Vertex3::new(0., 0., z), Vertex3::new(0.1, 0., z), Vertex3::new(0., 0.1, z)].map(|p| output(p, &[]));
+			assert!(clip(outside).is_empty());
+		}
+		// past the far plane and off to the side
+		let far = [Vertex3::new(0., 0., 1001.), Vertex3::new(1., 0., 1001.), Vertex3::new(0., 1., 1002.)];
+		assert!(clip(far.map(|p| output(p, &[]))).is_empty());
+		let left = [Vertex3::new(-3., 0., 2.), Vertex3::new(-2., 0., 2.), Vertex3::new(-3., 0.5, 2.)];
+		assert!(clip(left.map(|p| output(p, &[]))).is_empty());
+	}
+
+	#[test]
+	fn one_inside_shrinks_triangle() {
+		let corners = [Vertex3::new(0., 0., 3.), Vertex3::new(0.2, 0., 0.5), Vertex3::new(-0.2, 0.1, 0.5)].map(|p| output(p, &[]));
+		let triangles = clip(corners);
+
+		assert_eq!(triangles.len(), 1);
+		assert_eq!(triangles[0][0].position, corners[0].position);
+		assert!(triangles[0].iter().all(is_inside));
+		// the new corners are on the near plane, at camera space z 1
+		let near = Projection::default().to_clip(Vertex3::new(0.16, 0., 1.));
+		assert!(triangles[0][1].position.abs_diff_eq(near, 1e-5));
+		assert_eq!(winding(&triangles[0]).signum(), winding(&corners).signum());
+	}
+
+	#[test]
+	fn two_inside_makes_quad() {
+		let corners = [Vertex3::new(-0.2, 0., 3.), Vertex3::new(0.2, 0., 3.), Vertex3::new(0., 0.3, 0.5)].map(|p| output(p, &[]));
+		let triangles = clip(corners);
+
+		assert_eq!(triangles.len(), 2);
+		for t in &triangles {
+			assert!(t.iter().all(is_inside));
+			assert_eq!(winding(t).signum(), winding(&corners).signum());
+		}
+	}
+
+	#[test]
+	fn crossing_the_corner_of_the_frustum_makes_a_fan() {
+		// a big triangle around the whole view, cut by all four sides
+		let corners = [Vertex3::new(0., -20., 4.), Vertex3::new(20., 20., 4.), Vertex3::new(-20., 20., 4.)].map(|p| output(p, &[]));
+		let triangles = clip(corners);
+
+		assert_eq!(triangles.len(), 2);
+		let area: f32 = triangles.iter().map(|t| winding(t).abs() / 2.).sum();
+		assert!((area - 4.).abs() < 1e-4);
+		for t in &triangles {
+			assert!(t.iter().all(is_inside));
+			assert_eq!(winding(t).signum(), winding(&corners).signum());
+		}
+	}
+
+	#[test]
+	fn interpolates_varyings() {
+		let corners = [
+			output(Vertex3::new(-0.2, 0., 3.), &[0., 0., 1.]),
+			output(Vertex3::new(0.2, 0., 3.), &[1., 0., 1.]),
+			output(Vertex3::new(0., 0., -1.), &[0.5, 1., 1.]),
+		];
+		let triangles = clip(corners);
+
+		assert_eq!(triangles.len(), 2);
+		assert_eq!(triangles[0][0].varyings, corners[0].varyings);
+		// the near plane cuts the edges halfway in clip space
+		assert!(triangles[0][2].varyings.vec2(0).abs_diff_eq(Vec2::new(0.75, 0.5), 1e-5));
+		assert!(triangles[1][2].varyings.vec2(0).abs_diff_eq(Vec2::new(0.25, 0.5), 1e-5));
+		// values that are the same at every corner stay exact
+		assert!(triangles.iter().flatten().all(|o| o.varyings.get(2) == 1.));
+	}
+
+	#[test]
+	fn normals_follow_nonuniform_scale() {
+		// a 45 degree slope, squashed to half its height
+		let verts = vec![Vertex3::new(0., 0., 3.), Vertex3::new(1., 1., 3.), Vertex3::new(0., 0., 4.)];
+		let normal = Vertex3::new(1., -1., 0.).normalize();
+		let model = Model::from_mesh(vec![Triangle::new(0, 1, 2, RED).with_normals([normal; 3])], verts);
+		let squash = Mat4x4::from_scale(Vertex3::new(1., 0.5, 1.));
+
+		let transformed = transform_and_cull(&[], &model, squash).unwrap();
+		let [a, b, _] = transformed.triangles[0].ind.map(|i| transformed.verticies[i]);
+		let n = transformed.triangles[0].normals.unwrap()[0];
+		assert!((b - a).dot(n).abs() < 1e-6);
+		assert!(n.abs_diff_eq(Vertex3::new(1., -2., 0.).normalize(), 1e-6));
+	}
+
+	#[test]
+	fn culls_by_bounding_sphere() {
+		let near_plane = Plane::new(Vertex3::new(0., 0., 1.), -1.);
+		let model = Model::from_mesh(vec![Triangle::new(0, 1, 2, RED)], vec![Vertex3::new(-1., 0., 0.), Vertex3::new(1., 0., 0.), Vertex3::new(0., 1., 0.)]);
+		let behind = Mat4x4::from_translation(Vertex3::new(0., 0., -2.));
+		assert!(transform_and_cull(&[near_plane], &model, behind).is_none());
+		// crossing the plane keeps the triangle whole
+		let crossing = Mat4x4::from_translation(Vertex3::new(0., 0., 1.));
+		let kept = transform_and_cull(&[near_plane], &model, crossing).unwrap();
+		assert_eq!(kept.triangles.len(), 1);
+		assert_eq!(kept.verticies.len(), 3);
+	}
+
+	#[test]
+	fn transforms_vertex_attributes() {
+		let verts = vec![Vertex3::new(0., 0., 3.), Vertex3::new(2., 0., 0.), Vertex3::new(-2., 0., 0.)];
+		let colors = vec![Color::new(1., 0., 0., 1.), Color::new(0., 1., 0., 1.), Color::new(0., 0., 1., 0.)];
+		let tangents = vec![Vertex4::new(2., 0., 0., -1.), Vertex4::new(0., 0., 1., -1.), Vertex4::new(0., 0., 1., 1.)];
+		let model = Model::from_mesh(vec![Triangle::new(0, 1, 2, RED)], verts)
+			.with_vertex_colors(colors.clone())
+			.with_vertex_normals(vec![Vertex3::new(0., -1., 0.); 3])
+			.with_vertex_uvs(vec![Vec2::new(0., 0.), Vec2::new(1., 0.), Vec2::new(0., 1.)])
+			.with_vertex_tangents(tangents);
+		// a quarter turn around z
+		let turn = Mat4x4::from_rotation_z(std::f32::consts::FRAC_PI_2);
+
+		let attributes = transform_and_cull(&[], &model, turn).unwrap().attributes;
+		assert_eq!(attributes.colors, Some(colors));
+		assert_eq!(attributes.uvs, model.attributes.uvs);
+		assert!(attributes.normals.unwrap().iter().all(|n| n.abs_diff_eq(Vertex3::new(1., 0., 0.), 1e-6)));
+		// turned with the model and normalized, keeping the handedness
+		let tangents = attributes.tangents.unwrap();
+		assert!(tangents[0].abs_diff_eq(Vertex4::new(0., 1., 0., -1.), 1e-6));
+		assert_eq!(tangents[2].w, 1.);
+	}
+}
@@ -1,2 +1,2 @@
-pub use macroquad::prelude::{Color, BLUE, GREEN, ORANGE, PURPLE, RED, YELLOW};
+pub use crate::color::{Color, BLUE, GRAY, GREEN, ORANGE, PURPLE, RED, WHITE, YELLOW};
 pub const CYAN: Color = Color::new(0., 1., 1., 1.);
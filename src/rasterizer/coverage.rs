@@ -0,0 +1,303 @@
+//! Which pixels a triangle covers, using edge functions over its bounding box.
+//! Corners are snapped to a fixed point grid of `SUBPIXEL_BITS` fractional bits,
+//! so the edge functions are exact integers and triangles sharing an edge agree
+//! on every pixel along it. A pixel belongs to a triangle when its center is
+//! inside, or on a top or left edge, so shared edges are drawn exactly once.
+//! With several samples per pixel the same goes for every sample position.
+
+/// fractional bits of the fixed point screen coordinates
+pub const SUBPIXEL_BITS: u32 = 4;
+pub const SUBPIXEL: i64 = 1 << SUBPIXEL_BITS;
+const HALF_PIXEL: i64 = SUBPIXEL / 2;
+
+/// screen position with `SUBPIXEL_BITS` fractional bits, origin at the top
+/// left corner of the canvas and y down
+pub type FixedPoint = [i64; 2];
+
+pub fn to_fixed(x: f32, y: f32) -> FixedPoint {
+    let scale = SUBPIXEL as f32;
+    [(x * scale).round() as i64, (y * scale).round() as i64]
+}
+
+/// pixels from `x0`, `y0` up to but not including `x1`, `y1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl Rect {
+    pub fn new(x0: usize, y0: usize, x1: usize, y1: usize) -> Self {
+        Self { x0, y0, x1, y1 }
+    }
+
+    /// the whole of a `width` by `height` canvas
+    pub fn canvas(width: usize, height: usize) -> Self {
+        Self::new(0, 0, width, height)
+    }
+}
+
+/// Twice the signed area of `a`, `b`, `p`, positive when they go clockwise on screen.
+fn edge(a: FixedPoint, b: FixedPoint, p: FixedPoint) -> i64 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
+
+/// Twice the signed area of a triangle on screen, positive when its corners go
+/// clockwise. Front faces go counter-clockwise, so this is how the renderer and
+/// the tile binning tell back faces, after the vertex shader and projection.
+pub fn signed_area(corners: [FixedPoint; 3]) -> i64 {
+    edge(corners[0], corners[1], corners[2])
+}
+
+/// For clockwise triangles, top edges run right and left edges run up.
+fn is_top_left(a: FixedPoint, b: FixedPoint) -> bool {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    dy < 0 || (dy == 0 && dx > 0)
+}
+
+/// first pixel whose center is at or after `fixed`
+fn first_pixel(fixed: i64) -> i64 {
+    (fixed - HALF_PIXEL + SUBPIXEL - 1).div_euclid(SUBPIXEL)
+}
+
+/// Calls `shade(x, y, barycentric)` for every pixel in `clip` covered by the
+/// triangle. The barycentric weights are for the corners in the order given,
+/// either winding works and triangles without area draw nothing.
+pub fn rasterize_triangle(
+    corners: [FixedPoint; 3],
+    clip: Rect,
+    mut shade: impl FnMut(usize, usize, [f32; 3]),
+) {
+    rasterize_triangle_samples(corners, clip, &[[0, 0]], |x, y, covered| {
+        if let Some(weights) = covered[0] {
+            shade(x, y, weights)
+        }
+    });
+}
+
+/// Like `rasterize_triangle` with samples at `offsets` from each pixel center.
+/// Calls `shade(x, y, covered)` for every pixel in `clip` with any sample inside,
+/// `covered[i]` has the weights at sample `i`, or `None` when it's outside.
+pub fn rasterize_triangle_samples(
+    corners: [FixedPoint; 3],
+    clip: Rect,
+    offsets: &[FixedPoint],
+    mut shade: impl FnMut(usize, usize, &[Option<[f32; 3]>]),
+) {
+    // make the winding clockwise, `order` maps the weights back
+    let (corners, order) = match signed_area(corners) {
+        0 => return,
+        area if area > 0 => (corners, [0, 1, 2]),
+        _ => ([corners[0], corners[2], corners[1]], [0, 2, 1]),
+    };
+    let [a, b, c] = corners;
+    let area = edge(a, b, c) as f32;
+
+    let min = |axis: usize| corners.iter().map(|p| p[axis]).min().unwrap();
+    let max = |axis: usize| corners.iter().map(|p| p[axis]).max().unwrap();
+    let min_offset = |axis: usize| offsets.iter().map(|o| o[axis]).min().unwrap_or(0);
+    let max_offset = |axis: usize| offsets.iter().map(|o| o[axis]).max().unwrap_or(0);
+    let x0 = first_pixel(min(0) - max_offset(0)).max(clip.x0 as i64);
+    let y0 = first_pixel(min(1) - max_offset(1)).max(clip.y0 as i64);
+    let x1 = first_pixel(max(0) - min_offset(0) + 1).min(clip.x1 as i64);
+    let y1 = first_pixel(max(1) - min_offset(1) + 1).min(clip.y1 as i64);
+    if x0 >= x1 || y0 >= y1 {
+        return;
+    }
+
+    // edge `i` is opposite corner `i`, so its function is that corner's weight
+    let edges = [(b, c), (c, a), (a, b)];
+    // pixels exactly on an edge only count for top and left edges
+    let bias = edges.map(|(from, to)| if is_top_left(from, to) { 0 } else { -1 });
+    let start = [x0 * SUBPIXEL + HALF_PIXEL, y0 * SUBPIXEL + HALF_PIXEL];
+    let mut row = edges.map(|(from, to)| edge(from, to, start));
+    let step_x = edges.map(|(from, to)| -(to[1] - from[1]) * SUBPIXEL);
+    let step_y = edges.map(|(from, to)| (to[0] - from[0]) * SUBPIXEL);
+    // the edge functions are linear, so a sample is the pixel center plus a constant
+    let sample_steps: Vec<[i64; 3]> = offsets
+        .iter()
+        .map(|o| edges.map(|(from, to)| -(to[1] - from[1]) * o[0] + (to[0] - from[0]) * o[1]))
+        .collect();
+    let mut covered = vec![None; offsets.len()];
+
+    for y in y0..y1 {
+        let mut w = row;
+        for x in x0..x1 {
+            let mut any = false;
+            for (sample, steps) in covered.iter_mut().zip(&sample_steps) {
+                let w = [0, 1, 2].map(|i| w[i] + steps[i]);
+                *sample = if (0..3).all(|i| w[i] + bias[i] >= 0) {
+                    any = true;
+                    let weights = w.map(|w| w as f32 / area);
+                    Some(order.map(|i| weights[i]))
+                } else {
+                    None
+                };
+            }
+            if any {
+                shade(x as usize, y as usize, &covered);
+            }
+            for i in 0..3 {
+                w[i] += step_x[i];
+            }
+        }
+        for i in 0..3 {
+            row[i] += step_y[i];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 16;
+
+    fn point(x: f32, y: f32) -> FixedPoint {
+        to_fixed(x, y)
+    }
+
+    /// how many times each pixel gets drawn
+    fn coverage(triangles: &[[FixedPoint; 3]]) -> Vec<u32> {
+        let mut counts = vec![0; SIZE * SIZE];
+        for t in triangles {
+            rasterize_triangle(*t, Rect::canvas(SIZE, SIZE), |x, y, _| {
+                counts[y * SIZE + x] += 1
+            });
+        }
+        counts
+    }
+
+    #[test]
+    fn shared_edges_are_drawn_once() {
+        // a fan around an off grid center, covering a square whose corners and
+        // edges sit on pixel centers, so every edge is hit by the fill rule
+        let center = point(7.3, 8.1);
+        let ring = [
+            point(2.5, 2.5),
+            point(8.5, 2.5),
+            point(13.5, 2.5),
+            point(13.5, 8.5),
+            point(13.5, 13.5),
+            point(6.5, 13.5),
+            point(2.5, 13.5),
+            point(2.5, 7.5),
+        ];
+        let fan: Vec<[FixedPoint; 3]> = (0..ring.len())
+            .map(|i| [center, ring[i], ring[(i + 1) % ring.len()]])
+            .collect();
+        let counts = coverage(&fan);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                // the square's top and left edges are in, bottom and right out
+                let inside = (2..13).contains(&x) && (2..13).contains(&y);
+                assert_eq!(counts[y * SIZE + x], inside as u32, "pixel {}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn either_winding_and_weights() {
+        let (a, b, c) = (point(1., 1.), point(9., 1.), point(1., 9.));
+        let mut clockwise = vec![];
+        rasterize_triangle([a, b, c], Rect::canvas(SIZE, SIZE), |x, y, w| {
+            clockwise.push((x, y, w))
+        });
+        let mut counter = vec![];
+        rasterize_triangle([a, c, b], Rect::canvas(SIZE, SIZE), |x, y, w| {
+            counter.push((x, y, [w[0], w[2], w[1]]))
+        });
+        assert_eq!(clockwise, counter);
+        // 7 + 6 + ... + 1 pixel centers, the ones on the diagonal are on a bottom right edge
+        assert_eq!(clockwise.len(), 28);
+
+        for (x, y, w) in clockwise {
+            assert!((w.iter().sum::<f32>() - 1.).abs() < 1e-5);
+            // the weights put the pixel center back together
+            let px = w[0] * 1. + w[1] * 9. + w[2] * 1.;
+            let py = w[0] * 1. + w[1] * 1. + w[2] * 9.;
+            assert!((px - (x as f32 + 0.5)).abs() < 1e-4);
+            assert!((py - (y as f32 + 0.5)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn clip_rect_and_degenerate_triangles() {
+        let big = [point(-20., -20.), point(40., -20.), point(-20., 40.)];
+        let mut count = 0;
+        rasterize_triangle(big, Rect::new(4, 4, 8, 6), |x, y, _| {
+            assert!((4..8).contains(&x) && (4..6).contains(&y));
+            count += 1;
+        });
+        assert_eq!(count, 8);
+
+        let line = [point(1., 1.), point(5., 5.), point(9., 9.)];
+        assert!(coverage(&[line]).iter().all(|c| *c == 0));
+    }
+
+    #[test]
+    fn samples_are_covered_once_across_shared_edges() {
+        // four samples in the corners of each pixel
+        let offsets = [[-4, -4], [4, -4], [-4, 4], [4, 4]];
+        let center = point(7.3, 8.1);
+        let ring = [
+            point(2.2, 2.9),
+            point(13.6, 3.3),
+            point(12.1, 13.7),
+            point(3.4, 12.2),
+        ];
+        let mut counts = vec![0; SIZE * SIZE * offsets.len()];
+        for i in 0..ring.len() {
+            let t = [center, ring[i], ring[(i + 1) % ring.len()]];
+            rasterize_triangle_samples(t, Rect::canvas(SIZE, SIZE), &offsets, |x, y, covered| {
+                for (s, weights) in covered.iter().enumerate() {
+                    if let Some(w) = weights {
+                        assert!((w.iter().sum::<f32>() - 1.).abs() < 1e-5);
+                        counts[(y * SIZE + x) * offsets.len() + s] += 1;
+                    }
+                }
+            });
+        }
+        assert!(counts.iter().all(|c| *c <= 1));
+        // the middle of the quad is fully covered, its edges partly
+        let pixels: Vec<&[u32]> = counts.chunks(offsets.len()).collect();
+        assert_eq!(pixels[7 * SIZE + 7], &[1, 1, 1, 1]);
+        assert!(pixels.iter().any(|p| p.contains(&0) && p.contains(&1)));
+    }
+
+    #[test]
+    fn samples_at_the_center_match_single_sampling() {
+        let t = [point(1.3, 0.7), point(14.2, 5.1), point(4.4, 15.)];
+        let mut single = vec![];
+        rasterize_triangle(t, Rect::canvas(SIZE, SIZE), |x, y, w| {
+            single.push((x, y, w))
+        });
+        let mut sampled = vec![];
+        rasterize_triangle_samples(
+            t,
+            Rect::canvas(SIZE, SIZE),
+            &[[0, 0], [0, 0]],
+            |x, y, covered| {
+                assert_eq!(covered[0], covered[1]);
+                sampled.push((x, y, covered[0].unwrap()));
+            },
+        );
+        assert_eq!(single, sampled);
+    }
+
+    #[test]
+    fn subpixel_positions_move_the_edge() {
+        // the left edge crosses the pixel centers at x = 4.5 only when it's left of them
+        let at = |x: f32| {
+            let mut covered = false;
+            let t = [point(x, 0.), point(12., 0.), point(x, 12.)];
+            rasterize_triangle(t, Rect::new(4, 0, 5, 1), |_, _, _| covered = true);
+            covered
+        };
+        assert!(at(4.4375));
+        assert!(at(4.5));
+        assert!(!at(4.5625));
+    }
+}
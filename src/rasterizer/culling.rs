@@ -0,0 +1,437 @@
+//! Culling whole instances against the camera's frustum before any of their
+//! vertices are transformed. `InstanceBvh` is a bounding volume hierarchy over a
+//! scene's instances, built once while they stay where they are. Every node has a
+//! box and a sphere around the instances under it: a node outside the frustum is
+//! skipped with everything under it, and a node inside it needs no more tests.
+
+use super::{
+    camera::Camera,
+    data_types::{Instance, Mat4x4, Model, Plane, Vertex3},
+};
+use std::{collections::HashMap, fmt, ops::Range};
+
+/// most instances in a leaf
+const LEAF_SIZE: usize = 4;
+
+/// Axis aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub min: Vertex3,
+    pub max: Vertex3,
+}
+
+impl Bounds {
+    /// contains nothing, the start for `union`
+    pub const EMPTY: Bounds = Bounds {
+        min: glam::const_vec3!([f32::INFINITY; 3]),
+        max: glam::const_vec3!([f32::NEG_INFINITY; 3]),
+    };
+
+    pub fn from_points(points: impl IntoIterator<Item = Vertex3>) -> Self {
+        points.into_iter().fold(Self::EMPTY, |b, p| Bounds {
+            min: b.min.min(p),
+            max: b.max.max(p),
+        })
+    }
+
+    pub fn union(self, other: Bounds) -> Self {
+        Bounds {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn center(&self) -> Vertex3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn corners(&self) -> [Vertex3; 8] {
+        let (a, b) = (self.min, self.max);
+        std::array::from_fn(|i| {
+            Vertex3::new(
+                if i & 1 == 0 { a.x } else { b.x },
+                if i & 2 == 0 { a.y } else { b.y },
+                if i & 4 == 0 { a.z } else { b.z },
+            )
+        })
+    }
+
+    /// the box around this one's corners moved by `transform`
+    pub fn transformed(&self, transform: Mat4x4) -> Self {
+        Self::from_points(self.corners().map(|c| transform.transform_point3(c)))
+    }
+}
+
+/// Where a volume is relative to the frustum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Containment {
+    Outside,
+    /// partly inside, or too close to a plane to tell
+    Intersecting,
+    Inside,
+}
+
+/// The camera's clipping planes in world space, pointing inwards.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    pub fn new(camera: &Camera) -> Self {
+        // a camera space plane dotted with world_to_camera * p is the world space
+        // plane transpose(world_to_camera) * plane dotted with p
+        let to_world = camera.world_to_camera().transpose();
+        Self {
+            planes: camera.clipping_planes.map(|p| {
+                let plane = to_world * p.normal.extend(p.dist);
+                let length = plane.truncate().length();
+                Plane::new(plane.truncate() / length, plane.w / length)
+            }),
+        }
+    }
+
+    pub fn test_sphere(&self, center: Vertex3, radius: f32) -> Containment {
+        let mut containment = Containment::Inside;
+        for p in &self.planes {
+            let dist = p.normal.dot(center) + p.dist;
+            if dist < -radius {
+                return Containment::Outside;
+            }
+            if dist < radius {
+                containment = Containment::Intersecting;
+            }
+        }
+        containment
+    }
+
+    pub fn test_box(&self, bounds: &Bounds) -> Containment {
+        let mut containment = Containment::Inside;
+        for p in &self.planes {
+            // the corners farthest along the normal and against it
+            let select = |towards: bool| {
+                Vertex3::select(
+                    p.normal.cmpge(Vertex3::ZERO),
+                    if towards { bounds.max } else { bounds.min },
+                    if towards { bounds.min } else { bounds.max },
+                )
+            };
+            if p.normal.dot(select(true)) + p.dist < 0. {
+                return Containment::Outside;
+            }
+            if p.normal.dot(select(false)) + p.dist < 0. {
+                containment = Containment::Intersecting;
+            }
+        }
+        containment
+    }
+
+    /// The cheap sphere test first, the box test when it can't tell. Either one
+    /// finding the volume outside or inside is enough.
+    pub fn test(&self, bounds: &Bounds, center: Vertex3, radius: f32) -> Containment {
+        match self.test_sphere(center, radius) {
+            Containment::Intersecting => self.test_box(bounds),
+            containment => containment,
+        }
+    }
+}
+
+/// What the culling let through.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CullStats {
+    pub instances: usize,
+    /// instances at least partly inside the frustum
+    pub drawn: usize,
+    pub culled: usize,
+    /// nodes and instances tested against the frustum
+    pub tests: usize,
+}
+
+impl CullStats {
+    /// one line per stat, like `raytracer::stats::RenderStats::lines`
+    pub fn lines(&self) -> Vec<String> {
+        vec![
+            format!("instances: {}", self.instances),
+            format!("drawn: {}, culled: {}", self.drawn, self.culled),
+            format!("frustum tests: {}", self.tests),
+        ]
+    }
+}
+
+impl fmt::Display for CullStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.lines().join("\n"))
+    }
+}
+
+/// An instance's world space bounds.
+#[derive(Debug, Clone, Copy)]
+struct Volume {
+    bounds: Bounds,
+    center: Vertex3,
+    radius: f32,
+}
+
+impl Volume {
+    /// `model_bounds` is the model's box, which many instances share
+    fn new(instance: &Instance, model_bounds: &Bounds) -> Self {
+        let transform = instance.transform;
+        Self {
+            bounds: model_bounds.transformed(transform),
+            center: transform.transform_point3(instance.model.bounds_center.truncate()),
+            radius: instance.bounding_radius(),
+        }
+    }
+
+    /// sphere around the box, for nodes
+    fn around(bounds: Bounds) -> Self {
+        Self {
+            bounds,
+            center: bounds.center(),
+            radius: bounds.max.distance(bounds.min) * 0.5,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Node {
+    volume: Volume,
+    /// the instances under the node, a range of `InstanceBvh::order`
+    instances: Range<usize>,
+    /// indices of the two children, `None` for leaves
+    children: Option<[usize; 2]>,
+}
+
+/// Bounding volume hierarchy over a scene's instances, see the module docs.
+/// Build it again when instances move or are added.
+#[derive(Debug)]
+pub struct InstanceBvh {
+    nodes: Vec<Node>,
+    /// instance indices, ordered so every node's are next to each other
+    order: Vec<usize>,
+    volumes: Vec<Volume>,
+}
+
+impl InstanceBvh {
+    /// Splits the instances in half along the longest axis of their centers until
+    /// there are `LEAF_SIZE` or fewer in a node.
+    pub fn new(instances: &[Instance]) -> Self {
+        let mut model_bounds: HashMap<*const Model, Bounds> = HashMap::new();
+        let volumes: Vec<Volume> = instances
+            .iter()
+            .map(|i| {
+                let bounds = model_bounds
+                    .entry(i.model as *const Model)
+                    .or_insert_with(|| Bounds::from_points(i.model.verticies.iter().copied()));
+                Volume::new(i, bounds)
+            })
+            .collect();
+        let mut bvh = Self {
+            nodes: vec![],
+            order: (0..instances.len()).collect(),
+            volumes,
+        };
+        if !instances.is_empty() {
+            bvh.build(0..instances.len());
+        }
+        bvh
+    }
+
+    /// adds the node for `range` of `order` and everything under it, returns its index
+    fn build(&mut self, range: Range<usize>) -> usize {
+        let volumes = &self.volumes;
+        let members = &mut self.order[range.clone()];
+        let bounds = members
+            .iter()
+            .fold(Bounds::EMPTY, |b, &i| b.union(volumes[i].bounds));
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            volume: Volume::around(bounds),
+            instances: range.clone(),
+            children: None,
+        });
+        if members.len() <= LEAF_SIZE {
+            return index;
+        }
+
+        let centers = Bounds::from_points(members.iter().map(|&i| volumes[i].bounds.center()));
+        let extent = centers.max - centers.min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let middle = members.len() / 2;
+        members.select_nth_unstable_by(middle, |&a, &b| {
+            let (a, b) = (volumes[a].bounds.center(), volumes[b].bounds.center());
+            a[axis].total_cmp(&b[axis])
+        });
+        let split = range.start + middle;
+        let left = self.build(range.start..split);
+        let right = self.build(split..range.end);
+        self.nodes[index].children = Some([left, right]);
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Indices of the instances at least partly inside `frustum`, in the order
+    /// they were given so they're drawn the same as without culling.
+    pub fn cull(&self, frustum: &Frustum) -> (Vec<usize>, CullStats) {
+        let mut visible = vec![];
+        let mut tests = 0;
+        let mut stack = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            let volume = &node.volume;
+            tests += 1;
+            match frustum.test(&volume.bounds, volume.center, volume.radius) {
+                Containment::Outside => {}
+                Containment::Inside => {
+                    visible.extend_from_slice(&self.order[node.instances.clone()])
+                }
+                Containment::Intersecting => match node.children {
+                    Some(children) => stack.extend(children),
+                    None => {
+                        for &i in &self.order[node.instances.clone()] {
+                            let volume = &self.volumes[i];
+                            tests += 1;
+                            if frustum.test(&volume.bounds, volume.center, volume.radius)
+                                != Containment::Outside
+                            {
+                                visible.push(i);
+                            }
+                        }
+                    }
+                },
+            }
+        }
+        visible.sort_unstable();
+        let stats = CullStats {
+            instances: self.len(),
+            drawn: visible.len(),
+            culled: self.len() - visible.len(),
+            tests,
+        };
+        (visible, stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rasterizer::{color::RED, data_types::Triangle};
+
+    fn unit_box() -> Model {
+        let corners = Bounds {
+            min: Vertex3::splat(-0.5),
+            max: Vertex3::splat(0.5),
+        }
+        .corners();
+        Model::from_mesh(vec![Triangle::new(0, 1, 2, RED)], corners.to_vec())
+    }
+
+    #[test]
+    fn bounds_follow_transforms() {
+        let bounds = Bounds::from_points([Vertex3::new(0., 0., 0.), Vertex3::new(2., 1., 1.)]);
+        assert_eq!(bounds.center(), Vertex3::new(1., 0.5, 0.5));
+        let turned = bounds.transformed(Mat4x4::from_rotation_y(std::f32::consts::FRAC_PI_2));
+        assert!(turned.min.abs_diff_eq(Vertex3::new(0., 0., -2.), 1e-6));
+        assert!(turned.max.abs_diff_eq(Vertex3::new(1., 1., 0.), 1e-6));
+        assert_eq!(Bounds::EMPTY.union(bounds), bounds);
+    }
+
+    #[test]
+    fn frustum_is_in_world_space() {
+        // looking down +x from x = 10
+        let camera = Camera::new(
+            Vertex3::new(10., 0., 0.),
+            Mat4x4::from_rotation_y(std::f32::consts::FRAC_PI_2),
+        )
+        .with_perspective(90., 1., 1., 100.);
+        let frustum = Frustum::new(&camera);
+        let ahead = Vertex3::new(20., 0., 0.);
+        assert_eq!(frustum.test_sphere(ahead, 1.), Containment::Inside);
+        assert_eq!(frustum.test_sphere(Vertex3::ZERO, 1.), Containment::Outside);
+        assert_eq!(frustum.test_sphere(ahead, 15.), Containment::Intersecting);
+        // past the far plane
+        assert_eq!(
+            frustum.test_sphere(Vertex3::new(120., 0., 0.), 1.),
+            Containment::Outside
+        );
+
+        let bounds = |min: Vertex3, max: Vertex3| Bounds { min, max };
+        let inside = bounds(Vertex3::new(19., -1., -1.), Vertex3::new(21., 1., 1.));
+        assert_eq!(frustum.test_box(&inside), Containment::Inside);
+        let behind = bounds(Vertex3::new(0., -1., -1.), Vertex3::new(9., 1., 1.));
+        assert_eq!(frustum.test_box(&behind), Containment::Outside);
+        let crossing = bounds(Vertex3::new(5., -1., -1.), Vertex3::new(15., 1., 1.));
+        assert_eq!(frustum.test_box(&crossing), Containment::Intersecting);
+        // just past the side, the sphere around the box pokes into the frustum
+        let corner = bounds(Vertex3::new(14.8, 5.3, -0.5), Vertex3::new(15.2, 6.3, 0.5));
+        let volume = Volume::around(corner);
+        assert_eq!(
+            frustum.test_sphere(volume.center, volume.radius),
+            Containment::Intersecting
+        );
+        assert_eq!(
+            frustum.test(&corner, volume.center, volume.radius),
+            Containment::Outside
+        );
+    }
+
+    #[test]
+    fn bvh_culls_like_testing_every_instance() {
+        let model = unit_box();
+        let mut instances = vec![];
+        for x in 0..30 {
+            for z in 0..30 {
+                let position = Vertex3::new(x as f32 * 3. - 45., 0., z as f32 * 3. - 45.);
+                let instance = Instance::new(&model, position, None, None);
+                instances.push(instance.with_scale(Vertex3::new(1., 1. + (x * z % 5) as f32, 1.)));
+            }
+        }
+        let bvh = InstanceBvh::new(&instances);
+        assert_eq!(bvh.len(), 900);
+        let camera = Camera::new(Vertex3::new(0., -2., -50.), Mat4x4::from_rotation_y(0.3))
+            .with_perspective(60., 1.5, 0.5, 60.);
+        let frustum = Frustum::new(&camera);
+
+        let (visible, stats) = bvh.cull(&frustum);
+        let model_bounds = Bounds::from_points(model.verticies.iter().copied());
+        let expected: Vec<usize> = (0..instances.len())
+            .filter(|&i| {
+                let volume = Volume::new(&instances[i], &model_bounds);
+                frustum.test(&volume.bounds, volume.center, volume.radius) != Containment::Outside
+            })
+            .collect();
+        assert_eq!(visible, expected);
+        assert_eq!(stats.drawn, visible.len());
+        assert_eq!(stats.drawn + stats.culled, 900);
+        assert!(stats.culled > 450 && stats.drawn > 0);
+        // whole subtrees were skipped
+        assert!(stats.tests < 900);
+
+        let everything = Camera::new(
+            Vertex3::new(0., -200., 0.),
+            Mat4x4::from_rotation_x(-std::f32::consts::FRAC_PI_2),
+        )
+        .with_perspective(90., 1., 1., 1000.);
+        let (visible, stats) = bvh.cull(&Frustum::new(&everything));
+        assert_eq!(visible, (0..900).collect::<Vec<_>>());
+        assert_eq!(stats.tests, 1);
+        assert_eq!(InstanceBvh::new(&[]).cull(&frustum).1, CullStats::default());
+    }
+}
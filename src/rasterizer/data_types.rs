@@ -1,11 +1,14 @@
-use super::{color::*, utils};
-use macroquad::prelude::Color;
+use super::{blending::BlendMode, color::*, shader::Shader, texture::Texture, utils};
+use crate::color::Color;
+use std::sync::Arc;
 
 pub type Point = glam::Vec3;
+pub type Vec2 = glam::Vec2;
 pub type Vertex3 = glam::Vec3;
 pub type Vertex4 = glam::Vec4;
 pub type Vec4 = glam::Vec4;
 pub type Mat4x4 = glam::Mat4;
+pub type Quaternion = glam::Quat;
 
 pub const IDEN_4X4: Mat4x4 = glam::const_mat4!(
     [1.0, 0.0, 0.0, 0.0],
@@ -18,11 +21,41 @@ pub const IDEN_4X4: Mat4x4 = glam::const_mat4!(
 pub struct Triangle {
     pub ind: [usize; 3],
     pub color: Color,
+    /// one normal per corner, the face normal is computed when missing
+    pub normals: Option<[Vertex3; 3]>,
+    /// Phong exponent, zero or less for matte
+    pub specular: f32,
+    /// one texture coordinate per corner
+    pub uvs: Option<[Vec2; 3]>,
+    /// index into the model's textures, replaces `color` when the triangle has uvs
+    pub texture: Option<usize>,
+    /// how the color is combined with what's under it
+    pub blend: BlendMode,
 }
 
 impl Triangle {
     pub const fn new(v0: usize, v1: usize, v2: usize, color: Color) -> Self {
-        Self { ind: [v0, v1, v2,], color }
+        Self { ind: [v0, v1, v2,], color, normals: None, specular: 0., uvs: None, texture: None, blend: BlendMode::Opaque }
+    }
+
+    pub const fn with_normals(self, normals: [Vertex3; 3]) -> Self {
+        Self { normals: Some(normals), ..self }
+    }
+
+    pub const fn with_specular(self, specular: f32) -> Self {
+        Self { specular, ..self }
+    }
+
+    pub const fn with_uvs(self, uvs: [Vec2; 3]) -> Self {
+        Self { uvs: Some(uvs), ..self }
+    }
+
+    pub const fn with_texture(self, texture: usize) -> Self {
+        Self { texture: Some(texture), ..self }
+    }
+
+    pub const fn with_blend(self, blend: BlendMode) -> Self {
+        Self { blend, ..self }
     }
 }
 
@@ -34,20 +67,30 @@ pub struct Cube {
 
 impl Cube {
     pub fn debug_cube() -> Self {
+        // the faces are flat, so every corner gets the face normal
+        const FRONT: Vertex3 = glam::const_vec3!([0., 0., 1.]);
+        const BACK: Vertex3 = glam::const_vec3!([0., 0., -1.]);
+        const LEFT: Vertex3 = glam::const_vec3!([-1., 0., 0.]);
+        const RIGHT: Vertex3 = glam::const_vec3!([1., 0., 0.]);
+        const TOP: Vertex3 = glam::const_vec3!([0., 1., 0.]);
+        const BOTTOM: Vertex3 = glam::const_vec3!([0., -1., 0.]);
+        // each face is split along the same diagonal, a-b-c and a-c-d
+        const ABC: [Vec2; 3] = [glam::const_vec2!([0., 0.]), glam::const_vec2!([1., 0.]), glam::const_vec2!([1., 1.])];
+        const ACD: [Vec2; 3] = [glam::const_vec2!([0., 0.]), glam::const_vec2!([1., 1.]), glam::const_vec2!([0., 1.])];
         Self {
             triangles: [
-                Triangle::new(0, 1, 2, RED),
-                Triangle::new(0, 2, 3, RED),
-                Triangle::new(1, 5, 6, YELLOW),
-                Triangle::new(1, 6, 2, YELLOW),
-                Triangle::new(2, 6, 7, CYAN),
-                Triangle::new(2, 7, 3, CYAN),
-                Triangle::new(4, 0, 3, GREEN),
-                Triangle::new(4, 1, 0, PURPLE),
-                Triangle::new(4, 3, 7, GREEN),
-                Triangle::new(4, 5, 1, PURPLE),
-                Triangle::new(5, 4, 7, BLUE),
-                Triangle::new(5, 7, 6, BLUE),
+                Triangle::new(0, 1, 2, RED).with_normals([FRONT; 3]).with_specular(50.).with_uvs(ABC),
+                Triangle::new(0, 2, 3, RED).with_normals([FRONT; 3]).with_specular(50.).with_uvs(ACD),
+                Triangle::new(1, 5, 6, YELLOW).with_normals([LEFT; 3]).with_specular(50.).with_uvs(ABC),
+                Triangle::new(1, 6, 2, YELLOW).with_normals([LEFT; 3]).with_specular(50.).with_uvs(ACD),
+                Triangle::new(2, 6, 7, CYAN).with_normals([BOTTOM; 3]).with_specular(50.).with_uvs(ABC),
+                Triangle::new(2, 7, 3, CYAN).with_normals([BOTTOM; 3]).with_specular(50.).with_uvs(ACD),
+                Triangle::new(4, 0, 3, GREEN).with_normals([RIGHT; 3]).with_specular(50.).with_uvs(ABC),
+                Triangle::new(4, 1, 0, PURPLE).with_normals([TOP; 3]).with_specular(50.).with_uvs(ACD),
+                Triangle::new(4, 3, 7, GREEN).with_normals([RIGHT; 3]).with_specular(50.).with_uvs(ACD),
+                Triangle::new(4, 5, 1, PURPLE).with_normals([TOP; 3]).with_specular(50.).with_uvs(ABC),
+                Triangle::new(5, 4, 7, BLUE).with_normals([BACK; 3]).with_specular(50.).with_uvs(ABC),
+                Triangle::new(5, 7, 6, BLUE).with_normals([BACK; 3]).with_specular(50.).with_uvs(ACD),
             ],
             verticies: [
                 Vertex3::new(1., 1., 1.),
@@ -63,12 +106,51 @@ impl Cube {
     }
 }
 
+/// Optional values for each of a model's vertices, every list is as long as the
+/// vertices. A triangle's own corner normals and uvs take precedence over these.
+#[derive(Debug, Default, Clone)]
+pub struct VertexAttributes {
+    /// used instead of the triangles' colors
+    pub colors: Option<Vec<Color>>,
+    pub normals: Option<Vec<Vertex3>>,
+    pub uvs: Option<Vec<Vec2>>,
+    /// `xyz` points the way u grows along the surface, `w` is 1 or -1 for the side
+    /// the bitangent is on
+    pub tangents: Option<Vec<Vertex4>>,
+}
+
+impl VertexAttributes {
+    /// the attributes moved by the model to camera space `transform`, normals
+    /// transformed by its inverse transpose `normal_mat`
+    pub fn transformed(&self, transform: Mat4x4, normal_mat: Mat4x4) -> Self {
+        Self {
+            colors: self.colors.clone(),
+            normals: self.normals.as_ref().map(|normals| {
+                normals.iter().map(|n| normal_mat.transform_vector3(*n).normalize_or_zero()).collect()
+            }),
+            uvs: self.uvs.clone(),
+            tangents: self.tangents.as_ref().map(|tangents| {
+                tangents
+                    .iter()
+                    .map(|t| transform.transform_vector3(t.truncate()).normalize_or_zero().extend(t.w))
+                    .collect()
+            }),
+        }
+    }
+
+}
+
 #[derive(Debug)]
 pub struct Model {
     pub triangles: Vec<Triangle>,
     pub verticies: Vec<Vertex3>,
     pub bounds_center: Vertex4,
-    pub bounds_radius: f32
+    pub bounds_radius: f32,
+    /// shared so clipped copies of the model don't copy the texels
+    pub textures: Vec<Arc<Texture>>,
+    /// replaces the built-in shader for `RenderContext::shading_model`
+    pub shader: Option<Arc<dyn Shader>>,
+    pub attributes: VertexAttributes,
 }
 
 impl Model {
@@ -77,20 +159,100 @@ impl Model {
             triangles: tris, 
             verticies: verts, 
             bounds_center: center, 
-            bounds_radius: radius 
+            bounds_radius: radius,
+            textures: vec![],
+            shader: None,
+            attributes: VertexAttributes::default(),
         }
     }
-}
 
-impl From<(&Cube, Vertex4, f32)> for Model {
-    fn from(data: (&Cube, Vertex4, f32)) -> Self {
-        let (cube, center, radius) = data;
-        Model {
-            triangles: Vec::from(cube.triangles),
-            verticies: Vec::from(cube.verticies),
-            bounds_center: center,
-            bounds_radius: radius
+    /// like `new`, with the bounding sphere computed from the vertices
+    pub fn from_mesh(tris: Vec<Triangle>, verts: Vec<Vertex3>) -> Self {
+        let (center, radius) = Model::bounding_sphere(&verts);
+        Model::new(tris, verts, center, radius)
+    }
+
+    /// Sphere around the center of the axis aligned bounds. Not the smallest one,
+    /// but never much bigger and cheap to compute.
+    pub fn bounding_sphere(verts: &[Vertex3]) -> (Vertex4, f32) {
+        if verts.is_empty() {
+            return (Vertex4::new(0., 0., 0., 1.), 0.);
         }
+        let (min, max) = verts
+            .iter()
+            .fold((verts[0], verts[0]), |(min, max), v| (min.min(*v), max.max(*v)));
+        let center = (min + max) * 0.5;
+        let radius = verts
+            .iter()
+            .map(|v| v.distance(center))
+            .fold(0., f32::max);
+        (center.extend(1.), radius)
+    }
+
+    /// `bounds_radius` once `transform` moves the model, the sphere grows with the
+    /// largest scale in it
+    pub fn transformed_bounds_radius(&self, transform: Mat4x4) -> f32 {
+        let scale = transform.x_axis.truncate().length()
+            .max(transform.y_axis.truncate().length())
+            .max(transform.z_axis.truncate().length());
+        self.bounds_radius * scale
+    }
+
+    /// use `texture` on every triangle that has uvs, all of them when the vertices have uvs
+    pub fn with_texture(mut self, texture: Arc<Texture>) -> Self {
+        let index = self.textures.len();
+        self.textures.push(texture);
+        let vertex_uvs = self.attributes.uvs.is_some();
+        for t in self.triangles.iter_mut().filter(|t| vertex_uvs || t.uvs.is_some()) {
+            t.texture = Some(index);
+        }
+        self
+    }
+
+    /// one color for every vertex, blended across the triangles
+    pub fn with_vertex_colors(mut self, colors: Vec<Color>) -> Self {
+        assert_eq!(colors.len(), self.verticies.len(), "one color for every vertex");
+        self.attributes.colors = Some(colors);
+        self
+    }
+
+    /// one normal for every vertex, for triangles without their own
+    pub fn with_vertex_normals(mut self, normals: Vec<Vertex3>) -> Self {
+        assert_eq!(normals.len(), self.verticies.len(), "one normal for every vertex");
+        self.attributes.normals = Some(normals);
+        self
+    }
+
+    /// one texture coordinate for every vertex, for triangles without their own
+    pub fn with_vertex_uvs(mut self, uvs: Vec<Vec2>) -> Self {
+        assert_eq!(uvs.len(), self.verticies.len(), "one uv for every vertex");
+        self.attributes.uvs = Some(uvs);
+        self
+    }
+
+    /// one tangent for every vertex, see `VertexAttributes::tangents`
+    pub fn with_vertex_tangents(mut self, tangents: Vec<Vertex4>) -> Self {
+        assert_eq!(tangents.len(), self.verticies.len(), "one tangent for every vertex");
+        self.attributes.tangents = Some(tangents);
+        self
+    }
+
+    pub fn with_shader(self, shader: Arc<dyn Shader>) -> Self {
+        Self { shader: Some(shader), ..self }
+    }
+
+    /// draw every triangle with `blend`
+    pub fn with_blend(mut self, blend: BlendMode) -> Self {
+        for t in self.triangles.iter_mut() {
+            t.blend = blend;
+        }
+        self
+    }
+}
+
+impl From<&Cube> for Model {
+    fn from(cube: &Cube) -> Self {
+        Model::from_mesh(Vec::from(cube.triangles), Vec::from(cube.verticies))
     }
 }
 
@@ -99,7 +261,8 @@ pub struct Instance<'a> {
     pub model: &'a Model,
     pub position: Vertex3,
     pub orientation: Mat4x4,
-    pub scale: f32,
+    /// along each of the model's axes
+    pub scale: Vertex3,
     pub transform: Mat4x4,
 }
 
@@ -110,27 +273,43 @@ impl<'a> Instance<'a> {
         orientation: Option<Mat4x4>,
         scale: Option<f32>,
     ) -> Self {
-        let mut s = 1.;
-        let o;
-        if let Some(scale) = scale {
-            s = scale;
+        let o = orientation.unwrap_or(IDEN_4X4);
+        let s = Vertex3::splat(scale.unwrap_or(1.));
+        let transform = utils::make_transform_mat(position, o, s);
+
+        Self {
+            model,
+            position,
+            transform,
+            orientation: o,
+            scale: s,
         }
+    }
+
+    /// radius of the model's bounding sphere in world space
+    pub fn bounding_radius(&self) -> f32 {
+        self.model.transformed_bounds_radius(self.transform)
+    }
 
-        if let Some(orientation) = orientation {
-            o = orientation;
-        } else {
-            o = IDEN_4X4.clone();
+    /// replaces the scale with a separate one for each axis
+    pub fn with_scale(self, scale: Vertex3) -> Self {
+        Self {
+            scale,
+            transform: utils::make_transform_mat(self.position, self.orientation, scale),
+            ..self
         }
-        let t_mat = utils::make_translation_mat(position);
-        let s_mat = utils::mul_mm(o, utils::make_scaling_mat(s));
-        let transform = utils::mul_mm(t_mat, s_mat);
+    }
 
+    /// Instance placed by an already composed model to world transform. The
+    /// parts are only approximate for sheared transforms, `transform` stays exact.
+    pub fn from_transform(model: &'a Model, transform: Mat4x4) -> Self {
+        let (scale, rotation, position) = transform.to_scale_rotation_translation();
         Self {
             model,
             position,
+            orientation: Mat4x4::from_quat(rotation),
+            scale,
             transform,
-            orientation: o,
-            scale: s,
         }
     }
 }
@@ -0,0 +1,150 @@
+//! Mesh importers. All three formats use a y up, counter clockwise convention,
+//! so positions and normals get their y flipped and triangles their winding
+//! reversed to match the rasterizer's y down screen space.
+
+pub mod obj;
+pub mod ply;
+pub mod stl;
+
+use super::data_types::{Model, Triangle, Vertex3};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub struct ImportError {
+    pub path: PathBuf,
+    /// 1 based, missing for errors in binary data or about the whole file
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl ImportError {
+    pub fn new(path: &Path, line: Option<usize>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// pick the importer from the file extension
+pub fn load(path: impl AsRef<Path>) -> Result<Model, ImportError> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("obj") => obj::load(path),
+        Some("ply") => ply::load(path),
+        Some("stl") => stl::load(path),
+        _ => Err(ImportError::new(path, None, "unknown mesh format")),
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, ImportError> {
+    fs::read(path).map_err(|e| ImportError::new(path, None, e.to_string()))
+}
+
+fn read_to_string(path: &Path) -> Result<String, ImportError> {
+    fs::read_to_string(path).map_err(|e| ImportError::new(path, None, e.to_string()))
+}
+
+/// parse the next whitespace separated field as a number
+fn parse_field<'a, T: std::str::FromStr>(
+    fields: &mut impl Iterator<Item = &'a str>,
+    what: &str,
+    path: &Path,
+    line: usize,
+) -> Result<T, ImportError> {
+    let field = fields
+        .next()
+        .ok_or_else(|| ImportError::new(path, Some(line), format!("missing {}", what)))?;
+    field
+        .parse()
+        .map_err(|_| ImportError::new(path, Some(line), format!("invalid {} '{}'", what, field)))
+}
+
+fn parse_vertex3<'a>(
+    fields: &mut impl Iterator<Item = &'a str>,
+    path: &Path,
+    line: usize,
+) -> Result<Vertex3, ImportError> {
+    Ok(Vertex3::new(
+        parse_field(fields, "x", path, line)?,
+        parse_field(fields, "y", path, line)?,
+        parse_field(fields, "z", path, line)?,
+    ))
+}
+
+fn flip_y(v: Vertex3) -> Vertex3 {
+    Vertex3::new(v.x, -v.y, v.z)
+}
+
+/// Converts the triangles to the rasterizer's conventions and computes the bounds.
+fn finish(
+    mut triangles: Vec<Triangle>,
+    verticies: Vec<Vertex3>,
+    path: &Path,
+) -> Result<Model, ImportError> {
+    if triangles.is_empty() {
+        return Err(ImportError::new(path, None, "no faces"));
+    }
+    for t in &mut triangles {
+        t.ind.swap(1, 2);
+        if let Some(normals) = &mut t.normals {
+            normals.swap(1, 2);
+            *normals = normals.map(flip_y);
+        }
+        if let Some(uvs) = &mut t.uvs {
+            uvs.swap(1, 2);
+        }
+    }
+    let verticies = verticies.into_iter().map(flip_y).collect();
+    Ok(Model::from_mesh(triangles, verticies))
+}
+
+/// Splits a convex polygon into a fan of triangles around its first corner.
+fn triangulate<T: Copy>(polygon: &[T]) -> impl Iterator<Item = [T; 3]> + '_ {
+    (1..polygon.len().saturating_sub(1)).map(move |k| [polygon[0], polygon[k], polygon[k + 1]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_names_file_and_line() {
+        let e = ImportError::new(Path::new("cube.obj"), Some(12), "invalid x 'a'");
+        assert_eq!(e.to_string(), "cube.obj:12: invalid x 'a'");
+        let e = ImportError::new(Path::new("cube.stl"), None, "no faces");
+        assert_eq!(e.to_string(), "cube.stl: no faces");
+    }
+
+    #[test]
+    fn unknown_extension() {
+        let e = load("mesh.fbx").unwrap_err();
+        assert_eq!(e.message, "unknown mesh format");
+    }
+
+    #[test]
+    fn fan_triangulation() {
+        let fan: Vec<_> = triangulate(&[0, 1, 2, 3, 4]).collect();
+        assert_eq!(fan, vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+        assert_eq!(triangulate(&[0, 1]).count(), 0);
+    }
+}
@@ -0,0 +1,333 @@
+//! Wavefront OBJ with MTL materials. Faces can have any number of corners and
+//! use any of the `v`, `v/vt`, `v//vn` and `v/vt/vn` forms, with negative
+//! indices counting back from the last element. Only diffuse color, dissolve
+//! and shininess are read from materials.
+
+use super::{finish, parse_field, parse_vertex3, read_to_string, triangulate, ImportError};
+use crate::rasterizer::{
+    color::WHITE,
+    data_types::{Model, Triangle, Vec2, Vertex3},
+};
+use crate::color::Color;
+use std::{collections::HashMap, path::Path};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Material {
+    pub color: Color,
+    /// Phong exponent, zero for matte
+    pub specular: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            color: WHITE,
+            specular: 0.,
+        }
+    }
+}
+
+pub fn load(path: &Path) -> Result<Model, ImportError> {
+    let source = read_to_string(path)?;
+    parse(&source, path)
+}
+
+/// `path` is used for errors and to find the `mtllib` files, which are read from disk
+pub fn parse(source: &str, path: &Path) -> Result<Model, ImportError> {
+    let mut positions: Vec<Vertex3> = vec![];
+    let mut uvs: Vec<Vec2> = vec![];
+    let mut normals: Vec<Vertex3> = vec![];
+    let mut triangles: Vec<Triangle> = vec![];
+    let mut materials: HashMap<String, Material> = HashMap::new();
+    let mut material = Material::default();
+
+    for (i, text) in source.lines().enumerate() {
+        let line = i + 1;
+        let text = text.split('#').next().unwrap_or_default();
+        let mut fields = text.split_whitespace();
+        let keyword = match fields.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        match keyword {
+            "v" => positions.push(parse_vertex3(&mut fields, path, line)?),
+            "vn" => normals.push(parse_vertex3(&mut fields, path, line)?),
+            "vt" => {
+                let u = parse_field(&mut fields, "u", path, line)?;
+                let v: f32 = match fields.next() {
+                    Some(v) => v.parse().map_err(|_| {
+                        ImportError::new(path, Some(line), format!("invalid v '{}'", v))
+                    })?,
+                    None => 0.,
+                };
+                // OBJ puts v = 0 at the bottom of the image, textures here start at the top
+                uvs.push(Vec2::new(u, 1. - v));
+            }
+            "f" => {
+                let corners = fields
+                    .map(|corner| parse_corner(corner, &positions, &uvs, &normals, path, line))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if corners.len() < 3 {
+                    return Err(ImportError::new(
+                        path,
+                        Some(line),
+                        "face with fewer than 3 corners",
+                    ));
+                }
+                for [a, b, c] in triangulate(&corners) {
+                    let mut t = Triangle::new(a.0, b.0, c.0, material.color)
+                        .with_specular(material.specular);
+                    if let (Some(ta), Some(tb), Some(tc)) = (a.1, b.1, c.1) {
+                        t = t.with_uvs([uvs[ta], uvs[tb], uvs[tc]]);
+                    }
+                    if let (Some(na), Some(nb), Some(nc)) = (a.2, b.2, c.2) {
+                        t = t.with_normals([normals[na], normals[nb], normals[nc]]);
+                    }
+                    triangles.push(t);
+                }
+            }
+            "mtllib" => {
+                let dir = path.parent().unwrap_or_else(|| Path::new(""));
+                for file in fields {
+                    let mtl_path = dir.join(file);
+                    let source = read_to_string(&mtl_path).map_err(|e| {
+                        ImportError::new(
+                            path,
+                            Some(line),
+                            format!("mtllib {}: {}", file, e.message),
+                        )
+                    })?;
+                    materials.extend(parse_mtl(&source, &mtl_path)?);
+                }
+            }
+            "usemtl" => {
+                let name = fields.next().unwrap_or_default();
+                material = *materials.get(name).ok_or_else(|| {
+                    ImportError::new(path, Some(line), format!("unknown material '{}'", name))
+                })?;
+            }
+            // groups, objects, smoothing groups, lines and points
+            _ => {}
+        }
+    }
+
+    finish(triangles, positions, path)
+}
+
+/// Materials by name. `path` is only used for errors.
+pub fn parse_mtl(source: &str, path: &Path) -> Result<HashMap<String, Material>, ImportError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, Material)> = None;
+
+    for (i, text) in source.lines().enumerate() {
+        let line = i + 1;
+        let text = text.split('#').next().unwrap_or_default();
+        let mut fields = text.split_whitespace();
+        let keyword = match fields.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name = fields
+                .next()
+                .ok_or_else(|| ImportError::new(path, Some(line), "material without a name"))?;
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material);
+            }
+            current = Some((name.to_owned(), Material::default()));
+            continue;
+        }
+
+        let material = match (&mut current, keyword) {
+            (Some((_, material)), _) => material,
+            (None, "Kd" | "d" | "Tr" | "Ns") => {
+                return Err(ImportError::new(
+                    path,
+                    Some(line),
+                    format!("{} before newmtl", keyword),
+                ))
+            }
+            (None, _) => continue,
+        };
+        match keyword {
+            "Kd" => {
+                let kd = parse_vertex3(&mut fields, path, line)?;
+                material.color = Color::new(kd.x, kd.y, kd.z, material.color.a);
+            }
+            "d" => material.color.a = parse_field(&mut fields, "dissolve", path, line)?,
+            "Tr" => {
+                material.color.a = 1. - parse_field::<f32>(&mut fields, "transparency", path, line)?
+            }
+            "Ns" => material.specular = parse_field(&mut fields, "shininess", path, line)?,
+            _ => {}
+        }
+    }
+
+    if let Some((name, material)) = current {
+        materials.insert(name, material);
+    }
+    Ok(materials)
+}
+
+/// (position, uv, normal) indices of one face corner
+type Corner = (usize, Option<usize>, Option<usize>);
+
+fn parse_corner(
+    corner: &str,
+    positions: &[Vertex3],
+    uvs: &[Vec2],
+    normals: &[Vertex3],
+    path: &Path,
+    line: usize,
+) -> Result<Corner, ImportError> {
+    let mut parts = corner.split('/');
+    let mut index =
+        |what: &str, count: usize, required: bool| -> Result<Option<usize>, ImportError> {
+            match parts.next() {
+                Some("") | None if !required => Ok(None),
+                Some(text) => {
+                    let i: i64 = text.parse().map_err(|_| {
+                        ImportError::new(
+                            path,
+                            Some(line),
+                            format!("invalid {} index '{}'", what, text),
+                        )
+                    })?;
+                    resolve_index(i, count).map(Some).ok_or_else(|| {
+                        ImportError::new(
+                            path,
+                            Some(line),
+                            format!("{} index {} out of range", what, i),
+                        )
+                    })
+                }
+                None => Err(ImportError::new(
+                    path,
+                    Some(line),
+                    format!("missing {} index", what),
+                )),
+            }
+        };
+
+    let position = index("vertex", positions.len(), true)?.unwrap_or_default();
+    let uv = index("texture", uvs.len(), false)?;
+    let normal = index("normal", normals.len(), false)?;
+    Ok((position, uv, normal))
+}
+
+/// 1 based from the start, or counting back from the end when negative
+fn resolve_index(i: i64, count: usize) -> Option<usize> {
+    let resolved = if i > 0 { i - 1 } else { count as i64 + i };
+    if i != 0 && (0..count as i64).contains(&resolved) {
+        Some(resolved as usize)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const QUAD: &str = "\
+# a unit quad facing the viewer
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1 4/4/1
+";
+
+    #[test]
+    fn parses_and_triangulates() {
+        let model = parse(QUAD, Path::new("quad.obj")).unwrap();
+        assert_eq!(model.verticies.len(), 4);
+        assert_eq!(model.triangles.len(), 2);
+        // y is flipped and the winding reversed
+        assert_eq!(model.verticies[2], Vertex3::new(1., -1., 0.));
+        assert_eq!(model.triangles[0].ind, [0, 2, 1]);
+        assert_eq!(model.triangles[1].ind, [0, 3, 2]);
+
+        let t = model.triangles[0];
+        assert_eq!(t.normals, Some([Vertex3::new(0., 0., 1.); 3]));
+        assert_eq!(t.uvs.unwrap()[1], Vec2::new(1., 0.));
+        assert_eq!(t.color, WHITE);
+
+        assert_eq!(model.bounds_center.truncate(), Vertex3::new(0.5, -0.5, 0.));
+        assert!((model.bounds_radius - 0.5f32.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn corner_forms_and_negative_indices() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf -3//1 -2//1 -1//1\nf 1 2 3\n";
+        let model = parse(source, Path::new("tri.obj")).unwrap();
+        assert_eq!(model.triangles[0].ind, model.triangles[1].ind);
+        assert!(model.triangles[0].normals.is_some());
+        assert!(model.triangles[1].normals.is_none());
+        assert!(model.triangles[1].uvs.is_none());
+    }
+
+    #[test]
+    fn errors_report_line() {
+        let e = parse(
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\n\nf 1 2 4\n",
+            Path::new("bad.obj"),
+        )
+        .unwrap_err();
+        assert_eq!(e.to_string(), "bad.obj:5: vertex index 4 out of range");
+
+        let e = parse("v 0 zero 0\n", Path::new("bad.obj")).unwrap_err();
+        assert_eq!(e.to_string(), "bad.obj:1: invalid y 'zero'");
+
+        let e = parse("v 0 0 0\nf 1 1\n", Path::new("bad.obj")).unwrap_err();
+        assert_eq!(e.line, Some(2));
+
+        let e = parse("usemtl missing\n", Path::new("bad.obj")).unwrap_err();
+        assert_eq!(e.to_string(), "bad.obj:1: unknown material 'missing'");
+
+        let e = parse("v 0 0 0\n", Path::new("empty.obj")).unwrap_err();
+        assert_eq!(e.to_string(), "empty.obj: no faces");
+    }
+
+    #[test]
+    fn materials() {
+        let mtl = "newmtl red\nKd 1 0 0\nNs 50\n\nnewmtl glass\nKd 0 0 1\nd 0.25\n";
+        let materials = parse_mtl(mtl, Path::new("scene.mtl")).unwrap();
+        assert_eq!(materials["red"].color, Color::new(1., 0., 0., 1.));
+        assert_eq!(materials["red"].specular, 50.);
+        assert_eq!(materials["glass"].color, Color::new(0., 0., 1., 0.25));
+
+        let e = parse_mtl("newmtl red\nKd 1 0\n", Path::new("scene.mtl")).unwrap_err();
+        assert_eq!(e.to_string(), "scene.mtl:2: missing z");
+    }
+
+    #[test]
+    fn loads_mtllib_next_to_the_obj() {
+        let dir = std::env::temp_dir().join(format!("obj_import_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("colors.mtl"), "newmtl green\nKd 0 1 0\nNs 10\n").unwrap();
+        fs::write(
+            dir.join("tri.obj"),
+            "mtllib colors.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl green\nf 1 2 3\n",
+        )
+        .unwrap();
+
+        let model = super::super::load(dir.join("tri.obj")).unwrap();
+        assert_eq!(model.triangles[0].color, Color::new(0., 1., 0., 1.));
+        assert_eq!(model.triangles[0].specular, 10.);
+
+        fs::write(dir.join("missing.obj"), "mtllib nope.mtl\n").unwrap();
+        let e = load(&dir.join("missing.obj")).unwrap_err();
+        assert_eq!(e.line, Some(1));
+        assert!(e.message.starts_with("mtllib nope.mtl"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
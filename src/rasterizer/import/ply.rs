@@ -0,0 +1,571 @@
+//! Stanford PLY, ASCII or binary of either endianness. Reads positions, normals
+//! (`nx`, `ny`, `nz`), uvs (`u`/`v`, `s`/`t` or `texture_u`/`texture_v`) and
+//! vertex colors from the `vertex` element and polygons from the `face`
+//! element. Vertex colors are kept on the model and triangles get the average
+//! color of their corners, for wireframes. Other elements and properties are
+//! skipped.
+
+use super::{finish, read, triangulate, ImportError};
+use crate::rasterizer::{
+    color::WHITE,
+    data_types::{Model, Triangle, Vec2, Vertex3},
+};
+use crate::color::Color;
+use std::path::Path;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Property {
+    Scalar(String, Scalar),
+    /// name, type of the length, type of the items
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(name, _) | Property::List(name, _, _) => name,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+#[derive(Debug)]
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+    /// number of header lines, ASCII bodies continue counting from here
+    lines: usize,
+    /// byte offset of the body
+    body: usize,
+}
+
+/// One property value of an element, lists keep all their items
+#[derive(Debug)]
+enum Value {
+    Scalar(f64),
+    List(Vec<f64>),
+}
+
+pub fn load(path: &Path) -> Result<Model, ImportError> {
+    let bytes = read(path)?;
+    parse(&bytes, path)
+}
+
+/// `path` is only used for errors
+pub fn parse(bytes: &[u8], path: &Path) -> Result<Model, ImportError> {
+    let header = parse_header(bytes, path)?;
+    let mut body = Body::new(&header, bytes);
+
+    let mut positions: Vec<Vertex3> = vec![];
+    let mut normals: Vec<Vertex3> = vec![];
+    let mut uvs: Vec<Vec2> = vec![];
+    let mut colors: Vec<Color> = vec![];
+    let mut triangles: Vec<Triangle> = vec![];
+
+    for element in &header.elements {
+        let lookup = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| element.properties.iter().position(|p| p.name() == *name))
+                .collect::<Option<Vec<usize>>>()
+        };
+
+        match element.name.as_str() {
+            "vertex" => {
+                let position = lookup(&["x", "y", "z"]).ok_or_else(|| {
+                    ImportError::new(path, None, "vertex element without x, y and z")
+                })?;
+                let normal = lookup(&["nx", "ny", "nz"]);
+                let uv = lookup(&["u", "v"])
+                    .or_else(|| lookup(&["s", "t"]))
+                    .or_else(|| lookup(&["texture_u", "texture_v"]));
+                let color = lookup(&["red", "green", "blue"]);
+                let alpha = lookup(&["alpha"]);
+
+                for i in 0..element.count {
+                    let row = body.row(element, i, path)?;
+                    let get = |indices: &[usize]| -> Vec<f32> {
+                        indices
+                            .iter()
+                            .map(|p| match &row[*p] {
+                                Value::Scalar(v) => *v as f32,
+                                Value::List(items) => {
+                                    items.first().copied().unwrap_or_default() as f32
+                                }
+                            })
+                            .collect()
+                    };
+
+                    let p = get(&position);
+                    positions.push(Vertex3::new(p[0], p[1], p[2]));
+                    if let Some(normal) = &normal {
+                        let n = get(normal);
+                        normals.push(Vertex3::new(n[0], n[1], n[2]));
+                    }
+                    if let Some(uv) = &uv {
+                        let t = get(uv);
+                        // v = 0 is the bottom of the image, textures here start at the top
+                        uvs.push(Vec2::new(t[0], 1. - t[1]));
+                    }
+                    if let Some(color) = &color {
+                        let scale = color_scale(&element.properties[color[0]]);
+                        let c = get(color);
+                        let a = alpha.as_ref().map_or(1., |a| get(a)[0] * scale);
+                        colors.push(Color::new(c[0] * scale, c[1] * scale, c[2] * scale, a));
+                    }
+                }
+            }
+            "face" => {
+                let indices = lookup(&["vertex_indices"])
+                    .or_else(|| lookup(&["vertex_index"]))
+                    .ok_or_else(|| {
+                        ImportError::new(path, None, "face element without vertex_indices")
+                    })?[0];
+
+                for i in 0..element.count {
+                    let row = body.row(element, i, path)?;
+                    let polygon = match &row[indices] {
+                        Value::List(items) => items
+                            .iter()
+                            .map(|v| {
+                                if *v >= 0. && (*v as usize) < positions.len() {
+                                    Ok(*v as usize)
+                                } else {
+                                    Err(body.error(
+                                        path,
+                                        format!("face {}: vertex index {} out of range", i, v),
+                                    ))
+                                }
+                            })
+                            .collect::<Result<Vec<usize>, _>>()?,
+                        Value::Scalar(_) => {
+                            return Err(ImportError::new(
+                                path,
+                                None,
+                                "vertex_indices is not a list",
+                            ))
+                        }
+                    };
+                    if polygon.len() < 3 {
+                        return Err(
+                            body.error(path, format!("face {} with fewer than 3 corners", i))
+                        );
+                    }
+
+                    for [a, b, c] in triangulate(&polygon) {
+                        let color = if colors.is_empty() {
+                            WHITE
+                        } else {
+                            average([colors[a], colors[b], colors[c]])
+                        };
+                        let mut t = Triangle::new(a, b, c, color);
+                        if !normals.is_empty() {
+                            t = t.with_normals([normals[a], normals[b], normals[c]]);
+                        }
+                        if !uvs.is_empty() {
+                            t = t.with_uvs([uvs[a], uvs[b], uvs[c]]);
+                        }
+                        triangles.push(t);
+                    }
+                }
+            }
+            _ => {
+                for i in 0..element.count {
+                    body.row(element, i, path)?;
+                }
+            }
+        }
+    }
+
+    let model = finish(triangles, positions, path)?;
+    Ok(if colors.is_empty() {
+        model
+    } else {
+        model.with_vertex_colors(colors)
+    })
+}
+
+fn parse_header(bytes: &[u8], path: &Path) -> Result<Header, ImportError> {
+    const END: &[u8] = b"end_header";
+    let end = bytes
+        .windows(END.len())
+        .position(|w| w == END)
+        .ok_or_else(|| ImportError::new(path, None, "missing end_header"))?;
+    let body = match bytes[end + END.len()..].iter().position(|b| *b == b'\n') {
+        Some(newline) => end + END.len() + newline + 1,
+        None => bytes.len(),
+    };
+    let text = std::str::from_utf8(&bytes[..end])
+        .map_err(|_| ImportError::new(path, None, "header is not text"))?;
+
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    let mut lines = 1;
+    for (i, text) in text.lines().enumerate() {
+        let line = i + 1;
+        lines = line + 1;
+        let error = |message: String| ImportError::new(path, Some(line), message);
+        let mut fields = text.split_whitespace();
+        let keyword = fields.next().unwrap_or_default();
+        if line == 1 {
+            if keyword != "ply" {
+                return Err(error("not a PLY file".into()));
+            }
+            continue;
+        }
+
+        match keyword {
+            "format" => {
+                format = Some(match (fields.next(), fields.next()) {
+                    (Some("ascii"), Some("1.0")) => Format::Ascii,
+                    (Some("binary_little_endian"), Some("1.0")) => Format::BinaryLittleEndian,
+                    (Some("binary_big_endian"), Some("1.0")) => Format::BinaryBigEndian,
+                    _ => return Err(error(format!("unsupported format '{}'", text.trim()))),
+                });
+            }
+            "element" => {
+                let name = fields
+                    .next()
+                    .ok_or_else(|| error("element without a name".into()))?;
+                let count = fields
+                    .next()
+                    .and_then(|c| c.parse().ok())
+                    .ok_or_else(|| error(format!("invalid count for element {}", name)))?;
+                elements.push(Element {
+                    name: name.to_owned(),
+                    count,
+                    properties: vec![],
+                });
+            }
+            "property" => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before any element".into()))?;
+                let fields: Vec<&str> = fields.collect();
+                let scalar = |name: &str| {
+                    Scalar::parse(name).ok_or_else(|| error(format!("unknown type '{}'", name)))
+                };
+                let property = match fields.as_slice() {
+                    ["list", count, item, name] => {
+                        Property::List((*name).to_owned(), scalar(count)?, scalar(item)?)
+                    }
+                    [ty, name] => Property::Scalar((*name).to_owned(), scalar(ty)?),
+                    _ => return Err(error(format!("invalid property '{}'", text.trim()))),
+                };
+                element.properties.push(property);
+            }
+            "comment" | "obj_info" | "" => {}
+            _ => return Err(error(format!("unknown header line '{}'", text.trim()))),
+        }
+    }
+
+    let format = format.ok_or_else(|| ImportError::new(path, None, "missing format line"))?;
+    Ok(Header {
+        format,
+        elements,
+        lines,
+        body,
+    })
+}
+
+/// Reads element rows from the body, line by line for ASCII, value by value for binary
+struct Body<'a> {
+    format: Format,
+    bytes: &'a [u8],
+    /// byte offset for binary, start of the next line for ASCII
+    pos: usize,
+    /// line of the last ASCII row read
+    line: usize,
+}
+
+impl<'a> Body<'a> {
+    fn new(header: &Header, bytes: &'a [u8]) -> Self {
+        Self {
+            format: header.format,
+            bytes,
+            pos: header.body,
+            line: header.lines,
+        }
+    }
+
+    /// errors in ASCII bodies point at the row, binary ones only have the message
+    fn error(&self, path: &Path, message: String) -> ImportError {
+        match self.format {
+            Format::Ascii => ImportError::new(path, Some(self.line), message),
+            _ => ImportError::new(path, None, message),
+        }
+    }
+
+    fn row(
+        &mut self,
+        element: &Element,
+        index: usize,
+        path: &Path,
+    ) -> Result<Vec<Value>, ImportError> {
+        match self.format {
+            Format::Ascii => self.ascii_row(element, index, path),
+            _ => element
+                .properties
+                .iter()
+                .map(|property| match property {
+                    Property::Scalar(_, ty) => {
+                        Ok(Value::Scalar(self.binary(*ty, element, index, path)?))
+                    }
+                    Property::List(_, count, item) => {
+                        let count = self.binary(*count, element, index, path)? as usize;
+                        (0..count)
+                            .map(|_| self.binary(*item, element, index, path))
+                            .collect::<Result<_, _>>()
+                            .map(Value::List)
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    fn ascii_row(
+        &mut self,
+        element: &Element,
+        index: usize,
+        path: &Path,
+    ) -> Result<Vec<Value>, ImportError> {
+        // skip blank lines, every row is on a line of its own
+        let text = loop {
+            if self.pos >= self.bytes.len() {
+                return Err(self.error(path, format!("missing {} {}", element.name, index)));
+            }
+            let rest = &self.bytes[self.pos..];
+            let len = rest.iter().position(|b| *b == b'\n').unwrap_or(rest.len());
+            self.pos += len + 1;
+            self.line += 1;
+            let text = std::str::from_utf8(&rest[..len])
+                .map_err(|_| self.error(path, "invalid text".into()))?;
+            if !text.trim().is_empty() {
+                break text;
+            }
+        };
+
+        let mut fields = text.split_whitespace();
+        let mut next = |what: &str| -> Result<f64, ImportError> {
+            let field = fields.next().ok_or_else(|| {
+                self.error(
+                    path,
+                    format!("missing {} in {} {}", what, element.name, index),
+                )
+            })?;
+            field
+                .parse()
+                .map_err(|_| self.error(path, format!("invalid {} '{}'", what, field)))
+        };
+        element
+            .properties
+            .iter()
+            .map(|property| match property {
+                Property::Scalar(name, _) => next(name).map(Value::Scalar),
+                Property::List(name, _, _) => {
+                    let count = next(name)? as usize;
+                    (0..count)
+                        .map(|_| next(name))
+                        .collect::<Result<_, _>>()
+                        .map(Value::List)
+                }
+            })
+            .collect()
+    }
+
+    fn binary(
+        &mut self,
+        ty: Scalar,
+        element: &Element,
+        index: usize,
+        path: &Path,
+    ) -> Result<f64, ImportError> {
+        let size = ty.size();
+        let bytes = self.bytes.get(self.pos..self.pos + size).ok_or_else(|| {
+            self.error(
+                path,
+                format!("unexpected end of file in {} {}", element.name, index),
+            )
+        })?;
+        self.pos += size;
+
+        let mut buf = [0u8; 8];
+        buf[..size].copy_from_slice(bytes);
+        if self.format == Format::BinaryBigEndian {
+            buf[..size].reverse();
+        }
+        // buf now holds the value little endian
+        Ok(match ty {
+            Scalar::I8 => buf[0] as i8 as f64,
+            Scalar::U8 => buf[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes(buf),
+        })
+    }
+}
+
+/// integer colors go up to the type's maximum, float ones to 1
+fn color_scale(property: &Property) -> f32 {
+    match property {
+        Property::Scalar(_, Scalar::U8) => 1. / 255.,
+        Property::Scalar(_, Scalar::U16) => 1. / 65535.,
+        _ => 1.,
+    }
+}
+
+fn average(colors: [Color; 3]) -> Color {
+    let sum = colors.iter().fold([0.; 4], |s, c| {
+        [s[0] + c.r, s[1] + c.g, s[2] + c.b, s[3] + c.a]
+    });
+    Color::new(sum[0] / 3., sum[1] / 3., sum[2] / 3., sum[3] / 3.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASCII: &str = "\
+ply
+format ascii 1.0
+comment a quad with a red and a blue corner
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0
+1 0 0 255 0 0
+1 1 0 0 0 255
+0 1 0 0 0 255
+4 0 1 2 3
+";
+
+    #[test]
+    fn ascii() {
+        let model = parse(ASCII.as_bytes(), Path::new("quad.ply")).unwrap();
+        assert_eq!(model.verticies.len(), 4);
+        assert_eq!(model.triangles.len(), 2);
+        assert_eq!(model.verticies[2], Vertex3::new(1., -1., 0.));
+        assert_eq!(model.triangles[0].ind, [0, 2, 1]);
+        let c = model.triangles[0].color;
+        assert!((c.r - 2. / 3.).abs() < 1e-6 && (c.b - 1. / 3.).abs() < 1e-6);
+        let colors = model.attributes.colors.as_ref().unwrap();
+        assert_eq!(colors[1], Color::new(1., 0., 0., 1.));
+        assert_eq!(colors[2], Color::new(0., 0., 1., 1.));
+        assert!(model.triangles[0].normals.is_none());
+    }
+
+    #[test]
+    fn binary_matches_ascii() {
+        let header = "ply\nformat binary_big_endian 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+                      property float z\nproperty double nx\nproperty double ny\nproperty double nz\n\
+                      element face 1\nproperty list uchar uint vertex_index\nend_header\n";
+        let mut bytes = header.as_bytes().to_vec();
+        for v in [[0f32, 0., 0.], [1., 0., 0.], [0., 1., 0.]] {
+            for x in v {
+                bytes.extend_from_slice(&x.to_be_bytes());
+            }
+            for n in [0f64, 0., 1.] {
+                bytes.extend_from_slice(&n.to_be_bytes());
+            }
+        }
+        bytes.push(3);
+        for i in [0u32, 1, 2] {
+            bytes.extend_from_slice(&i.to_be_bytes());
+        }
+
+        let model = parse(&bytes, Path::new("tri.ply")).unwrap();
+        assert_eq!(model.verticies[1], Vertex3::new(1., 0., 0.));
+        assert_eq!(model.verticies[2], Vertex3::new(0., -1., 0.));
+        assert_eq!(model.triangles[0].ind, [0, 2, 1]);
+        assert_eq!(
+            model.triangles[0].normals,
+            Some([Vertex3::new(0., 0., 1.); 3])
+        );
+        assert_eq!(model.triangles[0].color, WHITE);
+        assert!(model.attributes.colors.is_none());
+
+        let e = parse(&bytes[..bytes.len() - 2], Path::new("tri.ply")).unwrap_err();
+        assert_eq!(e.to_string(), "tri.ply: unexpected end of file in face 0");
+    }
+
+    #[test]
+    fn errors_report_line() {
+        let bad_value = ASCII.replace("1 1 0 0 0 255", "1 one 0 0 0 255");
+        let e = parse(bad_value.as_bytes(), Path::new("quad.ply")).unwrap_err();
+        assert_eq!(e.to_string(), "quad.ply:16: invalid y 'one'");
+
+        let bad_index = ASCII.replace("4 0 1 2 3", "4 0 1 2 7");
+        let e = parse(bad_index.as_bytes(), Path::new("quad.ply")).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "quad.ply:18: face 0: vertex index 7 out of range"
+        );
+
+        let bad_type = ASCII.replace("property uchar red", "property colour red");
+        let e = parse(bad_type.as_bytes(), Path::new("quad.ply")).unwrap_err();
+        assert_eq!(e.to_string(), "quad.ply:8: unknown type 'colour'");
+
+        let e = parse(b"solid cube\n", Path::new("cube.ply")).unwrap_err();
+        assert_eq!(e.to_string(), "cube.ply: missing end_header");
+    }
+}
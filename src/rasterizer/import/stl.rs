@@ -0,0 +1,233 @@
+//! STL, ASCII or binary. Binary files are recognized by their size matching the
+//! triangle count in the header, since many of them also start with "solid".
+//! Every facet gets its own three vertices and the facet normal on each corner,
+//! or computed normals when the file leaves it zero.
+
+use super::{finish, parse_vertex3, read, ImportError};
+use crate::rasterizer::{
+    color::WHITE,
+    data_types::{Model, Triangle, Vertex3},
+};
+use std::path::Path;
+
+const BINARY_HEADER: usize = 84;
+const BINARY_FACET: usize = 50;
+
+pub fn load(path: &Path) -> Result<Model, ImportError> {
+    let bytes = read(path)?;
+    parse(&bytes, path)
+}
+
+/// `path` is only used for errors
+pub fn parse(bytes: &[u8], path: &Path) -> Result<Model, ImportError> {
+    if is_binary(bytes) {
+        parse_binary(bytes, path)
+    } else {
+        let text = std::str::from_utf8(bytes)
+            .map_err(|_| ImportError::new(path, None, "neither ASCII nor binary STL"))?;
+        parse_ascii(text, path)
+    }
+}
+
+fn is_binary(bytes: &[u8]) -> bool {
+    match bytes.get(80..BINARY_HEADER) {
+        Some(count) => {
+            let count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize;
+            bytes.len() == BINARY_HEADER + count * BINARY_FACET
+        }
+        None => false,
+    }
+}
+
+fn parse_binary(bytes: &[u8], path: &Path) -> Result<Model, ImportError> {
+    let mut verticies = vec![];
+    let mut triangles = vec![];
+
+    for facet in bytes[BINARY_HEADER..].chunks_exact(BINARY_FACET) {
+        let value = |i: usize| {
+            f32::from_le_bytes([
+                facet[i * 4],
+                facet[i * 4 + 1],
+                facet[i * 4 + 2],
+                facet[i * 4 + 3],
+            ])
+        };
+        let vector = |i: usize| Vertex3::new(value(i), value(i + 1), value(i + 2));
+        // the 2 trailing attribute bytes have no agreed meaning
+        add_facet(
+            &mut triangles,
+            &mut verticies,
+            vector(0),
+            [vector(3), vector(6), vector(9)],
+        );
+    }
+
+    finish(triangles, verticies, path)
+}
+
+fn parse_ascii(text: &str, path: &Path) -> Result<Model, ImportError> {
+    let mut verticies = vec![];
+    let mut triangles = vec![];
+    let mut normal: Option<Vertex3> = None;
+    let mut corners: Vec<Vertex3> = Vec::with_capacity(3);
+    let mut facet_line = 0;
+
+    for (i, text) in text.lines().enumerate() {
+        let line = i + 1;
+        let error = |message: &str| ImportError::new(path, Some(line), message);
+        let mut fields = text.split_whitespace();
+        match fields.next() {
+            Some("solid") if line == 1 => {}
+            Some("facet") => {
+                if normal.is_some() {
+                    return Err(error("facet inside a facet"));
+                }
+                if fields.next() != Some("normal") {
+                    return Err(error("expected 'facet normal'"));
+                }
+                normal = Some(parse_vertex3(&mut fields, path, line)?);
+                facet_line = line;
+            }
+            Some("vertex") => {
+                if normal.is_none() {
+                    return Err(error("vertex outside a facet"));
+                }
+                if corners.len() == 3 {
+                    return Err(error("facet with more than 3 vertices"));
+                }
+                corners.push(parse_vertex3(&mut fields, path, line)?);
+            }
+            Some("endfacet") => {
+                let n = normal
+                    .take()
+                    .ok_or_else(|| error("endfacet without facet"))?;
+                if corners.len() != 3 {
+                    return Err(ImportError::new(
+                        path,
+                        Some(facet_line),
+                        "facet without 3 vertices",
+                    ));
+                }
+                add_facet(
+                    &mut triangles,
+                    &mut verticies,
+                    n,
+                    [corners[0], corners[1], corners[2]],
+                );
+                corners.clear();
+            }
+            Some("outer" | "endloop" | "endsolid") | None => {}
+            Some(keyword) => return Err(error(&format!("unexpected '{}'", keyword))),
+        }
+    }
+    if normal.is_some() {
+        return Err(ImportError::new(
+            path,
+            Some(facet_line),
+            "facet without endfacet",
+        ));
+    }
+
+    finish(triangles, verticies, path)
+}
+
+fn add_facet(
+    triangles: &mut Vec<Triangle>,
+    verticies: &mut Vec<Vertex3>,
+    normal: Vertex3,
+    corners: [Vertex3; 3],
+) {
+    let first = verticies.len();
+    verticies.extend_from_slice(&corners);
+    let mut t = Triangle::new(first, first + 1, first + 2, WHITE);
+    if normal != Vertex3::ZERO {
+        t = t.with_normals([normal.normalize(); 3]);
+    }
+    triangles.push(t);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASCII: &str = "\
+solid tri
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+  facet normal 0 0 0
+    outer loop
+      vertex 0 0 0
+      vertex 0 1 0
+      vertex -1 0 0
+    endloop
+  endfacet
+endsolid tri
+";
+
+    fn binary() -> Vec<u8> {
+        // header text starting with "solid" like many exporters write
+        let mut bytes = b"solid exported".to_vec();
+        bytes.resize(80, 0);
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        for v in [0f32, 0., 2., 0., 0., 0., 1., 0., 0., 0., 1., 0.] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0, 0]);
+        bytes
+    }
+
+    #[test]
+    fn ascii() {
+        let model = parse(ASCII.as_bytes(), Path::new("tri.stl")).unwrap();
+        assert_eq!(model.verticies.len(), 6);
+        assert_eq!(model.triangles[0].ind, [0, 2, 1]);
+        assert_eq!(model.verticies[2], Vertex3::new(0., -1., 0.));
+        assert_eq!(
+            model.triangles[0].normals,
+            Some([Vertex3::new(0., 0., 1.); 3])
+        );
+        assert!(model.triangles[1].normals.is_none());
+    }
+
+    #[test]
+    fn binary_with_solid_header() {
+        let bytes = binary();
+        let model = parse(&bytes, Path::new("tri.stl")).unwrap();
+        assert_eq!(model.triangles.len(), 1);
+        assert_eq!(model.verticies[1], Vertex3::new(1., 0., 0.));
+        // normal is normalized and flipped like the positions
+        assert_eq!(
+            model.triangles[0].normals,
+            Some([Vertex3::new(0., 0., 1.); 3])
+        );
+
+        // a truncated file isn't binary, and isn't valid ASCII either
+        let e = parse(&bytes[..bytes.len() - 1], Path::new("tri.stl")).unwrap_err();
+        assert_eq!(e.path, Path::new("tri.stl"));
+    }
+
+    #[test]
+    fn errors_report_line() {
+        let e = parse(
+            ASCII.replace("vertex 1 0 0", "vertex 1 0").as_bytes(),
+            Path::new("tri.stl"),
+        )
+        .unwrap_err();
+        assert_eq!(e.to_string(), "tri.stl:5: missing z");
+
+        let two_corners = ASCII.replacen("      vertex 0 1 0\n", "", 1);
+        let e = parse(two_corners.as_bytes(), Path::new("tri.stl")).unwrap_err();
+        assert_eq!(e.to_string(), "tri.stl:2: facet without 3 vertices");
+
+        let e = parse(b"solid x\nfacet normal 0 0 1\nloop\n", Path::new("tri.stl")).unwrap_err();
+        assert_eq!(e.to_string(), "tri.stl:3: unexpected 'loop'");
+
+        let e = parse(b"solid empty\nendsolid empty\n", Path::new("empty.stl")).unwrap_err();
+        assert_eq!(e.to_string(), "empty.stl: no faces");
+    }
+}
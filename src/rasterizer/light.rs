@@ -0,0 +1,211 @@
+use super::data_types::{Mat4x4, Vertex3};
+
+#[derive(Debug, Copy, Clone)]
+pub enum LightType {
+    Ambient,
+    Point,
+    Directional,
+    /// a point light that only shines into a cone, see `Light::with_spot_angle`
+    Spot,
+}
+
+/// Same semantics as the raytracer's `LightSource`: point lights have a position,
+/// directional lights a direction pointing towards the light. Spot lights have
+/// both, the direction points from the middle of their cone towards the light.
+#[derive(Debug, Copy, Clone)]
+pub struct Light {
+    pub light_type: LightType,
+    pub intensity: f32,
+    pub position: Option<Vertex3>,
+    pub direction: Option<Vertex3>,
+    /// angle between a spot light's direction and the edge of its cone, in degrees
+    pub spot_angle: f32,
+}
+
+impl Light {
+    pub fn new(
+        light_type: LightType,
+        intensity: f32,
+        position: Option<Vertex3>,
+        direction: Option<Vertex3>,
+    ) -> Self {
+        Light {
+            light_type,
+            intensity,
+            position,
+            direction,
+            spot_angle: 30.,
+        }
+    }
+
+    pub fn with_spot_angle(self, spot_angle: f32) -> Self {
+        Self { spot_angle, ..self }
+    }
+
+    /// How much of a spot light reaches along `l`, the direction from the surface
+    /// to the light. It fades out over the outer fifth of the cone, other lights
+    /// reach everywhere.
+    pub fn cone_falloff(&self, l: Vertex3) -> f32 {
+        let LightType::Spot = self.light_type else {
+            return 1.;
+        };
+        let axis = self.direction.expect("Spot light without direction");
+        let cos = l.normalize_or_zero().dot(axis.normalize_or_zero());
+        let outer = self.spot_angle.to_radians().cos();
+        let inner = (self.spot_angle.to_radians() * 0.8).cos();
+        ((cos - outer) / (inner - outer)).clamp(0., 1.)
+    }
+
+    /// move the light into the space `transform` maps to, used to go from world to camera space
+    pub fn transformed(&self, transform: Mat4x4) -> Self {
+        Light {
+            position: self.position.map(|p| transform.transform_point3(p)),
+            direction: self.direction.map(|d| transform.transform_vector3(d)),
+            ..*self
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ShadingModel {
+    /// one intensity per triangle, computed at its center
+    Flat,
+    /// intensities computed at the vertices and interpolated across the triangle
+    Gouraud,
+    /// normals interpolated across the triangle and lit per pixel
+    #[default]
+    Phong,
+}
+
+/// Light intensity reaching `vertex`, in camera space so the viewer is at the origin.
+/// `specular` is the Phong exponent, zero or less for matte surfaces.
+pub fn compute_illumination(vertex: Vertex3, normal: Vertex3, lights: &[Light], specular: f32) -> f32 {
+    compute_illumination_with(vertex, normal, lights, specular, |_| 1.)
+}
+
+/// Like `compute_illumination`, with the light from `lights[i]` scaled by
+/// `visibility(i)`, 0 where it's in shadow. Ambient light is never scaled.
+pub fn compute_illumination_with(
+    vertex: Vertex3,
+    normal: Vertex3,
+    lights: &[Light],
+    specular: f32,
+    visibility: impl Fn(usize) -> f32,
+) -> f32 {
+    let normal = normal.normalize_or_zero();
+    let view = -vertex;
+    let mut illumination = 0.;
+
+    for (i, light) in lights.iter().enumerate() {
+        let l = match light.light_type {
+            LightType::Ambient => {
+                illumination += light.intensity;
+                continue;
+            }
+            LightType::Point | LightType::Spot => light.position.expect("Point or spot light without position") - vertex,
+            LightType::Directional => light.direction.expect("Directional light without direction"),
+        };
+        let intensity = light.intensity * light.cone_falloff(l) * visibility(i);
+        if intensity <= 0. {
+            continue;
+        }
+
+        // Diffuse
+        let nl = normal.dot(l);
+        if nl > 0. {
+            illumination += intensity * nl / l.length();
+        }
+
+        // Specular
+        if specular > 0. {
+            let r = 2. * nl * normal - l;
+            let rv = r.dot(view);
+            if rv > 0. {
+                illumination += intensity * (rv / (r.length() * view.length())).powf(specular);
+            }
+        }
+    }
+
+    illumination
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn ambient_ignores_normal() {
+        let lights = [Light::new(LightType::Ambient, 0.3, None, None)];
+        let i = compute_illumination(Vertex3::new(0., 0., 5.), Vertex3::new(0., 0., 1.), &lights, 10.);
+        assert!(close(i, 0.3));
+    }
+
+    #[test]
+    fn diffuse_follows_angle() {
+        let vertex = Vertex3::new(0., 0., 5.);
+        let normal = Vertex3::new(0., 0., -2.);
+        let head_on = [Light::new(LightType::Directional, 0.5, None, Some(Vertex3::new(0., 0., -3.)))];
+        assert!(close(compute_illumination(vertex, normal, &head_on, 0.), 0.5));
+
+        let angled = [Light::new(LightType::Point, 0.5, Some(Vertex3::new(0., 5., 0.)), None)];
+        let expected = 0.5 * std::f32::consts::FRAC_1_SQRT_2;
+        assert!(close(compute_illumination(vertex, normal, &angled, 0.), expected));
+
+        let behind = [Light::new(LightType::Directional, 0.5, None, Some(Vertex3::new(0., 0., 1.)))];
+        assert!(close(compute_illumination(vertex, normal, &behind, 100.), 0.));
+    }
+
+    #[test]
+    fn specular_peaks_on_mirror_direction() {
+        // light straight behind the viewer reflects right back into the camera
+        let vertex = Vertex3::new(0., 0., 5.);
+        let normal = Vertex3::new(0., 0., -1.);
+        let lights = [Light::new(LightType::Point, 0.5, Some(Vertex3::new(0., 0., 0.)), None)];
+        assert!(close(compute_illumination(vertex, normal, &lights, 50.), 1.0));
+
+        // a slightly tilted surface only catches part of the highlight
+        let tilted = Vertex3::new(0., 0.1, -1.);
+        let matte = compute_illumination(vertex, tilted, &lights, 0.);
+        let shiny = compute_illumination(vertex, tilted, &lights, 50.);
+        assert!(shiny - matte > 0. && shiny - matte < 0.5);
+    }
+
+    #[test]
+    fn spot_lights_only_reach_into_their_cone() {
+        let vertex = Vertex3::new(0., 0., 5.);
+        let normal = Vertex3::new(0., 0., -1.);
+        // straight in front of the surface, shining at it
+        let spot = Light::new(LightType::Spot, 0.5, Some(Vertex3::new(0., 0., 0.)), Some(Vertex3::new(0., 0., -1.)));
+        assert!(close(compute_illumination(vertex, normal, &[spot], 0.), 0.5));
+        // the same light turned 45 degrees away
+        let away = spot.with_spot_angle(30.);
+        let away = Light { direction: Some(Vertex3::new(1., 0., -1.)), ..away };
+        assert!(close(compute_illumination(vertex, normal, &[away], 0.), 0.));
+        assert!(close(compute_illumination(vertex, normal, &[away.with_spot_angle(60.)], 0.), 0.5));
+    }
+
+    #[test]
+    fn visibility_scales_direct_light() {
+        let vertex = Vertex3::new(0., 0., 5.);
+        let normal = Vertex3::new(0., 0., -1.);
+        let lights = [
+            Light::new(LightType::Ambient, 0.2, None, None),
+            Light::new(LightType::Directional, 0.5, None, Some(Vertex3::new(0., 0., -1.))),
+        ];
+        let half = compute_illumination_with(vertex, normal, &lights, 0., |_| 0.5);
+        assert!(close(half, 0.2 + 0.25));
+    }
+
+    #[test]
+    fn transform_moves_position_not_direction() {
+        let t = Mat4x4::from_translation(Vertex3::new(1., 2., 3.));
+        let p = Light::new(LightType::Point, 1., Some(Vertex3::new(0., 0., 0.)), None).transformed(t);
+        assert_eq!(p.position, Some(Vertex3::new(1., 2., 3.)));
+        let d = Light::new(LightType::Directional, 1., None, Some(Vertex3::new(0., 0., 1.))).transformed(t);
+        assert_eq!(d.direction, Some(Vertex3::new(0., 0., 1.)));
+    }
+}
@@ -1,82 +1,127 @@
 use super::{
+    antialiasing::AntiAliasing,
     camera::Camera,
-    utils::math,
-    data_types::{Cube, Instance, Model, Vertex3, Vertex4},
-    render, utils::{self, math::INFINITY_F32},
+    color::{Color, GRAY, WHITE},
+    culling::{CullStats, InstanceBvh},
+    data_types::{Cube, Instance, Model, Triangle, Vec2, Vertex3},
+    light::{Light, LightType, ShadingModel},
+    texture::Texture,
+    render, shadows::ShadowSettings, utils,
+    wireframe::RenderMode,
 };
-use macroquad::prelude::Image;
-use once_cell::sync::Lazy;
-use std::sync::RwLock;
-
-#[derive(Debug, Default)]
-pub struct GlobalCtx {
-    width: usize,
-    height: usize,
-    view_width: f32,
-    view_height: f32,
-    viewport_dist: f32,
-    draw_outline: bool
+use crate::framebuffer::Framebuffer;
+use std::sync::Arc;
+
+/// Settings for a single render, passed down to everything that needs them.
+/// Renders don't share any state, so several can run at once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderContext {
+    /// canvas size in pixels
+    pub width: usize,
+    pub height: usize,
+    /// filled triangles, their edges or both
+    pub render_mode: RenderMode,
+    /// how far edges are pulled towards the camera, as a fraction of their distance,
+    /// so they aren't hidden by the triangles they belong to
+    pub wireframe_bias: f32,
+    /// draw edges with Xiaolin Wu's lines
+    pub anti_aliased_lines: bool,
+    pub shading_model: ShadingModel,
+    /// 1 renders on the calling thread, more splits the canvas into tiles
+    /// rendered in parallel, see `tiles`
+    pub threads: usize,
+    /// samples per pixel, the depth buffer needs a value for each, see `depth_buffer`
+    pub anti_aliasing: AntiAliasing,
+    /// shadow maps for every light that isn't ambient, `None` for no shadows
+    pub shadows: Option<ShadowSettings>,
 }
 
-static GLOBAL_CTX: Lazy<RwLock<GlobalCtx>> = Lazy::new(|| RwLock::new(GlobalCtx::default()));
+impl RenderContext {
+    /// filled triangles with aliased edges, Phong shading, a single thread, no anti-aliasing and no shadows
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            render_mode: RenderMode::default(),
+            wireframe_bias: 0.01,
+            anti_aliased_lines: false,
+            shading_model: ShadingModel::default(),
+            threads: 1,
+            anti_aliasing: AntiAliasing::default(),
+            shadows: None,
+        }
+    }
 
-pub fn get_canvas_dimensions() -> (usize, usize) {
-    let g = GLOBAL_CTX.try_read().unwrap();
-    (g.width, g.height)
-}
+    pub fn with_shading_model(self, shading_model: ShadingModel) -> Self {
+        Self { shading_model, ..self }
+    }
 
-pub fn get_view_dimensions() -> (f32, f32) {
-    let g = GLOBAL_CTX.try_read().unwrap();
-    (g.view_width, g.view_height)
-}
+    pub fn with_render_mode(self, render_mode: RenderMode) -> Self {
+        Self { render_mode, ..self }
+    }
 
-pub fn get_viewport_dist() -> f32 {
-    let g = GLOBAL_CTX.try_read().unwrap();
-    g.viewport_dist
+    pub fn with_wireframe_bias(self, wireframe_bias: f32) -> Self {
+        Self { wireframe_bias, ..self }
+    }
+
+    pub fn with_anti_aliased_lines(self, anti_aliased_lines: bool) -> Self {
+        Self { anti_aliased_lines, ..self }
+    }
+
+    pub fn with_threads(self, threads: usize) -> Self {
+        Self { threads: threads.max(1), ..self }
+    }
+
+    pub fn with_anti_aliasing(self, anti_aliasing: AntiAliasing) -> Self {
+        Self { anti_aliasing, ..self }
+    }
+
+    pub fn with_shadows(self, shadows: Option<ShadowSettings>) -> Self {
+        Self { shadows, ..self }
+    }
+
+    /// An empty depth buffer with a value for every sample of every pixel. It's
+    /// cleared to 0, the far plane, since larger depths are closer.
+    pub fn depth_buffer(&self) -> Vec<f32> {
+        vec![0.; self.width * self.height * self.anti_aliasing.samples()]
+    }
+
+    /// width / height of the canvas, for the camera's projection
+    pub fn aspect(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
 }
 
-pub fn get_draw_outline() -> bool {
-    let g = GLOBAL_CTX.try_read().unwrap();
-    g.draw_outline
+pub fn run(fb: &mut impl Framebuffer, ctx: &RenderContext) {
+    cube_scene(fb, ctx);
 }
 
-pub fn init_global_ctx(width: usize, height: usize) -> bool {
-    let g = GLOBAL_CTX.try_write();
-    match g {
-        Ok(mut global_ctx) => {
-            global_ctx.width = width;
-            global_ctx.height = height;
-            global_ctx.view_width = 1.;
-            global_ctx.view_height = 1.;
-            global_ctx.viewport_dist = 1.;
-            global_ctx.draw_outline = true;
-            return true;
-        }
-        Err(_) => {
-            println!("Couldn't write to global ctx");
-            return false;
-        }
-    }
+/// textured crates on a tiled floor
+pub fn run_textured(fb: &mut impl Framebuffer, ctx: &RenderContext) {
+    textured_scene(fb, ctx);
 }
 
-pub fn run(image: &mut Image, width: usize, height: usize) {
-    let init = init_global_ctx(width, height);
-    if init {
-        cube_scene(image);
-    } else {
-        return;
-    }
+/// same lights as the raytracer's test scene
+fn test_lights() -> Vec<Light> {
+    vec![
+        Light::new(LightType::Ambient, 0.2, None, None),
+        Light::new(LightType::Point, 0.6, Some(Vertex3::new(2., -1., 0.)), None),
+        Light::new(LightType::Directional, 0.2, None, Some(Vertex3::new(1., -4., 4.))),
+    ]
 }
 
-fn cube_scene(image: &mut Image) {
+fn cube_scene(fb: &mut impl Framebuffer, ctx: &RenderContext) {
     let camera = Camera::new(
         Vertex3::new(-3., 1., 2.),
         utils::make_rotation_mat(-30.),
-    );
+    )
+    .with_aspect(ctx.aspect());
     let base_cube: Cube = Cube::debug_cube();
-    let cube_model: Model = Model::from((&base_cube, Vertex4::new(0., 0., 0., 1.), math::sqrt_f(3.)));
+    let cube_model: Model = Model::from(&base_cube);
 
-    let mut depth_buf = vec![INFINITY_F32; image.width() * image.height()];
+    let mut depth_buf = ctx.depth_buffer();
+
+    let lights = test_lights();
 
     let instances = vec![
         Instance::new(&cube_model, Vertex3::new(-1.5, 0., 7.), None, Some(0.75)),
@@ -88,5 +133,111 @@ fn cube_scene(image: &mut Image) {
         )
     ];
 
-    render::render_scene(image, &camera, &instances, &mut depth_buf);
+    render::render_scene(fb, ctx, &camera, &instances, &lights, &mut depth_buf);
+}
+
+fn textured_scene(fb: &mut impl Framebuffer, ctx: &RenderContext) {
+    let camera = Camera::new(Vertex3::new(0., -1., 0.), utils::make_rotation_mat(0.)).with_aspect(ctx.aspect());
+    let crate_texture = Arc::new(Texture::crate_side(64));
+    let floor_texture = Arc::new(Texture::checkerboard(64, 2, WHITE, GRAY));
+
+    let crate_model = Model::from(&Cube::debug_cube()).with_texture(crate_texture);
+    let floor_model = floor(16., 8.).with_texture(floor_texture);
+
+    let mut depth_buf = ctx.depth_buffer();
+    let lights = test_lights();
+
+    // the crates rest on the floor, which runs behind the camera to show the near clipping
+    let instances = vec![
+        Instance::new(&floor_model, Vertex3::new(0., 1., 6.), None, None),
+        Instance::new(&crate_model, Vertex3::new(-1.5, 0., 6.), None, None),
+        Instance::new(&crate_model, Vertex3::new(1.75, 0., 5.), Some(utils::make_rotation_mat(30.)), None),
+    ];
+
+    render::render_scene(fb, ctx, &camera, &instances, &lights, &mut depth_buf);
+}
+
+/// The models a `City` is made of. They're kept apart from it because its
+/// instances borrow them.
+pub struct CityModels {
+    buildings: Vec<Model>,
+    ground: Model,
+}
+
+impl Default for CityModels {
+    fn default() -> Self {
+        let shades = [0.45, 0.6, 0.75, 0.9];
+        let buildings = shades.iter().map(|&s| building(Color::new(s, s, s * 0.9, 1.))).collect();
+        let ground = floor(City::BLOCKS as f32 * City::SPACING, 1.);
+        Self { buildings, ground }
+    }
+}
+
+/// Thousands of buildings in a grid of blocks with streets between them. The
+/// instances and their `InstanceBvh` are built once, every render culls them as
+/// the camera looks down one of the streets and only draws the ones in view.
+pub struct City<'a> {
+    instances: Vec<Instance<'a>>,
+    bvh: InstanceBvh,
+}
+
+impl<'a> City<'a> {
+    const BLOCKS: usize = 64;
+    const SPACING: f32 = 4.;
+
+    pub fn new(models: &'a CityModels) -> Self {
+        let extent = Self::BLOCKS as f32 * Self::SPACING;
+        let mut instances = vec![Instance::new(&models.ground, Vertex3::new(0., 0., 0.), None, None)];
+        for row in 0..Self::BLOCKS {
+            for col in 0..Self::BLOCKS {
+                // heights that look random but are the same every time
+                let hash = (row * 7919 + col * 104729) % 97;
+                let height = 1. + (hash % 12) as f32;
+                let position = Vertex3::new((col as f32 + 0.5) * Self::SPACING - extent / 2., -height / 2., (row as f32 + 0.5) * Self::SPACING - extent / 2.);
+                let instance = Instance::new(&models.buildings[hash % models.buildings.len()], position, None, None);
+                instances.push(instance.with_scale(Vertex3::new(1.2, height / 2., 1.2)));
+            }
+        }
+        let bvh = InstanceBvh::new(&instances);
+        Self { instances, bvh }
+    }
+
+    /// draws the buildings in view, and returns how many were culled
+    pub fn render(&self, fb: &mut impl Framebuffer, ctx: &RenderContext) -> CullStats {
+        let camera = Camera::new(Vertex3::new(2., -2., -110.), utils::make_rotation_mat(15.)).with_aspect(ctx.aspect());
+        let mut depth_buf = ctx.depth_buffer();
+        let lights = test_lights();
+        render::render_scene_culled(fb, ctx, &camera, &self.instances, &self.bvh, &lights, &mut depth_buf)
+    }
+}
+
+/// the debug cube in one color, two units on a side
+fn building(color: Color) -> Model {
+    let mut model = Model::from(&Cube::debug_cube());
+    for t in &mut model.triangles {
+        t.color = color;
+    }
+    model
+}
+
+/// `size` by `size` square in the xz plane facing up (-y), with the texture repeated `repeats` times
+fn floor(size: f32, repeats: f32) -> Model {
+    let s = size / 2.;
+    let up = Vertex3::new(0., -1., 0.);
+    let verticies = vec![
+        Vertex3::new(-s, 0., -s),
+        Vertex3::new(s, 0., -s),
+        Vertex3::new(s, 0., s),
+        Vertex3::new(-s, 0., s),
+    ];
+    let uv = |u: f32, v: f32| Vec2::new(u * repeats, v * repeats);
+    let triangles = vec![
+        Triangle::new(0, 1, 2, WHITE)
+            .with_normals([up; 3])
+            .with_uvs([uv(0., 1.), uv(1., 1.), uv(1., 0.)]),
+        Triangle::new(0, 2, 3, WHITE)
+            .with_normals([up; 3])
+            .with_uvs([uv(0., 1.), uv(1., 0.), uv(0., 0.)]),
+    ];
+    Model::from_mesh(triangles, verticies)
 }
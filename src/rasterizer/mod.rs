@@ -1,7 +1,20 @@
-mod camera;
-mod color;
-mod data_types;
+pub mod antialiasing;
+pub mod blending;
+pub mod camera;
+pub mod clipping;
+pub mod color;
+pub mod coverage;
+pub mod culling;
+pub mod data_types;
+pub mod import;
+pub mod light;
 pub mod main;
-mod render;
-mod utils;
-mod clipping;
+pub mod primitives;
+pub mod render;
+pub mod scene_graph;
+pub mod shader;
+pub mod shadows;
+pub mod texture;
+pub mod tiles;
+pub mod utils;
+pub mod wireframe;
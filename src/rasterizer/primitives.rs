@@ -0,0 +1,448 @@
+//! Procedural meshes for blocking out scenes. Everything is centered on the
+//! origin with its axis along y, and comes with per corner normals and uvs.
+//! Like the rest of the rasterizer -y is up, so the tip of a cone and the
+//! facing side of a plane point towards -y.
+
+use super::data_types::{Model, Triangle, Vec2, Vertex3};
+use crate::color::Color;
+use std::{collections::HashMap, f32::consts::PI};
+
+const TAU: f32 = 2. * PI;
+const UP: Vertex3 = glam::const_vec3!([0., -1., 0.]);
+const DOWN: Vertex3 = glam::const_vec3!([0., 1., 0.]);
+
+/// `segments` around the y axis, `rings` from top to bottom
+pub fn uv_sphere(radius: f32, segments: usize, rings: usize, color: Color) -> Model {
+    let segments = segments.max(3);
+    let rings = rings.max(2);
+    let mut mesh = MeshBuilder::new(color);
+    mesh.add_grid(segments, rings, |i, j| {
+        let normal = spherical(i as f32 / segments as f32, j as f32 / rings as f32 * PI);
+        (normal * radius, normal)
+    });
+    mesh.build()
+}
+
+/// Icosahedron with every face split in four `subdivisions` times and pushed out
+/// onto the sphere, which spreads the triangles much more evenly than `uv_sphere`.
+pub fn icosphere(radius: f32, subdivisions: usize, color: Color) -> Model {
+    let (mut points, mut faces) = icosahedron();
+    for _ in 0..subdivisions {
+        let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut midpoint = |a: usize, b: usize, points: &mut Vec<Vertex3>| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                points.push((points[a] + points[b]).normalize());
+                points.len() - 1
+            })
+        };
+        faces = faces
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let ab = midpoint(a, b, &mut points);
+                let bc = midpoint(b, c, &mut points);
+                let ca = midpoint(c, a, &mut points);
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let mut mesh = MeshBuilder::new(color);
+    mesh.verticies = points.iter().map(|p| *p * radius).collect();
+    for face in faces {
+        let normals = face.map(|i| points[i]);
+        mesh.add_triangle(face, normals, sphere_uvs(normals));
+    }
+    mesh.build()
+}
+
+/// capped, from y = -height / 2 to height / 2
+pub fn cylinder(radius: f32, height: f32, segments: usize, color: Color) -> Model {
+    let segments = segments.max(3);
+    let mut mesh = MeshBuilder::new(color);
+    mesh.add_grid(segments, 1, |i, j| {
+        let normal = around_y(i as f32 / segments as f32);
+        let y = (j as f32 - 0.5) * height;
+        (normal * radius + DOWN * y, normal)
+    });
+    mesh.add_disc(UP * (height / 2.), UP, radius, segments);
+    mesh.add_disc(DOWN * (height / 2.), DOWN, radius, segments);
+    mesh.build()
+}
+
+/// tip at y = -height / 2, capped base at height / 2
+pub fn cone(radius: f32, height: f32, segments: usize, color: Color) -> Model {
+    let segments = segments.max(3);
+    let mut mesh = MeshBuilder::new(color);
+    mesh.add_grid(segments, 1, |i, j| {
+        let out = around_y(i as f32 / segments as f32);
+        // the side leans in by radius over height
+        let normal = (out * height + UP * radius).normalize();
+        let y = (j as f32 - 0.5) * height;
+        (out * (radius * j as f32) + DOWN * y, normal)
+    });
+    mesh.add_disc(DOWN * (height / 2.), DOWN, radius, segments);
+    mesh.build()
+}
+
+/// ring of radius `major_radius` around the y axis, with a tube of `minor_radius`
+pub fn torus(
+    major_radius: f32,
+    minor_radius: f32,
+    major_segments: usize,
+    minor_segments: usize,
+    color: Color,
+) -> Model {
+    let major_segments = major_segments.max(3);
+    let minor_segments = minor_segments.max(3);
+    let mut mesh = MeshBuilder::new(color);
+    mesh.add_grid(major_segments, minor_segments, |i, j| {
+        let out = around_y(i as f32 / major_segments as f32);
+        let angle = j as f32 / minor_segments as f32 * TAU;
+        let normal = out * angle.cos() + UP * angle.sin();
+        (out * major_radius + normal * minor_radius, normal)
+    });
+    mesh.build()
+}
+
+/// `width` along x by `depth` along z facing up, split into a grid of quads.
+/// The uvs span the whole plane once.
+pub fn plane(
+    width: f32,
+    depth: f32,
+    x_divisions: usize,
+    z_divisions: usize,
+    color: Color,
+) -> Model {
+    let x_divisions = x_divisions.max(1);
+    let z_divisions = z_divisions.max(1);
+    let mut mesh = MeshBuilder::new(color);
+    mesh.add_grid(x_divisions, z_divisions, |i, j| {
+        let x = (i as f32 / x_divisions as f32 - 0.5) * width;
+        let z = (0.5 - j as f32 / z_divisions as f32) * depth;
+        (Vertex3::new(x, 0., z), UP)
+    });
+    mesh.build()
+}
+
+/// Cylinder of `height` with a hemisphere of `radius` on each end, so the total
+/// height is `height + 2 * radius`. `rings` is per hemisphere.
+pub fn capsule(radius: f32, height: f32, segments: usize, rings: usize, color: Color) -> Model {
+    let segments = segments.max(3);
+    let rings = rings.max(1);
+    let mut mesh = MeshBuilder::new(color);
+    // rows 0..=rings are the top cap and the rest the bottom one, the quads
+    // between the two middle rows make up the cylinder
+    mesh.add_grid(segments, 2 * rings + 1, |i, j| {
+        let u = i as f32 / segments as f32;
+        let (angle, offset) = if j <= rings {
+            (j as f32 / rings as f32 * PI / 2., UP * (height / 2.))
+        } else {
+            (
+                (j - 1) as f32 / rings as f32 * PI / 2.,
+                DOWN * (height / 2.),
+            )
+        };
+        let normal = spherical(u, angle);
+        (normal * radius + offset, normal)
+    });
+    mesh.build()
+}
+
+/// unit vector in the xz plane, `u` of 0 to 1 goes once around
+fn around_y(u: f32) -> Vertex3 {
+    let angle = u * TAU;
+    Vertex3::new(angle.cos(), 0., angle.sin())
+}
+
+/// unit vector `polar` radians from the top, `u` of 0 to 1 goes once around
+fn spherical(u: f32, polar: f32) -> Vertex3 {
+    around_y(u) * polar.sin() + UP * polar.cos()
+}
+
+/// Equirectangular uvs for a triangle on the unit sphere. Corners on either side
+/// of the seam are moved past 1 so the triangle doesn't span the whole texture,
+/// and corners on a pole take the u of the rest of the triangle.
+fn sphere_uvs(normals: [Vertex3; 3]) -> [Vec2; 3] {
+    let mut uvs = normals.map(|n| {
+        let u = n.z.atan2(n.x) / TAU;
+        Vec2::new(u.rem_euclid(1.), (-n.y).clamp(-1., 1.).acos() / PI)
+    });
+    let max_u = uvs.iter().map(|uv| uv.x).fold(0., f32::max);
+    for uv in &mut uvs {
+        if max_u - uv.x > 0.5 {
+            uv.x += 1.;
+        }
+    }
+
+    let on_pole = |n: &Vertex3| n.x.abs() < 1e-6 && n.z.abs() < 1e-6;
+    let others: Vec<f32> = (0..3)
+        .filter(|i| !on_pole(&normals[*i]))
+        .map(|i| uvs[i].x)
+        .collect();
+    if !others.is_empty() && others.len() < 3 {
+        let u = others.iter().sum::<f32>() / others.len() as f32;
+        for i in (0..3).filter(|i| on_pole(&normals[*i])) {
+            uvs[i].x = u;
+        }
+    }
+    uvs
+}
+
+fn icosahedron() -> (Vec<Vertex3>, Vec<[usize; 3]>) {
+    let t = (1. + 5f32.sqrt()) / 2.;
+    let points = [
+        [-1., t, 0.],
+        [1., t, 0.],
+        [-1., -t, 0.],
+        [1., -t, 0.],
+        [0., -1., t],
+        [0., 1., t],
+        [0., -1., -t],
+        [0., 1., -t],
+        [t, 0., -1.],
+        [t, 0., 1.],
+        [-t, 0., -1.],
+        [-t, 0., 1.],
+    ]
+    .iter()
+    .map(|p| Vertex3::from(*p).normalize())
+    .collect();
+    let faces = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+    (points, faces)
+}
+
+struct MeshBuilder {
+    verticies: Vec<Vertex3>,
+    triangles: Vec<Triangle>,
+    color: Color,
+}
+
+impl MeshBuilder {
+    fn new(color: Color) -> Self {
+        Self {
+            verticies: vec![],
+            triangles: vec![],
+            color,
+        }
+    }
+
+    /// Winds the triangle so it faces the same way as its normals and drops it
+    /// when it has no area, which happens at poles and tips.
+    fn add_triangle(&mut self, ind: [usize; 3], normals: [Vertex3; 3], uvs: [Vec2; 3]) {
+        let [a, b, c] = ind.map(|i| self.verticies[i]);
+        let (ab, ac) = (b - a, c - a);
+        let face = ab.cross(ac);
+        if face.length_squared() <= 1e-10 * ab.length_squared() * ac.length_squared() {
+            return;
+        }
+
+        let mut t = Triangle::new(ind[0], ind[1], ind[2], self.color)
+            .with_normals(normals)
+            .with_uvs(uvs);
+        if face.dot(normals[0] + normals[1] + normals[2]) < 0. {
+            t.ind.swap(1, 2);
+            t = t
+                .with_normals([normals[0], normals[2], normals[1]])
+                .with_uvs([uvs[0], uvs[2], uvs[1]]);
+        }
+        self.triangles.push(t);
+    }
+
+    /// `cols` by `rows` quads, `point(i, j)` gives the position and normal of
+    /// grid point i, j. Uvs go from 0 to 1 across and down the grid.
+    fn add_grid(
+        &mut self,
+        cols: usize,
+        rows: usize,
+        point: impl Fn(usize, usize) -> (Vertex3, Vertex3),
+    ) {
+        let first = self.verticies.len();
+        let mut normals = vec![];
+        let mut uvs = vec![];
+        for j in 0..=rows {
+            for i in 0..=cols {
+                let (p, n) = point(i, j);
+                self.verticies.push(p);
+                normals.push(n);
+                uvs.push(Vec2::new(i as f32 / cols as f32, j as f32 / rows as f32));
+            }
+        }
+
+        let index = |i: usize, j: usize| j * (cols + 1) + i;
+        for j in 0..rows {
+            for i in 0..cols {
+                let quad = [
+                    index(i, j),
+                    index(i + 1, j),
+                    index(i + 1, j + 1),
+                    index(i, j + 1),
+                ];
+                for [a, b, c] in [[0, 1, 2], [0, 2, 3]] {
+                    let corners = [quad[a], quad[b], quad[c]];
+                    self.add_triangle(
+                        corners.map(|k| first + k),
+                        corners.map(|k| normals[k]),
+                        corners.map(|k| uvs[k]),
+                    );
+                }
+            }
+        }
+    }
+
+    /// flat disc facing `normal`, which has to be UP or DOWN
+    fn add_disc(&mut self, center: Vertex3, normal: Vertex3, radius: f32, segments: usize) {
+        let first = self.verticies.len();
+        self.verticies.push(center);
+        let mut uvs = vec![Vec2::new(0.5, 0.5)];
+        for i in 0..segments {
+            let out = around_y(i as f32 / segments as f32);
+            self.verticies.push(center + out * radius);
+            uvs.push(Vec2::new(0.5 + out.x / 2., 0.5 + out.z / 2.));
+        }
+        for i in 0..segments {
+            let (a, b) = (1 + i, 1 + (i + 1) % segments);
+            self.add_triangle(
+                [first, first + a, first + b],
+                [normal; 3],
+                [uvs[0], uvs[a], uvs[b]],
+            );
+        }
+    }
+
+    fn build(self) -> Model {
+        Model::from_mesh(self.triangles, self.verticies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::WHITE;
+
+    /// every triangle faces away from `inside(centroid)` and agrees with its normals
+    fn assert_outward(model: &Model, inside: impl Fn(Vertex3) -> Vertex3) {
+        assert!(!model.triangles.is_empty());
+        for t in &model.triangles {
+            let [a, b, c] = t.ind.map(|i| model.verticies[i]);
+            let face = (b - a).cross(c - a);
+            let centroid = (a + b + c) / 3.;
+            assert!(
+                face.dot(centroid - inside(centroid)) > 0.,
+                "{:?} faces inwards",
+                t.ind
+            );
+            for n in t.normals.unwrap() {
+                assert!((n.length() - 1.).abs() < 1e-4);
+                assert!(n.dot(face) > 0.);
+            }
+            for uv in t.uvs.unwrap() {
+                assert!((-1e-6..=2.).contains(&uv.x) && (-1e-6..=1. + 1e-6).contains(&uv.y));
+            }
+        }
+    }
+
+    fn assert_bounds(model: &Model, radius: f32) {
+        for v in &model.verticies {
+            assert!(v.distance(model.bounds_center.truncate()) <= model.bounds_radius + 1e-5);
+        }
+        assert!(
+            (model.bounds_radius - radius).abs() < 1e-4,
+            "radius {}",
+            model.bounds_radius
+        );
+    }
+
+    fn origin(_: Vertex3) -> Vertex3 {
+        Vertex3::ZERO
+    }
+
+    #[test]
+    fn spheres() {
+        let uv = uv_sphere(2., 16, 8, WHITE);
+        assert_outward(&uv, origin);
+        assert_bounds(&uv, 2.);
+        // the triangles touching the poles collapse to one each
+        assert_eq!(uv.triangles.len(), 16 * 8 * 2 - 2 * 16);
+
+        for subdivisions in 0..3 {
+            let ico = icosphere(2., subdivisions, WHITE);
+            assert_eq!(ico.triangles.len(), 20 * 4usize.pow(subdivisions as u32));
+            assert_outward(&ico, origin);
+            assert_bounds(&ico, 2.);
+        }
+        assert_eq!(icosphere(1., 2, WHITE).verticies.len(), 162);
+    }
+
+    #[test]
+    fn cylinder_and_cone() {
+        let c = cylinder(1., 4., 12, WHITE);
+        // the caps are flat, so measure from the axis at the same height
+        assert_outward(&c, |p| Vertex3::new(0., p.y * 0.5, 0.));
+        assert_bounds(&c, 5f32.sqrt());
+        assert_eq!(c.triangles.len(), 12 * 2 + 2 * 12);
+
+        let cone = cone(1., 2., 12, WHITE);
+        assert_outward(&cone, |p| Vertex3::new(0., p.y * 0.5, 0.));
+        assert_bounds(&cone, 2f32.sqrt());
+        assert_eq!(cone.triangles.len(), 12 + 12);
+        let tip = cone.verticies.iter().fold(0f32, |top, v| top.min(v.y));
+        assert_eq!(tip, -1.);
+    }
+
+    #[test]
+    fn torus_faces_away_from_the_ring() {
+        let t = torus(2., 0.5, 16, 8, WHITE);
+        assert_outward(&t, |p| Vertex3::new(p.x, 0., p.z).normalize() * 2.);
+        assert_bounds(&t, 2.5);
+        assert_eq!(t.triangles.len(), 16 * 8 * 2);
+    }
+
+    #[test]
+    fn plane_faces_up() {
+        let p = plane(4., 2., 4, 2, WHITE);
+        assert_outward(&p, |c| c + DOWN);
+        assert_eq!(p.triangles.len(), 4 * 2 * 2);
+        assert_bounds(&p, 5f32.sqrt());
+        let uvs: Vec<Vec2> = p.triangles.iter().flat_map(|t| t.uvs.unwrap()).collect();
+        assert!(uvs.contains(&Vec2::new(0., 0.)) && uvs.contains(&Vec2::new(1., 1.)));
+    }
+
+    #[test]
+    fn capsule() {
+        let c = super::capsule(1., 2., 12, 4, WHITE);
+        assert_outward(&c, |p| Vertex3::new(0., p.y.clamp(-1., 1.), 0.));
+        assert_bounds(&c, 2.);
+        let top = c.verticies.iter().fold(0f32, |top, v| top.min(v.y));
+        assert!((top + 2.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sphere_uvs_wrap_across_the_seam() {
+        let n = |angle: f32| Vertex3::new(angle.cos(), 0., angle.sin());
+        let uvs = sphere_uvs([n(-0.1), n(0.1), Vertex3::new(0., -1., 0.)]);
+        assert!(uvs[0].x > 0.9 && uvs[1].x > 1.);
+        assert_eq!(uvs[2].x, (uvs[0].x + uvs[1].x) / 2.);
+        assert_eq!(uvs[2].y, 0.);
+    }
+}
@@ -1,236 +1,402 @@
 use super::{
-    camera::Camera,
+    antialiasing::{AntiAliasing, SampleBuffer},
+    camera::{Camera, Projection},
     data_types::Instance,
     data_types::{Point, Vertex3},
-    data_types::{Model, Triangle, Vertex4},
-    clipping::transform_and_clip,
-    main::{get_canvas_dimensions, get_draw_outline},
-    utils::{self, interpolate, math::INFINITY_F32, mul_color},
+    data_types::{Model, Triangle, Vec2, Vertex4},
+    clipping::{clip_triangle, transform_and_cull},
+    light::{compute_illumination_with, Light},
+    main::RenderContext,
+    shader::{Shader, Uniforms, Varyings, VertexOutput},
+    shadows::{shadow_maps, ShadowMap},
+    coverage::{rasterize_triangle_samples, signed_area, to_fixed, FixedPoint, Rect},
+    culling::{CullStats, Frustum, InstanceBvh},
+    tiles,
+    utils::{self, mul_color},
+    wireframe::{rasterize_line, rasterize_line_anti_aliased},
 };
-use macroquad::{color::Color, texture::Image};
-use std::mem::swap;
-
-pub fn draw_line(image: &mut Image, p0: Point, p1: Point, color: Color) {
-    let mut p0 = p0;
-    let mut p1 = p1;
-
-    let mut x0 = p0.x;
-    let mut y0 = p0.y;
-    let mut x1 = p1.x;
-    let mut y1 = p1.y;
-
-    let dx = x1 - x0;
-    let dy = y1 - y0;
-
-    if utils::math::abs(dx) > utils::math::abs(dy) {
-        // horizontalish
-        if x0 > x1 {
-            swap(&mut p0, &mut p1);
-            x0 = p0.x;
-            y0 = p0.y;
-            x1 = p1.x;
-            y1 = p1.y;
-        }
-        let ys = utils::interpolate(x0, y0 as f32, x1, y1 as f32);
-        let start = x0 as i32;
-        let end = x1 as i32;
-        for x in start..=end {
-            let index: usize = (x as f32 - x0) as usize;
-            let y = ys[index];
-            put_pixel(image, x, y as i32, color);
-        }
-    } else {
-        // verticalish
-        if y0 > y1 {
-            swap(&mut p0, &mut p1);
-            x0 = p0.x;
-            y0 = p0.y;
-            x1 = p1.x;
-            y1 = p1.y;
-        }
-        let xs = utils::interpolate(y0, x0 as f32, y1, x1 as f32);
-        let start = y0 as i32;
-        let end = y1 as i32;
-        for y in start..=end {
-            let index: usize = (y as f32 - y0) as usize;
-            let x = xs[index];
-            put_pixel(image, x as i32, y, color);
-        }
-    }
+use crate::framebuffer::Framebuffer;
+
+fn compute_triangle_normal(v0: Vertex3, v1: Vertex3, v2: Vertex3) -> Vertex3 {
+    let v0_v1 = v1 + (v0 * -1.);
+    let v0_v2 = v2 + (v0 * -1.);
+    v0_v1.cross(v0_v2)
+
 }
 
-fn sorted_vertex_indicies(triangle: &Triangle, projected: &Vec<Point>) -> [usize; 3] {
-    let mut ind = [0, 1, 2];
-    let verticies = [triangle.ind[0], triangle.ind[1], triangle.ind[2]];
-    if projected[verticies[ind[1]]].y < projected[verticies[ind[0]]].y {
-        let swap = ind[0];
-        ind[0] = ind[1];
-        ind[1] = swap;
-    }
+/// How the camera sees the scene: its projection, and the lights moved into camera space.
+pub struct View<'a> {
+    pub projection: &'a Projection,
+    pub lights: &'a [Light],
+    /// one for each light, `None` or missing for lights without shadows
+    pub shadows: &'a [Option<ShadowMap>],
+}
 
-    if projected[verticies[ind[2]]].y < projected[verticies[ind[0]]].y {
-        let swap = ind[0];
-        ind[0] = ind[2];
-        ind[2] = swap;
+impl View<'_> {
+    /// light reaching camera space `vertex`, where the shadow maps don't block it
+    pub fn illumination(&self, vertex: Vertex3, normal: Vertex3, specular: f32) -> f32 {
+        compute_illumination_with(vertex, normal, self.lights, specular, |i| match self.shadows.get(i) {
+            Some(Some(shadow)) => shadow.visibility(vertex, normal),
+            _ => 1.,
+        })
     }
+}
+
+/// Depth buffer for the pixels in `rect`, row major with `samples` values per pixel.
+/// Triangles are only drawn inside `rect`.
+pub struct DepthTarget<'a> {
+    pub rect: Rect,
+    pub samples: usize,
+    pub values: &'a mut [f32],
+}
 
-    if projected[verticies[ind[2]]].y < projected[verticies[ind[1]]].y {
-        let swap = ind[1];
-        ind[1] = ind[2];
-        ind[2] = swap;
+impl<'a> DepthTarget<'a> {
+    /// the depth buffer of the whole canvas, see `RenderContext::depth_buffer`
+    pub fn canvas(ctx: &RenderContext, values: &'a mut [f32]) -> Self {
+        let samples = ctx.anti_aliasing.samples();
+        assert_eq!(values.len(), ctx.width * ctx.height * samples, "the depth buffer needs a value for every sample");
+        Self { rect: Rect::canvas(ctx.width, ctx.height), samples, values }
     }
+}
 
+/// `x`, `y` in pixels from the top left corner of the canvas
+fn depth_offset(depth_buf: &DepthTarget, x: usize, y: usize, sample: usize) -> usize {
+    let rect = depth_buf.rect;
+    ((x - rect.x0) + (rect.x1 - rect.x0) * (y - rect.y0)) * depth_buf.samples + sample
+}
 
-    ind
+fn depth_buffer_is_closer(depth_buf: &DepthTarget, x: usize, y: usize, sample: usize, depth: f32) -> bool {
+    let current = depth_buf.values[depth_offset(depth_buf, x, y, sample)];
+    current < depth
 }
 
-fn compute_triangle_normal(v0: Vertex3, v1: Vertex3, v2: Vertex3) -> Vertex3 {
-    let v0_v1 = v1 + (v0 * -1.);
-    let v0_v2 = v2 + (v0 * -1.);
-    v0_v1.cross(v0_v2)
+fn update_depth_buffer_is_closer(depth_buf: &mut DepthTarget, x: usize, y: usize, sample: usize, depth: f32) -> bool {
+    if depth_buffer_is_closer(depth_buf, x, y, sample, depth) {
+        let offset = depth_offset(depth_buf, x, y, sample);
+        depth_buf.values[offset] = depth;
+        return true;
+    }
 
+    false
 }
 
-fn edge_interpolate(y0: f32, v0: f32, y1: f32, v1: f32, y2: f32, v2: f32) -> (Vec<f32>, Vec<f32>) {
-    let mut v01 = interpolate(y0, v0, y1, v1);
-    let mut v12 = interpolate(y1, v1, y2, v2);
-    let v02 = interpolate(y0, v0, y2, v2);
-    v01.pop();
-    v01.append(&mut v12);
-
-    (v02, v01)
+/// A model in camera space, run through the vertex shader, clipped in clip space and
+/// with the clipped triangles' corners projected onto the canvas. The methods taking
+/// a triangle `t` index the clipped triangles.
+pub struct ProjectedModel {
+    pub model: Model,
+    /// the index in `model.triangles` of each clipped triangle
+    pub sources: Vec<usize>,
+    /// the vertex shader's output for each corner of each clipped triangle
+    pub vertex_outputs: Vec<[VertexOutput; 3]>,
+    /// where those corners are on the canvas
+    pub projected: Vec<[Point; 3]>,
 }
 
-fn update_depth_buffer_is_closer(depth_buf: &mut Vec<f32>, x: isize, y: isize, inv_z: f32) -> bool {
-    let (width, height) = get_canvas_dimensions();
-    let width = width as isize;
-    let height = height as isize;
-    let canv_x = (width / 2) + x;
-    let canv_y = (height / 2) + y  - 1;
+impl ProjectedModel {
+    pub fn new(ctx: &RenderContext, view: &View, model: Model) -> Self {
+        let shader = model.shader.as_deref().unwrap_or(ctx.shading_model.shader());
+        let (mut sources, mut vertex_outputs, mut projected) = (vec![], vec![], vec![]);
+        for (source, t) in model.triangles.iter().enumerate() {
+            let uniforms = uniforms(view, &model, t);
+            let corners = [0, 1, 2].map(|i| shader.vertex(&uniforms, &uniforms.input(i)));
+            clip_triangle(corners, |clipped| {
+                // clipping leaves w positive, unless a shader gave all three corners a w of 0
+                let points = clipped.map(|c| utils::clip_to_canvas(ctx, c.position));
+                if let [Some(a), Some(b), Some(c)] = points {
+                    sources.push(source);
+                    vertex_outputs.push(clipped);
+                    projected.push([a, b, c]);
+                }
+            });
+        }
+        Self { model, sources, vertex_outputs, projected }
+    }
 
-    if canv_x < 0 || canv_x >= width || canv_y < 0 || canv_y >= height {
-        return false;
+    /// how many triangles are left after clipping
+    pub fn triangle_count(&self) -> usize {
+        self.sources.len()
     }
 
-    let offset = (canv_x + (width * canv_y)) as usize;
-    if depth_buf[offset] == INFINITY_F32 || depth_buf[offset] < inv_z {
-        depth_buf[offset] = inv_z;
-        return true; 
+    /// the model triangle clipped triangle `t` is part of
+    pub fn triangle(&self, t: usize) -> &Triangle {
+        &self.model.triangles[self.sources[t]]
     }
 
-    false
-}
+    /// the model's shader, or the built-in one for `ctx.shading_model`
+    pub fn shader<'a>(&'a self, ctx: &RenderContext) -> &'a dyn Shader {
+        self.model.shader.as_deref().unwrap_or(ctx.shading_model.shader())
+    }
 
-fn render_triangle(image: &mut Image, triangle: &Triangle, verticies: &Vec<Vertex3>, projected: &Vec<Point>, depth_buf: &mut Vec<f32>) {
-    let indicies = sorted_vertex_indicies(triangle, projected);
-    let unsorted = triangle.ind;
-    let i0 = indicies[0];
-    let i1 = indicies[1];
-    let i2 = indicies[2];
-
-    let v0 = verticies[triangle.ind[i0]];
-    let v1 = verticies[triangle.ind[i1]];
-    let v2 = verticies[triangle.ind[i2]];
-    
-    let normal = compute_triangle_normal(verticies[unsorted[0]], verticies[unsorted[1]], verticies[unsorted[2]]);
-    let center = (-1. / 3.) * ((verticies[unsorted[0]] + verticies[unsorted[1]]) + verticies[unsorted[2]]);
-    if center.dot(normal) < 0. {
-        return;
+    /// whether triangle `t` faces away from the camera, from its winding on the canvas
+    pub fn is_back_facing(&self, ctx: &RenderContext, t: usize) -> bool {
+        signed_area(self.corners(ctx, t)) > 0
     }
-    
-    // Get attribute values (X, 1/Z) at the vertices.
-    let p0 = projected[unsorted[i0]];
-    let p1 = projected[unsorted[i1]];
-    let p2 = projected[unsorted[i2]];
-
-    // Compute attribute values at the edges.
-    let (x02, x012) = edge_interpolate(p0.y, p0.x, p1.y, p1.x, p2.y, p2.x);
-    let (iz02, iz012) = edge_interpolate(p0.y, 1./v0.z, p1.y, 1./v1.z, p2.y, 1./v2.z);
-
-    // Determine which is left and which is right.
-    let m = x02.len() / 2;
-    let x_left: Vec<f32>;
-    let x_right: Vec<f32>;
-    let iz_left: Vec<f32>;
-    let iz_right: Vec<f32>;
-
-    if &x02[m] < &x012[m] {
-        x_left = x02;
-        x_right = x012;
-        iz_left = iz02;
-        iz_right = iz012;
-    } else {
-        x_left = x012;
-        x_right = x02;
-        iz_left = iz012;
-        iz_right = iz02;
-    }
-
-    let start = p0.y as isize;
-    let end = p2.y as isize;
-
-    // Draw horizontal segments.
-    for y in start..=end {
-        let y_ind = (y - start) as usize;
-        let (xl, xr) = (x_left[y_ind], x_right[y_ind]);
-        let (zl, zr) = (iz_left[y_ind], iz_right[y_ind]);
-        let z_scan = interpolate(xl, zl, xr, zr);
-        let x_start = xl as isize;
-        let x_end = xr as isize;
-
-        for x in x_start..=x_end {
-            let x_ind = (x - x_start) as usize;
-            if update_depth_buffer_is_closer(depth_buf, x, y, z_scan[x_ind]) {
-                put_pixel(image, x as i32, y as i32, triangle.color)
-            }
-        }
+
+    /// triangle `t`'s corners on the canvas in fixed point, for `coverage`
+    pub fn corners(&self, ctx: &RenderContext, t: usize) -> [FixedPoint; 3] {
+        self.projected[t].map(|p| canvas_to_fixed(ctx, p))
     }
 
-    let draw_outline = get_draw_outline();
-    if draw_outline {
-        let outline_color = mul_color(&triangle.color, 0.75);
-        draw_line(image, p0, p1, outline_color);
-        draw_line(image, p0, p2, outline_color);
-        draw_line(image, p2, p1, outline_color);
+    /// Pixels triangle `t` and its edges can touch, a pixel bigger than the
+    /// triangle on every side. `None` when it's off the canvas.
+    pub fn pixel_bounds(&self, ctx: &RenderContext, t: usize) -> Option<Rect> {
+        let points = self.projected[t];
+        let (w, h) = ((ctx.width / 2) as f32, (ctx.height / 2) as f32);
+        let min_x = points.iter().map(|p| p.x).fold(f32::INFINITY, f32::min) + w;
+        let max_x = points.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max) + w;
+        let min_y = points.iter().map(|p| p.y).fold(f32::INFINITY, f32::min) + h;
+        let max_y = points.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max) + h;
+        let clamp = |v: f32, size: usize| (v.max(0.) as usize).min(size);
+        let rect = Rect::new(
+            clamp(min_x.floor() - 1., ctx.width),
+            clamp(min_y.floor() - 1., ctx.height),
+            clamp(max_x.ceil() + 2., ctx.width),
+            clamp(max_y.ceil() + 2., ctx.height),
+        );
+        (rect.x0 < rect.x1 && rect.y0 < rect.y1).then_some(rect)
     }
 }
 
-pub fn render_scene(image: &mut Image, cam: &Camera, instances: &Vec<Instance>, depth_buf: &mut Vec<f32>) {
-    let camera_mat = utils::mul_mm(
-        cam.orientation.transpose(),
-        utils::make_translation_mat(cam.pos * -1.),
-    );
+/// What the shaders see of `triangle` of `model`.
+pub fn uniforms<'a>(view: &'a View<'a>, model: &'a Model, triangle: &'a Triangle) -> Uniforms<'a> {
+    let verticies = triangle.ind.map(|i| model.verticies[i]);
+    let [v0, v1, v2] = verticies;
+    let attributes = &model.attributes;
+    // Use the triangle's normals if it has them, then the vertices', the face normal otherwise.
+    let normals = triangle
+        .normals
+        .or_else(|| attributes.normals.as_ref().map(|normals| triangle.ind.map(|i| normals[i])))
+        .unwrap_or([compute_triangle_normal(v0, v1, v2).normalize(); 3]);
+    let uvs = triangle_uvs(model, triangle);
+    let colors = attributes.colors.as_ref().map_or([triangle.color; 3], |colors| triangle.ind.map(|i| colors[i]));
+    let tangents = attributes.tangents.as_ref().map_or([Vertex4::ZERO; 3], |tangents| triangle.ind.map(|i| tangents[i]));
+    Uniforms { view, triangle, verticies, normals, uvs, colors, tangents, textures: &model.textures }
+}
 
-    for i in instances{
-        let transform = utils::mul_mm(camera_mat, i.transform);
-        if let Some(clipped) = transform_and_clip(&cam.clipping_planes, i.model, transform) {
-            render_model(image, clipped, depth_buf);
-        } 
+/// the triangle's uvs if it has them, then the vertices'
+fn triangle_uvs(model: &Model, triangle: &Triangle) -> Option<[Vec2; 3]> {
+    triangle.uvs.or_else(|| model.attributes.uvs.as_ref().map(|uvs| triangle.ind.map(|i| uvs[i])))
+}
+
+/// canvas point, centered on the middle of the canvas, to pixels from the top left corner
+fn canvas_to_screen(ctx: &RenderContext, p: Point) -> [f32; 2] {
+    [p.x + (ctx.width / 2) as f32, p.y + (ctx.height / 2) as f32]
+}
+
+fn canvas_to_fixed(ctx: &RenderContext, p: Point) -> FixedPoint {
+    let [x, y] = canvas_to_screen(ctx, p);
+    to_fixed(x, y)
+}
+
+/// Barycentric weights of `p` for the triangle `points`, `None` when it has no area.
+fn screen_weights(points: [[f32; 2]; 3], p: [f32; 2]) -> Option<[f32; 3]> {
+    let [a, b, c] = points;
+    let edge = |from: [f32; 2], to: [f32; 2], p: [f32; 2]| (to[0] - from[0]) * (p[1] - from[1]) - (to[1] - from[1]) * (p[0] - from[0]);
+    let area = edge(a, b, c);
+    (area != 0.).then(|| [edge(b, c, p) / area, edge(c, a, p) / area, edge(a, b, p) / area])
+}
+
+/// `weights` times the three corner values
+fn blend<T: Copy + std::ops::Mul<f32, Output = T> + std::ops::Add<Output = T>>(values: [T; 3], weights: [f32; 3]) -> T {
+    values[0] * weights[0] + values[1] * weights[1] + values[2] * weights[2]
+}
+
+/// Fills the part of triangle `t` inside `depth_buf.rect` with the model's shader,
+/// with `ctx.anti_aliasing`'s samples.
+pub fn render_triangle(fb: &mut SampleBuffer, ctx: &RenderContext, model: &ProjectedModel, t: usize, depth_buf: &mut DepthTarget, view: &View) {
+    if model.is_back_facing(ctx, t) {
+        return;
     }
+    let triangle = model.triangle(t);
+    let uniforms = uniforms(view, &model.model, triangle);
+    let shader = model.shader(ctx);
+    let outputs = model.vertex_outputs[t];
+
+    // Depth, z / w, is linear in screen space, so the screen space weights interpolate
+    // it. The varyings are weighted by 1/w as well, which makes them perspective-correct.
+    let depths = outputs.map(|o| o.position.z / o.position.w);
+    let inv_w = outputs.map(|o| 1. / o.position.w);
+    let varyings = outputs.map(|o| o.varyings);
+    let corners = model.corners(ctx, t);
+
+    // screen space weights to the color, `None` when the shader discards it
+    let shade = |weights: [f32; 3]| {
+        let perspective = [0, 1, 2].map(|i| weights[i] * inv_w[i]);
+        let total: f32 = perspective.iter().sum();
+        let weights = perspective.map(|w| w / total);
+        shader.fragment(&uniforms, &Varyings::interpolate(varyings, weights))
+    };
+
+    let multisample = matches!(ctx.anti_aliasing, AntiAliasing::Multisample(_));
+    // transparent triangles are depth tested but don't hide what's drawn after them
+    let opaque = triangle.blend.is_opaque();
+    rasterize_triangle_samples(corners, depth_buf.rect, ctx.anti_aliasing.offsets(), |x, y, covered| {
+        // with multisampling the first visible sample is shaded for all the visible ones
+        let mut shaded = None;
+        for (sample, weights) in covered.iter().enumerate() {
+            let Some(weights) = *weights else { continue };
+            let depth = blend(depths, weights);
+            if !depth_buffer_is_closer(depth_buf, x, y, sample, depth) {
+                continue;
+            }
+            let color = match shaded {
+                Some(color) if multisample => color,
+                _ => *shaded.insert(shade(weights)),
+            };
+            let Some(color) = color else { continue };
+            if opaque {
+                update_depth_buffer_is_closer(depth_buf, x, y, sample, depth);
+            }
+            fb.composite_sample(x, y, sample, color, triangle.blend);
+        }
+    });
 }
 
-pub fn render_model(image: &mut Image, model: Model, depth_buf: &mut Vec<f32>) {
-    let mut projected: Vec<Point> = vec![];
-    for v in &model.verticies {
-        let v = Vertex4::new(v.x, v.y, v.z, 1.);
+/// Draws triangle `t`'s edges inside `depth_buf.rect` in a darker shade of its color.
+/// They're tested against the depth buffer but don't write to it, so they go over
+/// the finished surfaces, see `render_triangles`.
+pub fn render_triangle_edges(fb: &mut SampleBuffer, ctx: &RenderContext, model: &ProjectedModel, t: usize, depth_buf: &DepthTarget, view: &View) {
+    let projection = view.projection;
+    let triangle = model.triangle(t);
+    if model.is_back_facing(ctx, t) {
+        return;
+    }
+    // pulled towards the camera so they aren't hidden by their own triangle
+    let depths = model.vertex_outputs[t].map(|o| projection.depth(projection.depth_to_z(o.position.z / o.position.w) * (1. - ctx.wireframe_bias)));
+    let points = model.projected[t].map(|p| canvas_to_screen(ctx, p));
+    let color = mul_color(&triangle.color, 0.75);
+    for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+        let plot = |x: usize, y: usize, t: f32, coverage| {
+            // the depth of the triangle at the pixel center, like its fill has there,
+            // steep triangles change depth a lot between the line and the center
+            let depth = match screen_weights(points, [x as f32 + 0.5, y as f32 + 0.5]) {
+                Some(weights) => blend(depths, weights),
+                None => depths[a] + (depths[b] - depths[a]) * t,
+            };
+            for sample in 0..depth_buf.samples {
+                if depth_buffer_is_closer(depth_buf, x, y, sample, depth) {
+                    fb.blend_sample(x, y, sample, color, coverage);
+                }
+            }
+        };
+        if ctx.anti_aliased_lines {
+            rasterize_line_anti_aliased(points[a], points[b], depth_buf.rect, plot);
+        } else {
+            rasterize_line(points[a], points[b], depth_buf.rect, plot);
+        }
+    }
+}
 
-        let mut p_v = utils::project_vertex(v);
-        utils::truncate_parts(&mut p_v);
-        projected.push(p_v);
+/// Fills the triangles if `ctx.render_mode` has fills, the opaque ones in order and
+/// then the transparent ones from back to front. Then draws their edges over the
+/// finished depth buffer if it has edges, so later triangles can't cover them up.
+pub fn render_triangles<'a>(
+    fb: &mut SampleBuffer,
+    ctx: &RenderContext,
+    triangles: impl Iterator<Item = (&'a ProjectedModel, usize)> + Clone,
+    depth_buf: &mut DepthTarget,
+    view: &View,
+) {
+    if ctx.render_mode.fills() {
+        let mut transparent = vec![];
+        for (model, t) in triangles.clone() {
+            let triangle = model.triangle(t);
+            if triangle.blend.is_opaque() {
+                render_triangle(fb, ctx, model, t, depth_buf, view);
+            } else {
+                let distance: f32 = triangle.ind.iter().map(|&i| model.model.verticies[i].z).sum();
+                transparent.push((distance, model, t));
+            }
+        }
+        // farthest first, the sort is stable so a tile sorts its share of the
+        // triangles the same way the whole list is sorted
+        transparent.sort_by(|a, b| b.0.total_cmp(&a.0));
+        for (_, model, t) in transparent {
+            render_triangle(fb, ctx, model, t, depth_buf, view);
+        }
     }
-    for t in &model.triangles {
-        render_triangle(image, t, &model.verticies, &projected, depth_buf);
+    if ctx.render_mode.edges() {
+        for (model, t) in triangles {
+            render_triangle_edges(fb, ctx, model, t, depth_buf, view);
+        }
     }
 }
 
-fn put_pixel(image: &mut Image, x: i32, y: i32, color: Color) {
-    let width = image.width();
-    let height = image.height();
-    let (x_mapped, y_mapped) = utils::map_to_pixels(x, y, width, height);
+/// `lights` are in world space, lighting is done in camera space. `depth_buf` needs a
+/// value for every sample, see `RenderContext::depth_buffer`.
+/// With `ctx.threads` above 1 the canvas is rendered in tiles on that many threads,
+/// which gives exactly the same image and depth buffer. With `ctx.shadows` every
+/// light but the ambient ones gets shadow maps of `instances` first.
+pub fn render_scene(fb: &mut impl Framebuffer, ctx: &RenderContext, cam: &Camera, instances: &[Instance], lights: &[Light], depth_buf: &mut [f32]) {
+    render_instances(fb, ctx, cam, instances, None, lights, depth_buf);
+}
 
-    if x_mapped >= width || y_mapped >= height {
-        return;
+/// `render_scene` without the instances `bvh` finds outside the camera's frustum,
+/// they're skipped before any of their vertices are transformed. `bvh` has to be
+/// built from `instances`. The image is the same: the shadow maps are drawn from the
+/// instances `bvh` finds in each light's frustum instead, so instances out of view
+/// still cast shadows into it.
+pub fn render_scene_culled(fb: &mut impl Framebuffer, ctx: &RenderContext, cam: &Camera, instances: &[Instance], bvh: &InstanceBvh, lights: &[Light], depth_buf: &mut [f32]) -> CullStats {
+    assert_eq!(bvh.len(), instances.len(), "the bvh has to be built from the instances");
+    render_instances(fb, ctx, cam, instances, Some(bvh), lights, depth_buf).expect("culled with the bvh")
+}
+
+/// Draws `instances`, only the ones `bvh` finds in the camera's frustum when there's
+/// one, and returns what it culled. See `shadow_maps` for how `bvh` picks the casters.
+fn render_instances(fb: &mut impl Framebuffer, ctx: &RenderContext, cam: &Camera, instances: &[Instance], bvh: Option<&InstanceBvh>, lights: &[Light], depth_buf: &mut [f32]) -> Option<CullStats> {
+    let (visible, stats) = match bvh {
+        Some(bvh) => {
+            let (visible, stats) = bvh.cull(&Frustum::new(cam));
+            (visible, Some(stats))
+        }
+        None => ((0..instances.len()).collect(), None),
+    };
+    let camera_mat = cam.world_to_camera();
+    let shadows = match ctx.shadows {
+        Some(settings) => shadow_maps(lights, instances, bvh, settings, camera_mat),
+        None => vec![],
+    };
+    let lights: Vec<Light> = lights.iter().map(|l| l.transformed(camera_mat)).collect();
+    let view = View { projection: &cam.projection, lights: &lights, shadows: &shadows };
+    let mut depth_buf = DepthTarget::canvas(ctx, depth_buf);
+
+    let models: Vec<ProjectedModel> = visible
+        .iter()
+        .map(|&i| &instances[i])
+        .filter_map(|i| {
+            let transform = utils::mul_mm(camera_mat, i.transform);
+            transform_and_cull(&cam.clipping_planes, i.model, transform)
+        })
+        .map(|m| ProjectedModel::new(ctx, &view, m))
+        .collect();
+    if ctx.threads > 1 {
+        tiles::render_tiled(fb, ctx, &models, &mut depth_buf, &view);
+    } else {
+        let mut samples = SampleBuffer::new(ctx, depth_buf.rect, fb);
+        let triangles = models.iter().flat_map(|m| (0..m.triangle_count()).map(move |t| (m, t)));
+        render_triangles(&mut samples, ctx, triangles, &mut depth_buf, &view);
+        samples.resolve(fb);
     }
+    stats
+}
 
-    image.set_pixel(x_mapped as u32, y_mapped as u32, color);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::GRAY;
+
+    #[test]
+    fn uniforms_take_the_vertex_uvs_unless_the_triangle_has_its_own() {
+        let verticies = vec![Vertex3::new(-1., -1., 4.), Vertex3::new(1., -1., 4.), Vertex3::new(-1., 1., 4.)];
+        let vertex_uvs = vec![Vec2::new(0., 0.), Vec2::new(1., 0.), Vec2::new(0., 1.)];
+        let model = Model::from_mesh(vec![Triangle::new(0, 2, 1, GRAY)], verticies).with_vertex_uvs(vertex_uvs);
+        let camera = Camera::default();
+        let view = View { projection: &camera.projection, lights: &[], shadows: &[] };
+        assert_eq!(uniforms(&view, &model, &model.triangles[0]).uvs, Some([Vec2::new(0., 0.), Vec2::new(0., 1.), Vec2::new(1., 0.)]));
+
+        let mut triangle = model.triangles[0];
+        triangle.uvs = Some([Vec2::ONE; 3]);
+        assert_eq!(uniforms(&view, &model, &triangle).uvs, Some([Vec2::ONE; 3]));
+    }
 }
@@ -0,0 +1,189 @@
+//! Tree of nodes with transforms relative to their parent, for articulated
+//! objects where moving a part should move everything attached to it.
+//! Nodes are stored in the order they were added and a parent always has to
+//! exist before its children, so world transforms come out of a single pass.
+
+use super::{
+    data_types::{Instance, Mat4x4, Model},
+    utils,
+};
+
+/// index of a node in its `SceneGraph`
+pub type NodeId = usize;
+
+#[derive(Debug, Clone)]
+pub struct Node<'a> {
+    /// nodes without a model only group and move their children
+    pub model: Option<&'a Model>,
+    /// relative to the parent, or to the world for root nodes
+    pub local: Mat4x4,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+impl<'a> Node<'a> {
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SceneGraph<'a> {
+    nodes: Vec<Node<'a>>,
+}
+
+impl<'a> SceneGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a node under `parent`, or as a root when it's `None`.
+    /// Panics if `parent` isn't in the graph.
+    pub fn add(
+        &mut self,
+        parent: Option<NodeId>,
+        model: Option<&'a Model>,
+        local: Mat4x4,
+    ) -> NodeId {
+        let id = self.nodes.len();
+        if let Some(parent) = parent {
+            assert!(parent < id, "parent node {} doesn't exist", parent);
+            self.nodes[parent].children.push(id);
+        }
+        self.nodes.push(Node {
+            model,
+            local,
+            parent,
+            children: vec![],
+        });
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node<'a> {
+        &self.nodes[id]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node<'a> {
+        &mut self.nodes[id]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// the node's local transform composed with all of its ancestors'
+    pub fn world_transform(&self, id: NodeId) -> Mat4x4 {
+        let node = &self.nodes[id];
+        match node.parent {
+            Some(parent) => utils::mul_mm(self.world_transform(parent), node.local),
+            None => node.local,
+        }
+    }
+
+    /// world transforms of every node, indexed by `NodeId`
+    pub fn world_transforms(&self) -> Vec<Mat4x4> {
+        let mut world: Vec<Mat4x4> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let transform = match node.parent {
+                Some(parent) => utils::mul_mm(world[parent], node.local),
+                None => node.local,
+            };
+            world.push(transform);
+        }
+        world
+    }
+
+    /// an instance for every node with a model, ready for `render::render_scene`
+    pub fn instances(&self) -> Vec<Instance<'a>> {
+        self.world_transforms()
+            .into_iter()
+            .zip(&self.nodes)
+            .filter_map(|(transform, node)| {
+                node.model
+                    .map(|model| Instance::from_transform(model, transform))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rasterizer::data_types::{Cube, Vertex3, IDEN_4X4};
+
+    fn translation(x: f32, y: f32, z: f32) -> Mat4x4 {
+        utils::make_translation_mat(Vertex3::new(x, y, z))
+    }
+
+    fn origin_of(transform: Mat4x4) -> Vertex3 {
+        transform.transform_point3(Vertex3::ZERO)
+    }
+
+    #[test]
+    fn children_follow_their_parent() {
+        let mut graph = SceneGraph::new();
+        let sun = graph.add(None, None, translation(0., 0., 10.));
+        let earth = graph.add(Some(sun), None, translation(4., 0., 0.));
+        let moon = graph.add(Some(earth), None, translation(1., 0., 0.));
+        assert_eq!(graph.node(sun).children(), &[earth]);
+        assert_eq!(graph.node(moon).parent(), Some(earth));
+        assert_eq!(
+            origin_of(graph.world_transform(moon)),
+            Vertex3::new(5., 0., 10.)
+        );
+
+        // a quarter turn of the sun carries the earth and the moon around it
+        graph.node_mut(sun).local =
+            utils::make_transform_mat(Vertex3::new(0., 0., 10.), utils::make_rotation_mat(90.), Vertex3::ONE);
+        let world = graph.world_transforms();
+        assert!(origin_of(world[earth]).abs_diff_eq(Vertex3::new(0., 0., 14.), 1e-5));
+        assert!(origin_of(world[moon]).abs_diff_eq(Vertex3::new(0., 0., 15.), 1e-5));
+        for (id, transform) in world.iter().enumerate() {
+            assert!(transform.abs_diff_eq(graph.world_transform(id), 1e-6));
+        }
+    }
+
+    #[test]
+    fn parent_scale_applies_to_children() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add(
+            None,
+            None,
+            utils::make_transform_mat(Vertex3::ZERO, IDEN_4X4, Vertex3::splat(2.)),
+        );
+        let child = graph.add(Some(root), None, translation(1., 0., 0.));
+        assert_eq!(
+            origin_of(graph.world_transform(child)),
+            Vertex3::new(2., 0., 0.)
+        );
+    }
+
+    #[test]
+    fn instances_only_for_nodes_with_models() {
+        let cube = Model::from(&Cube::debug_cube());
+        let mut graph = SceneGraph::new();
+        let group = graph.add(None, None, translation(0., 0., 5.));
+        graph.add(Some(group), Some(&cube), translation(1., 0., 0.));
+        graph.add(Some(group), Some(&cube), translation(-1., 0., 0.));
+
+        let instances = graph.instances();
+        assert_eq!(instances.len(), 2);
+        assert_eq!(instances[0].position, Vertex3::new(1., 0., 5.));
+        assert_eq!(instances[1].transform, translation(-1., 0., 5.));
+    }
+
+    #[test]
+    #[should_panic(expected = "parent node 3 doesn't exist")]
+    fn parent_must_exist() {
+        let mut graph = SceneGraph::new();
+        graph.add(Some(3), None, IDEN_4X4);
+    }
+}
@@ -0,0 +1,271 @@
+//! Programmable shading. The vertex function runs for each corner of each triangle
+//! and gives its clip space position and the values to interpolate across the
+//! triangle, the varyings. The fragment function gets the varyings interpolated to a
+//! sample, perspective-correct, and returns its color, or `None` to discard it and
+//! leave the sample and its depth as they were. The built-in shaders are the
+//! `ShadingModel`s and an unlit `Textured` one. A model can bring its own with
+//! `Model::with_shader`.
+
+use super::{
+    data_types::{Triangle, Vec2, Vertex3, Vertex4},
+    light::ShadingModel,
+    render::View,
+    texture::Texture,
+    utils::mul_color,
+};
+use crate::color::Color;
+use std::{fmt, sync::Arc};
+
+/// how many values a vertex can pass on to the fragments
+pub const MAX_VARYINGS: usize = 12;
+
+/// Values interpolated across a triangle, the ones a shader doesn't use stay 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Varyings(pub [f32; MAX_VARYINGS]);
+
+impl Varyings {
+    /// `values` followed by zeros
+    pub fn new(values: &[f32]) -> Self {
+        let mut varyings = [0.; MAX_VARYINGS];
+        varyings[..values.len()].copy_from_slice(values);
+        Self(varyings)
+    }
+
+    pub fn get(&self, i: usize) -> f32 {
+        self.0[i]
+    }
+
+    /// the two values starting at `i`
+    pub fn vec2(&self, i: usize) -> Vec2 {
+        Vec2::new(self.0[i], self.0[i + 1])
+    }
+
+    /// the three values starting at `i`
+    pub fn vec3(&self, i: usize) -> Vertex3 {
+        Vertex3::new(self.0[i], self.0[i + 1], self.0[i + 2])
+    }
+
+    /// the four values starting at `i` as a color
+    pub fn color(&self, i: usize) -> Color {
+        Color::new(self.0[i], self.0[i + 1], self.0[i + 2], self.0[i + 3])
+    }
+
+    /// `t` of the way from `a` to `b`, values that are the same in both stay exact
+    pub fn lerp(a: Varyings, b: Varyings, t: f32) -> Self {
+        Self(std::array::from_fn(|i| a.0[i] + (b.0[i] - a.0[i]) * t))
+    }
+
+    /// `weights` times the three corners' values. Values that are the same at every
+    /// corner come out exactly the same, like a flat shaded triangle's intensity.
+    pub fn interpolate(corners: [Varyings; 3], weights: [f32; 3]) -> Self {
+        let [a, b, c] = corners.map(|v| v.0);
+        Self(std::array::from_fn(|i| {
+            if a[i] == b[i] && b[i] == c[i] {
+                a[i]
+            } else {
+                a[i] * weights[0] + b[i] * weights[1] + c[i] * weights[2]
+            }
+        }))
+    }
+}
+
+/// One corner of a triangle, in camera space.
+#[derive(Debug, Clone, Copy)]
+pub struct VertexInput {
+    pub position: Vertex3,
+    pub normal: Vertex3,
+    /// zero when the triangle has no uvs
+    pub uv: Vec2,
+    /// the vertex color, or the triangle's
+    pub color: Color,
+    /// zero when the model has no tangents
+    pub tangent: Vertex4,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct VertexOutput {
+    /// Homogeneous clip space position, divided by `w` it's on the viewport.
+    /// See `Projection::to_clip`.
+    pub position: Vertex4,
+    pub varyings: Varyings,
+}
+
+/// What the shaders see besides their inputs, the same for the whole triangle.
+pub struct Uniforms<'a> {
+    pub view: &'a View<'a>,
+    pub triangle: &'a Triangle,
+    /// camera space corners
+    pub verticies: [Vertex3; 3],
+    /// the triangle's normals, or the vertices', or its face normal at every corner
+    pub normals: [Vertex3; 3],
+    /// the triangle's uvs, or the vertices'
+    pub uvs: Option<[Vec2; 3]>,
+    /// the vertex colors, or the triangle's color at every corner
+    pub colors: [Color; 3],
+    /// the vertex tangents, zero when the model has none
+    pub tangents: [Vertex4; 3],
+    /// the model's textures, `triangle.texture` indexes them
+    pub textures: &'a [Arc<Texture>],
+}
+
+impl Uniforms<'_> {
+    /// the vertex function's input for corner `i`
+    pub fn input(&self, i: usize) -> VertexInput {
+        VertexInput {
+            position: self.verticies[i],
+            normal: self.normals[i],
+            uv: self.uvs.map_or(Vec2::ZERO, |uvs| uvs[i]),
+            color: self.colors[i],
+            tangent: self.tangents[i],
+        }
+    }
+
+    /// the triangle's texture at `uv`, or `color` when it isn't textured
+    pub fn color_at(&self, uv: Vec2, color: Color) -> Color {
+        match (self.triangle.texture, self.uvs) {
+            (Some(texture), Some(_)) => self.textures[texture].sample(uv.x, uv.y),
+            _ => color,
+        }
+    }
+}
+
+pub trait Shader: Send + Sync + fmt::Debug {
+    fn vertex(&self, uniforms: &Uniforms, input: &VertexInput) -> VertexOutput;
+
+    /// the color of a sample, `None` discards it
+    fn fragment(&self, uniforms: &Uniforms, varyings: &Varyings) -> Option<Color>;
+}
+
+/// One intensity for the whole triangle, lit at its center.
+/// Varyings: intensity, uv, color.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Flat;
+
+impl Shader for Flat {
+    fn vertex(&self, uniforms: &Uniforms, input: &VertexInput) -> VertexOutput {
+        let (uv, c) = (input.uv, input.color);
+        let [v0, v1, v2] = uniforms.verticies;
+        let center = (1. / 3.) * ((v0 + v1) + v2);
+        let [n0, n1, n2] = uniforms.normals;
+        let intensity =
+            uniforms
+                .view
+                .illumination(center, n0 + n1 + n2, uniforms.triangle.specular);
+        VertexOutput {
+            position: uniforms.view.projection.to_clip(input.position),
+            varyings: Varyings::new(&[intensity, uv.x, uv.y, c.r, c.g, c.b, c.a]),
+        }
+    }
+
+    fn fragment(&self, uniforms: &Uniforms, varyings: &Varyings) -> Option<Color> {
+        Some(mul_color(
+            &uniforms.color_at(varyings.vec2(1), varyings.color(3)),
+            varyings.get(0),
+        ))
+    }
+}
+
+/// Lit at the corners, the intensities are interpolated.
+/// Varyings: intensity, uv, color.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gouraud;
+
+impl Shader for Gouraud {
+    fn vertex(&self, uniforms: &Uniforms, input: &VertexInput) -> VertexOutput {
+        let (uv, c) = (input.uv, input.color);
+        let intensity =
+            uniforms
+                .view
+                .illumination(input.position, input.normal, uniforms.triangle.specular);
+        VertexOutput {
+            position: uniforms.view.projection.to_clip(input.position),
+            varyings: Varyings::new(&[intensity, uv.x, uv.y, c.r, c.g, c.b, c.a]),
+        }
+    }
+
+    fn fragment(&self, uniforms: &Uniforms, varyings: &Varyings) -> Option<Color> {
+        Some(mul_color(
+            &uniforms.color_at(varyings.vec2(1), varyings.color(3)),
+            varyings.get(0),
+        ))
+    }
+}
+
+/// Position and normal interpolated and lit at every sample.
+/// Varyings: position, normal, uv, color.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Phong;
+
+impl Shader for Phong {
+    fn vertex(&self, uniforms: &Uniforms, input: &VertexInput) -> VertexOutput {
+        let (p, n, uv, c) = (input.position, input.normal, input.uv, input.color);
+        VertexOutput {
+            position: uniforms.view.projection.to_clip(p),
+            varyings: Varyings::new(&[
+                p.x, p.y, p.z, n.x, n.y, n.z, uv.x, uv.y, c.r, c.g, c.b, c.a,
+            ]),
+        }
+    }
+
+    fn fragment(&self, uniforms: &Uniforms, varyings: &Varyings) -> Option<Color> {
+        let intensity = uniforms.view.illumination(
+            varyings.vec3(0),
+            varyings.vec3(3),
+            uniforms.triangle.specular,
+        );
+        Some(mul_color(
+            &uniforms.color_at(varyings.vec2(6), varyings.color(8)),
+            intensity,
+        ))
+    }
+}
+
+/// The texture or color as it is, without lighting, for overlays and backdrops.
+/// Varyings: uv, color.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Textured;
+
+impl Shader for Textured {
+    fn vertex(&self, uniforms: &Uniforms, input: &VertexInput) -> VertexOutput {
+        let (uv, c) = (input.uv, input.color);
+        VertexOutput {
+            position: uniforms.view.projection.to_clip(input.position),
+            varyings: Varyings::new(&[uv.x, uv.y, c.r, c.g, c.b, c.a]),
+        }
+    }
+
+    fn fragment(&self, uniforms: &Uniforms, varyings: &Varyings) -> Option<Color> {
+        Some(uniforms.color_at(varyings.vec2(0), varyings.color(2)))
+    }
+}
+
+impl ShadingModel {
+    /// the built-in shader for the shading model
+    pub fn shader(self) -> &'static dyn Shader {
+        match self {
+            ShadingModel::Flat => &Flat,
+            ShadingModel::Gouraud => &Gouraud,
+            ShadingModel::Phong => &Phong,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varyings_interpolate_component_wise() {
+        let a = Varyings::new(&[1., 2., 3.]);
+        let b = Varyings::new(&[3., 2., 1., 4.]);
+        let c = Varyings::new(&[2., 2., 2.]);
+        let mixed = Varyings::interpolate([a, b, c], [0.25, 0.25, 0.5]);
+        assert_eq!(mixed.vec3(0), Vertex3::splat(2.));
+        assert_eq!(mixed.get(3), 1.);
+        assert_eq!(mixed.vec2(4), Vec2::ZERO);
+        // a value that's the same everywhere stays exact
+        let third = 1. / 3.;
+        let flat = Varyings::new(&[0.1]);
+        assert_eq!(Varyings::interpolate([flat; 3], [third; 3]).get(0), 0.1);
+    }
+}
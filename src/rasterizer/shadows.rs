@@ -0,0 +1,463 @@
+//! Shadow maps. Before the view is drawn, the scene's depth is rendered from every
+//! directional and spot light, and in six directions around every point light, a
+//! cube map. A surface is in shadow when the map has something closer to the light
+//! than it. Surfaces are pulled towards the light by a bias before the test so they
+//! don't shadow themselves, and percentage-closer filtering averages the test over
+//! the texels around the surface, which softens the edges of the shadows.
+
+use super::{
+    camera::{Camera, Projection},
+    clipping::transform_and_cull,
+    coverage::{rasterize_triangle, Rect},
+    culling::{Frustum, InstanceBvh},
+    data_types::{Instance, Mat4x4, Vertex3},
+    light::{Light, LightType},
+    main::RenderContext,
+    render::{ProjectedModel, View},
+    utils,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSettings {
+    /// width and height of the maps in texels
+    pub resolution: usize,
+    /// how far surfaces are pulled towards the light, in world units
+    pub bias: f32,
+    /// extra bias for surfaces at an angle to the light, in texels of depth they
+    /// change over, so slopes don't shadow themselves in stripes
+    pub slope_bias: f32,
+    /// texels on each side averaged by percentage-closer filtering, 0 for hard shadows
+    pub pcf_radius: usize,
+}
+
+impl Default for ShadowSettings {
+    /// 512 texels with a 3x3 filter
+    fn default() -> Self {
+        Self {
+            resolution: 512,
+            bias: 0.02,
+            slope_bias: 1.,
+            pcf_radius: 1,
+        }
+    }
+}
+
+impl ShadowSettings {
+    pub fn with_resolution(self, resolution: usize) -> Self {
+        Self {
+            resolution: resolution.max(1),
+            ..self
+        }
+    }
+
+    pub fn with_bias(self, bias: f32, slope_bias: f32) -> Self {
+        Self {
+            bias,
+            slope_bias,
+            ..self
+        }
+    }
+
+    pub fn with_pcf_radius(self, pcf_radius: usize) -> Self {
+        Self { pcf_radius, ..self }
+    }
+}
+
+/// The scene's depth seen through `camera`, which is placed at the light.
+pub struct DepthMap {
+    pub camera: Camera,
+    /// `resolution` texels square
+    ctx: RenderContext,
+    /// row major like the depth buffer, larger is closer to the light
+    pub values: Vec<f32>,
+}
+
+impl DepthMap {
+    /// Renders the opaque triangles of `instances`, both sides of them.
+    pub fn render<'a, 'm: 'a>(
+        camera: Camera,
+        resolution: usize,
+        instances: impl IntoIterator<Item = &'a Instance<'m>>,
+    ) -> Self {
+        let ctx = RenderContext::new(resolution, resolution);
+        let projection = &camera.projection;
+        let view = View {
+            projection,
+            lights: &[],
+            shadows: &[],
+        };
+        let world_to_light = camera.world_to_camera();
+        let mut values = vec![0.; resolution * resolution];
+        for instance in instances {
+            let transform = utils::mul_mm(world_to_light, instance.transform);
+            let Some(model) =
+                transform_and_cull(&camera.clipping_planes, instance.model, transform)
+            else {
+                continue;
+            };
+            let model = ProjectedModel::new(&ctx, &view, model);
+            for t in 0..model.triangle_count() {
+                if !model.triangle(t).blend.is_opaque() {
+                    continue;
+                }
+                let depths = model.vertex_outputs[t].map(|o| o.position.z / o.position.w);
+                let corners = model.corners(&ctx, t);
+                rasterize_triangle(
+                    corners,
+                    Rect::canvas(resolution, resolution),
+                    |x, y, weights| {
+                        let depth = depths[0] * weights[0]
+                            + depths[1] * weights[1]
+                            + depths[2] * weights[2];
+                        let value = &mut values[x + resolution * y];
+                        if *value < depth {
+                            *value = depth;
+                        }
+                    },
+                );
+            }
+        }
+        Self {
+            camera,
+            ctx,
+            values,
+        }
+    }
+
+    /// width of a texel at light space distance `z`
+    fn texel_size(&self, z: f32) -> f32 {
+        let (_, height) = self.camera.projection.view_size();
+        let size = height / self.ctx.height as f32;
+        match self.camera.projection {
+            Projection::Perspective { .. } => size * z,
+            Projection::Orthographic { .. } => size,
+        }
+    }
+
+    /// Fraction of the texels within `radius` of light space point `p` that have
+    /// nothing closer to the light than `p` moved `bias` towards it. Points outside
+    /// the map are lit.
+    pub fn lit_fraction(&self, p: Vertex3, bias: f32, radius: usize) -> f32 {
+        let projection = &self.camera.projection;
+        let Some(canvas) = utils::project_vertex(&self.ctx, projection, p.extend(1.)) else {
+            return 1.;
+        };
+        let size = self.ctx.width as i64;
+        let x = (canvas.x + (self.ctx.width / 2) as f32).floor() as i64;
+        let y = (canvas.y + (self.ctx.height / 2) as f32).floor() as i64;
+        if !(0..size).contains(&x) || !(0..size).contains(&y) {
+            return 1.;
+        }
+        let depth = projection.depth((p.z - bias).max(f32::EPSILON));
+        let r = radius as i64;
+        let mut lit = 0;
+        for dy in -r..=r {
+            for dx in -r..=r {
+                let tx = (x + dx).clamp(0, size - 1);
+                let ty = (y + dy).clamp(0, size - 1);
+                let stored = self.values[(tx + size * ty) as usize];
+                if stored <= depth {
+                    lit += 1;
+                }
+            }
+        }
+        lit as f32 / ((2 * r + 1) * (2 * r + 1)) as f32
+    }
+}
+
+/// Shadows of one light, for the camera space a view is drawn in.
+pub struct ShadowMap {
+    settings: ShadowSettings,
+    /// one for directional and spot lights, six for point lights
+    faces: Vec<DepthMap>,
+    /// from the view's camera space to each face's light space
+    to_light: Vec<Mat4x4>,
+}
+
+impl ShadowMap {
+    /// Maps for `light`, which is in world space, seeing `instances`. `world_to_camera`
+    /// is the view's, see `Camera::world_to_camera`. `None` for ambient lights.
+    /// With `bvh`, built from `instances`, only the instances it finds in the frustum
+    /// of each of the light's cameras are drawn into the maps.
+    pub fn new(
+        light: &Light,
+        instances: &[Instance],
+        bvh: Option<&InstanceBvh>,
+        settings: ShadowSettings,
+        world_to_camera: Mat4x4,
+    ) -> Option<Self> {
+        let (center, radius) = scene_bounds(instances);
+        let radius = radius.max(1e-3);
+        let cameras = match light.light_type {
+            LightType::Ambient => return None,
+            LightType::Directional => {
+                let towards = light
+                    .direction
+                    .expect("Directional light without direction")
+                    .normalize();
+                // far enough out to see the whole scene in front of it
+                let position = center + towards * (radius + 1.);
+                let size = 2. * radius;
+                vec![
+                    Camera::new(position, look_along(-towards)).with_orthographic(
+                        size,
+                        size,
+                        0.5,
+                        size + 1.5,
+                    ),
+                ]
+            }
+            LightType::Spot => {
+                let position = light.position.expect("Spot light without position");
+                let towards = light.direction.expect("Spot light without direction");
+                let (near, far) = light_range(position, center, radius);
+                let fov = (2. * light.spot_angle).min(170.);
+                vec![Camera::new(position, look_along(-towards))
+                    .with_perspective(fov, 1., near, far)]
+            }
+            LightType::Point => {
+                let position = light.position.expect("Point light without position");
+                let (near, far) = light_range(position, center, radius);
+                [
+                    Vertex3::X,
+                    -Vertex3::X,
+                    Vertex3::Y,
+                    -Vertex3::Y,
+                    Vertex3::Z,
+                    -Vertex3::Z,
+                ]
+                .iter()
+                .map(|axis| {
+                    Camera::new(position, look_along(*axis)).with_perspective(90., 1., near, far)
+                })
+                .collect()
+            }
+        };
+        let camera_to_world = world_to_camera.inverse();
+        let to_light = cameras
+            .iter()
+            .map(|c| utils::mul_mm(c.world_to_camera(), camera_to_world))
+            .collect();
+        let faces = cameras
+            .into_iter()
+            .map(|c| match bvh {
+                Some(bvh) => {
+                    let (casters, _) = bvh.cull(&Frustum::new(&c));
+                    let casters = casters.iter().map(|&i| &instances[i]);
+                    DepthMap::render(c, settings.resolution, casters)
+                }
+                None => DepthMap::render(c, settings.resolution, instances),
+            })
+            .collect();
+        Some(Self {
+            settings,
+            faces,
+            to_light,
+        })
+    }
+
+    /// How much of the light reaches camera space `p` on a surface facing `normal`,
+    /// from 0 in full shadow to 1.
+    pub fn visibility(&self, p: Vertex3, normal: Vertex3) -> f32 {
+        // the face the point is most in front of, the only one for most lights
+        let Some((face, to_light, q)) = self
+            .faces
+            .iter()
+            .zip(&self.to_light)
+            .map(|(face, to_light)| (face, to_light, to_light.transform_point3(p)))
+            .max_by(|a, b| a.2.z.total_cmp(&b.2.z))
+        else {
+            return 1.;
+        };
+        // surfaces at a grazing angle change depth quickly from texel to texel,
+        // and the filter compares texels up to its radius away
+        let normal = to_light.transform_vector3(normal).normalize_or_zero();
+        let towards = face.camera.projection.to_camera(q).normalize_or_zero();
+        let cos = normal.dot(towards).abs().max(0.1);
+        let tan = (1. - cos * cos).sqrt() / cos;
+        let settings = &self.settings;
+        let texels = (settings.pcf_radius + 1) as f32;
+        let bias = settings.bias + settings.slope_bias * face.texel_size(q.z) * tan * texels;
+        face.lit_fraction(q, bias, settings.pcf_radius)
+    }
+}
+
+/// Shadow maps for each of `lights`, in world space, `None` for the ones without.
+/// See `ShadowMap::new` for `bvh`.
+pub fn shadow_maps(
+    lights: &[Light],
+    instances: &[Instance],
+    bvh: Option<&InstanceBvh>,
+    settings: ShadowSettings,
+    world_to_camera: Mat4x4,
+) -> Vec<Option<ShadowMap>> {
+    lights
+        .iter()
+        .map(|light| ShadowMap::new(light, instances, bvh, settings, world_to_camera))
+        .collect()
+}
+
+/// A rotation pointing the camera's z axis along `forward`.
+fn look_along(forward: Vertex3) -> Mat4x4 {
+    let z = forward.normalize();
+    let up = if z.y.abs() > 0.99 {
+        Vertex3::Z
+    } else {
+        Vertex3::Y
+    };
+    let x = up.cross(z).normalize();
+    let y = z.cross(x);
+    Mat4x4::from_cols(
+        x.extend(0.),
+        y.extend(0.),
+        z.extend(0.),
+        Vertex3::ZERO.extend(1.),
+    )
+}
+
+/// near and far planes for a light at `position` that reach the whole scene
+fn light_range(position: Vertex3, center: Vertex3, radius: f32) -> (f32, f32) {
+    let near = 0.05;
+    (near, (position.distance(center) + radius).max(2. * near))
+}
+
+/// World space bounding sphere of all the instances.
+fn scene_bounds(instances: &[Instance]) -> (Vertex3, f32) {
+    let spheres: Vec<(Vertex3, f32)> = instances
+        .iter()
+        .map(|i| {
+            let center = i
+                .transform
+                .transform_point3(i.model.bounds_center.truncate());
+            (center, i.bounding_radius())
+        })
+        .collect();
+    let Some(&(first, _)) = spheres.first() else {
+        return (Vertex3::ZERO, 0.);
+    };
+    let (min, max) = spheres.iter().fold((first, first), |(min, max), (c, r)| {
+        (
+            min.min(*c - Vertex3::splat(*r)),
+            max.max(*c + Vertex3::splat(*r)),
+        )
+    });
+    let center = (min + max) * 0.5;
+    let radius = spheres
+        .iter()
+        .map(|(c, r)| c.distance(center) + r)
+        .fold(0., f32::max);
+    (center, radius)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rasterizer::{color::RED, data_types::Model, primitives};
+
+    fn settings() -> ShadowSettings {
+        ShadowSettings::default().with_resolution(64)
+    }
+
+    #[test]
+    fn look_along_points_the_camera() {
+        for forward in [
+            Vertex3::new(1., 2., 3.),
+            Vertex3::Y,
+            -Vertex3::Y,
+            -Vertex3::Z,
+        ] {
+            let camera = Camera::new(Vertex3::ZERO, look_along(forward));
+            let ahead = camera
+                .world_to_camera()
+                .transform_point3(forward.normalize());
+            assert!(ahead.abs_diff_eq(Vertex3::Z, 1e-5));
+        }
+    }
+
+    #[test]
+    fn directional_light_shadows_what_is_behind() {
+        // a small box over the middle of a floor, lit from straight above
+        let floor = primitives::plane(10., 10., 1, 1, RED);
+        let cube = Model::from(&crate::rasterizer::data_types::Cube::debug_cube());
+        let instances = vec![
+            Instance::new(&floor, Vertex3::new(0., 1., 0.), None, None),
+            Instance::new(&cube, Vertex3::new(0., -1., 0.), None, Some(0.5)),
+        ];
+        let above = Light::new(
+            LightType::Directional,
+            1.,
+            None,
+            Some(Vertex3::new(0., -1., 0.)),
+        );
+        let map = ShadowMap::new(&above, &instances, None, settings(), Mat4x4::IDENTITY).unwrap();
+        let up = -Vertex3::Y;
+        assert_eq!(map.visibility(Vertex3::new(0., 1., 0.), up), 0.);
+        assert_eq!(map.visibility(Vertex3::new(3., 1., 2.), up), 1.);
+        // the lit top of the box doesn't shadow itself
+        assert_eq!(map.visibility(Vertex3::new(0.2, -1.5, 0.1), up), 1.);
+        // the filter softens the edge of the shadow
+        let edge = map.visibility(Vertex3::new(0.5, 1., 0.), up);
+        assert!(edge > 0. && edge < 1.);
+        let hard = ShadowMap::new(
+            &above,
+            &instances,
+            None,
+            settings().with_pcf_radius(0),
+            Mat4x4::IDENTITY,
+        )
+        .unwrap();
+        let edge = hard.visibility(Vertex3::new(0.5, 1., 0.), up);
+        assert!(edge == 0. || edge == 1.);
+
+        assert!(ShadowMap::new(
+            &Light::new(LightType::Ambient, 1., None, None),
+            &instances,
+            None,
+            settings(),
+            Mat4x4::IDENTITY
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn point_lights_cast_shadows_every_way() {
+        // boxes on either side of the light, and the view camera moved away
+        let cube = Model::from(&crate::rasterizer::data_types::Cube::debug_cube());
+        let instances = vec![
+            Instance::new(&cube, Vertex3::new(3., 0., 0.), None, Some(0.5)),
+            Instance::new(&cube, Vertex3::new(0., 0., -3.), None, Some(0.5)),
+            Instance::new(&cube, Vertex3::new(0., 0., 8.), None, Some(0.5)),
+        ];
+        let light = Light::new(LightType::Point, 1., Some(Vertex3::ZERO), None);
+        let world_to_camera = Mat4x4::from_translation(Vertex3::new(0., 0., 5.));
+        let map = ShadowMap::new(&light, &instances, None, settings(), world_to_camera).unwrap();
+        let visibility = |world: Vertex3| {
+            let normal = world_to_camera.transform_vector3(-world.normalize());
+            map.visibility(world_to_camera.transform_point3(world), normal)
+        };
+        assert_eq!(visibility(Vertex3::new(6., 0., 0.)), 0.);
+        assert_eq!(visibility(Vertex3::new(0., 0., -6.)), 0.);
+        assert_eq!(visibility(Vertex3::new(-6., 0., 0.)), 1.);
+        assert_eq!(visibility(Vertex3::new(0., 6., 0.)), 1.);
+        // in front of the box, not behind it
+        assert_eq!(visibility(Vertex3::new(0., 0., 6.)), 1.);
+    }
+
+    #[test]
+    fn spot_lights_only_map_their_cone() {
+        let cube = Model::from(&crate::rasterizer::data_types::Cube::debug_cube());
+        let instances = vec![Instance::new(
+            &cube,
+            Vertex3::new(0., 0., 3.),
+            None,
+            Some(0.5),
+        )];
+        let spot = Light::new(LightType::Spot, 1., Some(Vertex3::ZERO), Some(-Vertex3::Z))
+            .with_spot_angle(20.);
+        let map = ShadowMap::new(&spot, &instances, None, settings(), Mat4x4::IDENTITY).unwrap();
+        assert_eq!(map.faces.len(), 1);
+        assert_eq!(map.visibility(Vertex3::new(0., 0., 6.), -Vertex3::Z), 0.);
+        // outside the cone the map has nothing to say
+        assert_eq!(map.visibility(Vertex3::new(6., 0., 0.), -Vertex3::X), 1.);
+    }
+}
@@ -0,0 +1,190 @@
+use super::utils::math;
+use crate::color::Color;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Filter {
+    /// the texel the sample falls in
+    Nearest,
+    /// blend of the four closest texels
+    #[default]
+    Bilinear,
+}
+
+/// What happens to coordinates outside 0..1
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Wrap {
+    /// the texture tiles
+    #[default]
+    Repeat,
+    /// the edge texels are stretched out
+    Clamp,
+}
+
+/// Row major image sampled with UV coordinates, (0, 0) is the top left corner
+#[derive(Debug, Clone)]
+pub struct Texture {
+    pub width: usize,
+    pub height: usize,
+    pub texels: Vec<Color>,
+    pub filter: Filter,
+    pub wrap: Wrap,
+}
+
+impl Texture {
+    /// panics when `texels` doesn't hold `width * height` colors
+    pub fn new(width: usize, height: usize, texels: Vec<Color>) -> Self {
+        assert!(width > 0 && height > 0, "empty texture");
+        assert_eq!(texels.len(), width * height, "texel count doesn't match the size");
+        Self {
+            width,
+            height,
+            texels,
+            filter: Filter::default(),
+            wrap: Wrap::default(),
+        }
+    }
+
+    pub fn with_filter(self, filter: Filter) -> Self {
+        Self { filter, ..self }
+    }
+
+    pub fn with_wrap(self, wrap: Wrap) -> Self {
+        Self { wrap, ..self }
+    }
+
+    /// `cells` by `cells` squares alternating between `a` and `b`
+    pub fn checkerboard(size: usize, cells: usize, a: Color, b: Color) -> Self {
+        let cell = (size / cells.max(1)).max(1);
+        let texels = (0..size * size)
+            .map(|i| {
+                let (x, y) = (i % size, i / size);
+                if (x / cell + y / cell).is_multiple_of(2) {
+                    a
+                } else {
+                    b
+                }
+            })
+            .collect();
+        Self::new(size, size, texels)
+    }
+
+    /// wooden crate side: planks inside a frame, with a diagonal brace
+    pub fn crate_side(size: usize) -> Self {
+        let wood = Color::new(0.72, 0.5, 0.28, 1.);
+        let frame = Color::new(0.5, 0.33, 0.17, 1.);
+        let seam = Color::new(0.3, 0.2, 0.1, 1.);
+        let border = (size / 8).max(1);
+        let plank = (size / 4).max(1);
+
+        let texels = (0..size * size)
+            .map(|i| {
+                let (x, y) = (i % size, i / size);
+                let edge = x.min(y).min(size - 1 - x).min(size - 1 - y);
+                if edge == 0 || edge == border {
+                    seam
+                } else if edge < border || x.abs_diff(y) <= border / 2 {
+                    frame
+                } else if y % plank == 0 {
+                    seam
+                } else {
+                    wood
+                }
+            })
+            .collect();
+        Self::new(size, size, texels)
+    }
+
+    /// color at `u`, `v`, using the texture's filter and wrap modes
+    pub fn sample(&self, u: f32, v: f32) -> Color {
+        let x = u * self.width as f32;
+        let y = v * self.height as f32;
+        match self.filter {
+            Filter::Nearest => self.texel(math::floor_f(x) as isize, math::floor_f(y) as isize),
+            Filter::Bilinear => {
+                // texel centers sit at half coordinates
+                let x = x - 0.5;
+                let y = y - 0.5;
+                let (x0, y0) = (math::floor_f(x), math::floor_f(y));
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as isize, y0 as isize);
+
+                let top = lerp_color(self.texel(x0, y0), self.texel(x0 + 1, y0), fx);
+                let bottom = lerp_color(self.texel(x0, y0 + 1), self.texel(x0 + 1, y0 + 1), fx);
+                lerp_color(top, bottom, fy)
+            }
+        }
+    }
+
+    /// texel at integer coordinates, out of range ones are wrapped or clamped
+    fn texel(&self, x: isize, y: isize) -> Color {
+        let x = address(x, self.width, self.wrap);
+        let y = address(y, self.height, self.wrap);
+        self.texels[y * self.width + x]
+    }
+}
+
+fn address(i: isize, size: usize, wrap: Wrap) -> usize {
+    match wrap {
+        Wrap::Repeat => i.rem_euclid(size as isize) as usize,
+        Wrap::Clamp => i.clamp(0, size as isize - 1) as usize,
+    }
+}
+
+fn lerp_color(a: Color, b: Color, t: f32) -> Color {
+    Color::new(
+        a.r + (b.r - a.r) * t,
+        a.g + (b.g - a.g) * t,
+        a.b + (b.b - a.b) * t,
+        a.a + (b.a - a.a) * t,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::{BLACK, WHITE};
+
+    fn two_by_one() -> Texture {
+        Texture::new(2, 1, vec![BLACK, WHITE])
+    }
+
+    #[test]
+    fn nearest_picks_texel() {
+        let t = two_by_one().with_filter(Filter::Nearest);
+        assert_eq!(t.sample(0.1, 0.5), BLACK);
+        assert_eq!(t.sample(0.6, 0.5), WHITE);
+    }
+
+    #[test]
+    fn wrap_modes() {
+        let repeat = two_by_one().with_filter(Filter::Nearest);
+        assert_eq!(repeat.sample(1.1, 0.5), BLACK);
+        assert_eq!(repeat.sample(-0.1, 0.5), WHITE);
+
+        let clamp = repeat.with_wrap(Wrap::Clamp);
+        assert_eq!(clamp.sample(1.1, 0.5), WHITE);
+        assert_eq!(clamp.sample(-0.1, 0.5), BLACK);
+    }
+
+    #[test]
+    fn bilinear_blends_between_centers() {
+        let clamp = two_by_one().with_wrap(Wrap::Clamp);
+        // halfway between the two texel centers
+        assert_eq!(clamp.sample(0.5, 0.5).r, 0.5);
+        assert_eq!(clamp.sample(0.25, 0.5), BLACK);
+        assert_eq!(clamp.sample(0., 0.5), BLACK);
+
+        // at the left edge the repeat mode blends with the texel on the other side
+        let repeat = two_by_one();
+        assert_eq!(repeat.sample(0., 0.5).r, 0.5);
+    }
+
+    #[test]
+    fn checkerboard_alternates() {
+        let t = Texture::checkerboard(4, 2, BLACK, WHITE);
+        assert_eq!(t.texels[0], BLACK);
+        assert_eq!(t.texels[2], WHITE);
+        assert_eq!(t.texels[2 * 4], WHITE);
+        assert_eq!(t.texels[2 * 4 + 2], BLACK);
+    }
+}
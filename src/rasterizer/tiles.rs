@@ -0,0 +1,234 @@
+//! Parallel rendering for scenes with many triangles. The canvas is cut into
+//! `TILE_SIZE` squares and every triangle is binned into the tiles its bounding
+//! box touches, keeping the order they were submitted in. Threads take whole
+//! tiles, each with its own copy of that part of the depth buffer, and draw the
+//! tile's triangles clipped to it, fills first and then edges. Tiles don't
+//! share pixels and a pixel sees the same triangles in the same order as a
+//! serial render, transparent ones sorted the same way too, so the result is
+//! the same however the tiles end up spread over the threads.
+
+use super::{
+    antialiasing::SampleBuffer,
+    coverage::Rect,
+    main::RenderContext,
+    render::{render_triangles, DepthTarget, ProjectedModel, View},
+};
+use crate::framebuffer::Framebuffer;
+use std::{sync::Mutex, thread};
+
+/// width and height of a tile in pixels
+pub const TILE_SIZE: usize = 32;
+
+/// the canvas cut into tiles row by row, the last row and column can be smaller
+pub fn tile_rects(width: usize, height: usize) -> Vec<Rect> {
+    let mut tiles = vec![];
+    for y in (0..height).step_by(TILE_SIZE) {
+        for x in (0..width).step_by(TILE_SIZE) {
+            tiles.push(Rect::new(
+                x,
+                y,
+                (x + TILE_SIZE).min(width),
+                (y + TILE_SIZE).min(height),
+            ));
+        }
+    }
+    tiles
+}
+
+/// `(model, triangle)` indices touching each tile in submission order.
+/// Back faces and triangles off the canvas aren't in any tile.
+pub fn bin_triangles(ctx: &RenderContext, models: &[ProjectedModel]) -> Vec<Vec<(usize, usize)>> {
+    let columns = ctx.width.div_ceil(TILE_SIZE);
+    let rows = ctx.height.div_ceil(TILE_SIZE);
+    let mut bins = vec![vec![]; columns * rows];
+    for (m, model) in models.iter().enumerate() {
+        for t in 0..model.triangle_count() {
+            if model.is_back_facing(ctx, t) {
+                continue;
+            }
+            let Some(bounds) = model.pixel_bounds(ctx, t) else {
+                continue;
+            };
+            for row in bounds.y0 / TILE_SIZE..=(bounds.y1 - 1) / TILE_SIZE {
+                for column in bounds.x0 / TILE_SIZE..=(bounds.x1 - 1) / TILE_SIZE {
+                    bins[column + columns * row].push((m, t));
+                }
+            }
+        }
+    }
+    bins
+}
+
+/// Renders already clipped and projected models on `ctx.threads` threads.
+/// `depth_buf` covers the whole canvas and is updated like a serial render would.
+pub fn render_tiled(
+    fb: &mut impl Framebuffer,
+    ctx: &RenderContext,
+    models: &[ProjectedModel],
+    depth_buf: &mut DepthTarget,
+    view: &View,
+) {
+    let tiles = tile_rects(ctx.width, ctx.height);
+    let bins = bin_triangles(ctx, models);
+    let samples = depth_buf.samples;
+    // the depth values of row `y` from `x0` up to `x1`
+    let row = |y: usize, x0: usize, x1: usize| {
+        (x0 + ctx.width * y) * samples..(x1 + ctx.width * y) * samples
+    };
+    let depth: &[f32] = depth_buf.values;
+
+    let render_tile = |mut tile: SampleBuffer, bin: &[(usize, usize)]| {
+        let rect = tile.rect();
+        let mut values: Vec<f32> = (rect.y0..rect.y1)
+            .flat_map(|y| &depth[row(y, rect.x0, rect.x1)])
+            .copied()
+            .collect();
+        let mut target = DepthTarget {
+            rect,
+            samples,
+            values: &mut values,
+        };
+        let triangles = bin.iter().map(|&(m, t)| (&models[m], t));
+        render_triangles(&mut tile, ctx, triangles, &mut target, view);
+        (tile, values)
+    };
+
+    // tiles with something to draw, with what's under them in `fb` for blending
+    let jobs: Vec<(SampleBuffer, &[(usize, usize)])> = tiles
+        .iter()
+        .zip(&bins)
+        .filter(|(_, bin)| !bin.is_empty())
+        .map(|(rect, bin)| (SampleBuffer::new(ctx, *rect, fb), bin.as_slice()))
+        .collect();
+    let jobs = Mutex::new(jobs.into_iter());
+
+    let rendered: Vec<(SampleBuffer, Vec<f32>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..ctx.threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = vec![];
+                    loop {
+                        let job = jobs.lock().unwrap().next();
+                        let Some((tile, bin)) = job else {
+                            break done;
+                        };
+                        done.push(render_tile(tile, bin));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().unwrap())
+            .collect()
+    });
+
+    for (tile, values) in rendered {
+        let rect = tile.rect();
+        for (y, tile_row) in (rect.y0..rect.y1).zip(values.chunks((rect.x1 - rect.x0) * samples)) {
+            depth_buf.values[row(y, rect.x0, rect.x1)].copy_from_slice(tile_row);
+        }
+        tile.resolve(fb);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rasterizer::{
+        camera::Projection,
+        color::{Color, RED},
+        data_types::{Model, Triangle, Vertex3},
+        shader::{Flat, Shader, Uniforms, Varyings, VertexInput, VertexOutput},
+    };
+    use std::sync::Arc;
+
+    /// `Flat` mirrored left to right, which turns every triangle around
+    #[derive(Debug)]
+    struct Mirrored;
+
+    impl Shader for Mirrored {
+        fn vertex(&self, uniforms: &Uniforms, input: &VertexInput) -> VertexOutput {
+            let mut output = Flat.vertex(uniforms, input);
+            output.position.x = -output.position.x;
+            output
+        }
+
+        fn fragment(&self, uniforms: &Uniforms, varyings: &Varyings) -> Option<Color> {
+            Flat.fragment(uniforms, varyings)
+        }
+    }
+
+    #[test]
+    fn tiles_cover_the_canvas_once() {
+        let tiles = tile_rects(70, 40);
+        assert_eq!(tiles.len(), 3 * 2);
+        assert_eq!(tiles[2], Rect::new(64, 0, 70, 32));
+        assert_eq!(tiles[5], Rect::new(64, 32, 70, 40));
+        let area: usize = tiles.iter().map(|r| (r.x1 - r.x0) * (r.y1 - r.y0)).sum();
+        assert_eq!(area, 70 * 40);
+    }
+
+    #[test]
+    fn triangles_go_to_the_tiles_they_touch() {
+        let ctx = RenderContext::new(64, 64);
+        let projection = Projection::default();
+        let view = View {
+            projection: &projection,
+            lights: &[],
+            shadows: &[],
+        };
+        // 64 pixels to a unit at z = 1: one near the top left corner, then one
+        // across the middle, then the same one facing away
+        let verticies = vec![
+            Vertex3::new(-0.3, -0.3, 1.),
+            Vertex3::new(-0.25, -0.3, 1.),
+            Vertex3::new(-0.3, -0.25, 1.),
+            Vertex3::new(-0.01, -0.01, 1.),
+            Vertex3::new(0.01, -0.01, 1.),
+            Vertex3::new(-0.01, 0.01, 1.),
+        ];
+        let triangles = vec![
+            Triangle::new(0, 2, 1, RED),
+            Triangle::new(3, 5, 4, RED),
+            Triangle::new(3, 4, 5, RED),
+        ];
+        let model = ProjectedModel::new(&ctx, &view, Model::from_mesh(triangles, verticies));
+        assert!(!model.is_back_facing(&ctx, 0));
+        assert!(model.is_back_facing(&ctx, 2));
+
+        let bins = bin_triangles(&ctx, &[model]);
+        assert_eq!(bins[0], vec![(0, 0), (0, 1)]);
+        for bin in &bins[1..] {
+            assert_eq!(bin, &vec![(0, 1)]);
+        }
+    }
+
+    #[test]
+    fn facing_follows_the_vertex_shader() {
+        let ctx = RenderContext::new(64, 64);
+        let projection = Projection::default();
+        let view = View {
+            projection: &projection,
+            lights: &[],
+            shadows: &[],
+        };
+        let verticies = vec![
+            Vertex3::new(-0.1, -0.1, 1.),
+            Vertex3::new(0.1, -0.1, 1.),
+            Vertex3::new(-0.1, 0.1, 1.),
+        ];
+        let triangles = vec![Triangle::new(0, 2, 1, RED), Triangle::new(0, 1, 2, RED)];
+        let model = || Model::from_mesh(triangles.clone(), verticies.clone());
+        let plain = ProjectedModel::new(&ctx, &view, model());
+        let mirrored = ProjectedModel::new(&ctx, &view, model().with_shader(Arc::new(Mirrored)));
+        assert!(!plain.is_back_facing(&ctx, 0) && plain.is_back_facing(&ctx, 1));
+        assert!(mirrored.is_back_facing(&ctx, 0) && !mirrored.is_back_facing(&ctx, 1));
+
+        let bins = bin_triangles(&ctx, &[plain, mirrored]);
+        assert!(bins
+            .iter()
+            .flatten()
+            .all(|&bin| bin == (0, 0) || bin == (1, 1)));
+    }
+}
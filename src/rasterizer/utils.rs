@@ -1,30 +1,10 @@
 use super::{
+    camera::Projection,
     data_types::Mat4x4,
-    data_types::{Point, Vec4, Vertex3},
-    main::{get_canvas_dimensions, get_view_dimensions, get_viewport_dist},
+    data_types::{Point, Quaternion, Vec4, Vertex3},
+    main::RenderContext,
 };
-use macroquad::color::Color;
-
-pub fn interpolate(i0: f32, d0: f32, i1: f32, d1: f32) -> Vec<f32> {
-    // i == independent variable
-    // d == dependent variable
-    if i0 == i1 {
-        return vec![d0];
-    }
-
-    let mut values = vec![];
-    let a = (d1 - d0) / (i1 - i0);
-
-    let mut d = d0;
-    let start = i0 as i32;
-    let end = i1 as i32;
-    for _i in start..=end {
-        values.push(d);
-        d += a;
-    }
-
-    values
-}
+use crate::color::Color;
 
 pub fn mul_color(color: &Color, h: f32) -> Color {
     Color::new(color.r * h, color.g * h, color.b * h, color.a)
@@ -37,42 +17,27 @@ pub fn map_to_pixels(x: i32, y: i32, width: usize, height: usize) -> (usize, usi
     (x_mapped, y_mapped)
 }
 
-pub fn viewport_to_canvas(x: f32, y: f32) -> Point {
-    let (width, height) = get_canvas_dimensions();
-    let (view_width, view_height) = get_view_dimensions();
-    Point::new(
-        x * (width as f32) / view_width,
-        y * (height as f32) / view_height,
-        1.0,
-    )
-}
-
-pub fn truncate_parts(p: &mut Point) {
-    if p.x < 0. {
-        p.x = math::ceil_f(p.x);
-    } else {
-        p.x = math::floor_f(p.x);
-    }
-    if p.y < 0. {
-        p.y = math::ceil_f(p.y);
-    } else {
-        p.y = math::floor_f(p.y);
-    }
-    if p.z < 0. {
-        p.z = math::ceil_f(p.z);
-    } else {
-        p.z = math::floor_f(p.z);
+/// Perspective divide and viewport transform of a clip space position, see
+/// `Projection::matrix`. `None` when `w` isn't positive, the point is then at or
+/// behind the camera and has no place on the canvas.
+pub fn clip_to_canvas(ctx: &RenderContext, clip: Vec4) -> Option<Point> {
+    if clip.w <= 0. {
+        return None;
     }
+    let inv_w = 1. / clip.w;
+    Some(Point::new(
+        clip.x * inv_w * (ctx.width as f32) / 2.,
+        clip.y * inv_w * (ctx.height as f32) / 2.,
+        1.0,
+    ))
 }
 
-pub fn project_vertex(v: Vec4) -> Point {
-    let viewport_dist = get_viewport_dist();
-    viewport_to_canvas(
-        v.x * viewport_dist / v.z,
-        v.y * viewport_dist / v.z,
-    )
+/// camera space `v` on the canvas, `None` when it's behind the camera
+pub fn project_vertex(ctx: &RenderContext, projection: &Projection, v: Vec4) -> Option<Point> {
+    clip_to_canvas(ctx, projection.to_clip(v.truncate()))
 }
 
+/// counter clockwise around the up axis (-y), seen from above
 pub fn make_rotation_mat(degrees: f32) -> Mat4x4 {
     let cos = math::cos(degrees * math::PI / 180.);
     let sin = math::sin(degrees * math::PI / 180.);
@@ -86,6 +51,29 @@ pub fn make_rotation_mat(degrees: f32) -> Mat4x4 {
     .transpose()
 }
 
+/// counter clockwise around `axis` when it points at the viewer, which doesn't need to be normalized
+pub fn make_axis_rotation_mat(axis: Vertex3, degrees: f32) -> Mat4x4 {
+    Mat4x4::from_axis_angle(axis.normalize(), degrees * math::PI / 180.)
+}
+
+/// Orientation from yaw around the up axis, pitch around x and roll around z, in
+/// degrees. Roll is applied first and yaw last, so a positive pitch tilts +z up and
+/// yaw turns the result like `make_rotation_mat`. A positive roll dips +x down.
+pub fn make_euler_rotation_mat(yaw: f32, pitch: f32, roll: f32) -> Mat4x4 {
+    mul_mm(
+        make_rotation_mat(yaw),
+        mul_mm(
+            make_axis_rotation_mat(Vertex3::X, pitch),
+            make_axis_rotation_mat(Vertex3::Z, roll),
+        ),
+    )
+}
+
+/// the quaternion doesn't need to be normalized
+pub fn make_quaternion_rotation_mat(rotation: Quaternion) -> Mat4x4 {
+    Mat4x4::from_quat(rotation.normalize())
+}
+
 pub fn make_translation_mat(translation: Vertex3) -> Mat4x4 {
     Mat4x4::from_cols(
         Vec4::new(1., 0., 0., translation.x),
@@ -106,6 +94,22 @@ pub fn make_scaling_mat(scale: f32) -> Mat4x4 {
     .transpose()
 }
 
+/// scale along each axis
+pub fn make_nonuniform_scaling_mat(scale: Vertex3) -> Mat4x4 {
+    Mat4x4::from_scale(scale)
+}
+
+/// scales, then rotates by `orientation`, then moves to `position`
+pub fn make_transform_mat(position: Vertex3, orientation: Mat4x4, scale: Vertex3) -> Mat4x4 {
+    mul_mm(make_translation_mat(position), mul_mm(orientation, make_nonuniform_scaling_mat(scale)))
+}
+
+/// Matrix for transforming normals, the inverse transpose. Normals put through
+/// `transform` itself stop being perpendicular to the surface under non-uniform scale.
+pub fn make_normal_mat(transform: Mat4x4) -> Mat4x4 {
+    transform.inverse().transpose()
+}
+
 pub fn mul_mv(mat: Mat4x4, vec: Vec4) -> Vec4 {
     mat.mul_vec4(vec)
 }
@@ -115,10 +119,8 @@ pub fn mul_mm(mat_a: Mat4x4, mat_b: Mat4x4) -> Mat4x4 {
 }
 
 pub mod math {
-    use std::f32;
-
     pub const PI: f32 = std::f32::consts::PI;
-    pub const INFINITY_F32: f32 = std::f32::INFINITY;
+    pub const INFINITY_F32: f32 = f32::INFINITY;
     
     pub fn abs(n: f32) -> f32 {
         n.abs()
@@ -132,6 +134,14 @@ pub mod math {
         n.sin()
     }
 
+    pub fn tan(n: f32) -> f32 {
+        n.tan()
+    }
+
+    pub fn atan(n: f32) -> f32 {
+        n.atan()
+    }
+
     pub fn floor_f(n: f32) -> f32 {
         n.floor()
     }
@@ -144,3 +154,58 @@ pub mod math {
         n.sqrt()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rotated(mat: Mat4x4, v: Vertex3) -> Vertex3 {
+        mat.transform_vector3(v)
+    }
+
+    #[test]
+    fn axis_rotation_matches_the_y_rotation() {
+        for degrees in [30., 90., 195.] {
+            let up = make_axis_rotation_mat(Vertex3::new(0., -2., 0.), degrees);
+            assert!(up.abs_diff_eq(make_rotation_mat(degrees), 1e-6));
+        }
+        let quarter = make_axis_rotation_mat(Vertex3::Z, 90.);
+        assert!(rotated(quarter, Vertex3::X).abs_diff_eq(Vertex3::Y, 1e-6));
+    }
+
+    #[test]
+    fn euler_angles() {
+        // pitching up tilts the forward axis towards -y
+        let up = make_euler_rotation_mat(0., 90., 0.);
+        assert!(rotated(up, Vertex3::Z).abs_diff_eq(-Vertex3::Y, 1e-6));
+        let roll = make_euler_rotation_mat(0., 0., 90.);
+        assert!(rotated(roll, Vertex3::X).abs_diff_eq(Vertex3::Y, 1e-6));
+
+        // roll, then pitch, then yaw
+        let all = make_euler_rotation_mat(90., 90., 90.);
+        assert!(rotated(all, Vertex3::X).abs_diff_eq(-Vertex3::X, 1e-6));
+        assert!(rotated(all, Vertex3::Z).abs_diff_eq(-Vertex3::Y, 1e-6));
+    }
+
+    #[test]
+    fn quaternion_rotation() {
+        let q = Quaternion::from_axis_angle(Vertex3::X, math::PI / 2.) * 3.;
+        let mat = make_quaternion_rotation_mat(q);
+        assert!(mat.abs_diff_eq(make_axis_rotation_mat(Vertex3::X, 90.), 1e-6));
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_nonuniform_scale() {
+        let transform = make_transform_mat(
+            Vertex3::new(1., 2., 3.),
+            make_euler_rotation_mat(30., 20., 10.),
+            Vertex3::new(4., 1., 0.5),
+        );
+        // a 45 degree slope and its normal
+        let (tangent, normal) = (Vertex3::new(1., 1., 0.), Vertex3::new(1., -1., 0.));
+        let tangent = transform.transform_vector3(tangent);
+        let normal = make_normal_mat(transform).transform_vector3(normal);
+        assert!(tangent.dot(normal).abs() < 1e-5);
+        assert!(tangent.dot(transform.transform_vector3(Vertex3::new(1., -1., 0.))).abs() > 1.);
+    }
+}
@@ -0,0 +1,244 @@
+//! Triangle edges for wireframes. Lines are walked one pixel at a time along
+//! their longer axis. The plain version plots the pixel the line passes through,
+//! Xiaolin Wu's version splits each step between the two pixels the line passes
+//! between, weighted by how close it is to each, so edges blend smoothly into
+//! what's behind them.
+
+use super::coverage::Rect;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderMode {
+    /// filled triangles only
+    Filled,
+    /// triangle edges only
+    Wireframe,
+    /// filled triangles with their edges drawn over them, hidden edges stay hidden
+    #[default]
+    FilledWireframe,
+}
+
+impl RenderMode {
+    pub fn fills(self) -> bool {
+        matches!(self, RenderMode::Filled | RenderMode::FilledWireframe)
+    }
+
+    pub fn edges(self) -> bool {
+        matches!(self, RenderMode::Wireframe | RenderMode::FilledWireframe)
+    }
+
+    /// the next mode, for switching between them
+    pub fn next(self) -> Self {
+        match self {
+            RenderMode::Filled => RenderMode::Wireframe,
+            RenderMode::Wireframe => RenderMode::FilledWireframe,
+            RenderMode::FilledWireframe => RenderMode::Filled,
+        }
+    }
+}
+
+/// A line with its axes swapped when it's steep, so it's walked along x from left to right.
+struct Line {
+    steep: bool,
+    start: [f32; 2],
+    end: [f32; 2],
+    /// the line was flipped to go left to right
+    reversed: bool,
+}
+
+impl Line {
+    fn new(p0: [f32; 2], p1: [f32; 2]) -> Self {
+        let steep = (p1[1] - p0[1]).abs() > (p1[0] - p0[0]).abs();
+        let (p0, p1) = if steep {
+            ([p0[1], p0[0]], [p1[1], p1[0]])
+        } else {
+            (p0, p1)
+        };
+        let reversed = p0[0] > p1[0];
+        let (start, end) = if reversed { (p1, p0) } else { (p0, p1) };
+        Self {
+            steep,
+            start,
+            end,
+            reversed,
+        }
+    }
+
+    fn length(&self) -> f32 {
+        self.end[0] - self.start[0]
+    }
+
+    /// where the line is on the minor axis at `major`
+    fn minor_at(&self, major: f32) -> f32 {
+        if self.length() == 0. {
+            return self.start[1];
+        }
+        let slope = (self.end[1] - self.start[1]) / self.length();
+        self.start[1] + (major - self.start[0]) * slope
+    }
+
+    /// how far along from the first point to the second `major` is
+    fn t_at(&self, major: f32) -> f32 {
+        if self.length() == 0. {
+            return 0.;
+        }
+        let t = ((major - self.start[0]) / self.length()).clamp(0., 1.);
+        if self.reversed {
+            1. - t
+        } else {
+            t
+        }
+    }
+
+    fn plot(
+        &self,
+        clip: Rect,
+        major: i64,
+        minor: i64,
+        t: f32,
+        coverage: f32,
+        plot: &mut impl FnMut(usize, usize, f32, f32),
+    ) {
+        let (x, y) = if self.steep {
+            (minor, major)
+        } else {
+            (major, minor)
+        };
+        let inside = (clip.x0 as i64..clip.x1 as i64).contains(&x)
+            && (clip.y0 as i64..clip.y1 as i64).contains(&y);
+        if inside && coverage > 0. {
+            plot(x as usize, y as usize, t, coverage);
+        }
+    }
+}
+
+/// Calls `plot(x, y, t, coverage)` for the pixels in `clip` on the line from `p0` to
+/// `p1`, in pixels from the top left corner of the canvas. `t` goes from 0 at `p0`
+/// to 1 at `p1` and the coverage is always 1. Lines shorter than a pixel draw one.
+pub fn rasterize_line(
+    p0: [f32; 2],
+    p1: [f32; 2],
+    clip: Rect,
+    mut plot: impl FnMut(usize, usize, f32, f32),
+) {
+    let line = Line::new(p0, p1);
+    // pixels whose center is on the line
+    let mut first = (line.start[0] - 0.5).ceil();
+    let mut last = (line.end[0] - 0.5).floor();
+    if first > last {
+        first = ((line.start[0] + line.end[0]) / 2.).floor();
+        last = first;
+    }
+    for major in first as i64..=last as i64 {
+        let center = major as f32 + 0.5;
+        let minor = line.minor_at(center).floor() as i64;
+        line.plot(clip, major, minor, line.t_at(center), 1., &mut plot);
+    }
+}
+
+/// Xiaolin Wu's anti-aliased line, like `rasterize_line` with two pixels for every
+/// step and the coverage of each. The steps at the ends are weighted by how much of
+/// the pixel the line reaches into.
+pub fn rasterize_line_anti_aliased(
+    p0: [f32; 2],
+    p1: [f32; 2],
+    clip: Rect,
+    mut plot: impl FnMut(usize, usize, f32, f32),
+) {
+    let line = Line::new(p0, p1);
+    let (start, end) = (line.start[0], line.end[0]);
+    for major in start.floor() as i64..end.ceil() as i64 {
+        let left = major as f32;
+        let overlap = (end.min(left + 1.) - start.max(left)).clamp(0., 1.);
+        let center = (left + 0.5).clamp(start, end);
+        // between the two pixel centers around the line
+        let minor = line.minor_at(center) - 0.5;
+        let below = minor.floor();
+        let fraction = minor - below;
+        let t = line.t_at(center);
+        line.plot(
+            clip,
+            major,
+            below as i64,
+            t,
+            overlap * (1. - fraction),
+            &mut plot,
+        );
+        line.plot(
+            clip,
+            major,
+            below as i64 + 1,
+            t,
+            overlap * fraction,
+            &mut plot,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIP: Rect = Rect {
+        x0: 0,
+        y0: 0,
+        x1: 16,
+        y1: 16,
+    };
+
+    fn plotted(anti_aliased: bool, p0: [f32; 2], p1: [f32; 2]) -> Vec<(usize, usize, f32, f32)> {
+        let mut pixels = vec![];
+        let plot = |x, y, t, coverage| pixels.push((x, y, t, coverage));
+        if anti_aliased {
+            rasterize_line_anti_aliased(p0, p1, CLIP, plot);
+        } else {
+            rasterize_line(p0, p1, CLIP, plot);
+        }
+        pixels
+    }
+
+    #[test]
+    fn one_pixel_per_step_along_the_longer_axis() {
+        let flat = plotted(false, [2., 3.2], [8., 4.8]);
+        let xs: Vec<usize> = flat.iter().map(|p| p.0).collect();
+        assert_eq!(xs, vec![2, 3, 4, 5, 6, 7]);
+        assert_eq!((flat[0].1, flat[5].1), (3, 4));
+
+        // steep and drawn backwards, t still starts at the first point
+        let steep = plotted(false, [5.5, 12.], [4.5, 2.]);
+        let ys: Vec<usize> = steep.iter().map(|p| p.1).collect();
+        assert_eq!(ys, (2..12).collect::<Vec<_>>());
+        assert!(steep[0].2 > 0.9 && steep[9].2 < 0.1);
+        assert!(steep.iter().all(|p| p.3 == 1.));
+
+        assert_eq!(plotted(false, [3.6, 3.6], [3.7, 3.7]).len(), 1);
+    }
+
+    #[test]
+    fn wu_lines_split_coverage_between_two_pixels() {
+        // halfway between the centers of rows 4 and 5
+        let pixels = plotted(true, [2., 5.], [10., 5.]);
+        for column in pixels.chunks(2) {
+            assert_eq!((column[0].1, column[1].1), (4, 5));
+            assert!((column[0].3 - 0.5).abs() < 1e-6);
+            assert!((column[1].3 - 0.5).abs() < 1e-6);
+        }
+        // on the pixel centers of row 5 and stopping halfway into the last pixel
+        let pixels = plotted(true, [2., 5.5], [6.5, 5.5]);
+        let covered: Vec<(usize, f32)> = pixels
+            .iter()
+            .filter(|p| p.3 > 0.)
+            .map(|p| (p.0, p.3))
+            .collect();
+        assert_eq!(covered, vec![(2, 1.), (3, 1.), (4, 1.), (5, 1.), (6, 0.5)]);
+        assert!(pixels.iter().all(|p| p.1 == 5));
+    }
+
+    #[test]
+    fn lines_are_clipped() {
+        for anti_aliased in [false, true] {
+            let pixels = plotted(anti_aliased, [-10., -4.], [30., 20.]);
+            assert!(!pixels.is_empty());
+            assert!(pixels.iter().all(|p| p.0 < 16 && p.1 < 16));
+        }
+    }
+}
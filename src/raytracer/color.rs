@@ -1,4 +1,6 @@
-pub use macroquad::prelude::Color;
+use crate::raytracer::vec3::Vec3;
+
+pub use crate::color::Color;
 
 pub const RED: Color = Color::new(1.0, 0.0, 0.0, 1.0);
 pub const BLUE: Color = Color::new(0.0, 0.0, 1.0, 1.0);
@@ -7,9 +9,10 @@ pub const WHITE: Color = Color::new(1.0, 1.0, 1.0, 1.0);
 pub const BLACK: Color = Color::new(0.0, 0.0, 0.0, 1.0);
 pub const YELLOW: Color = Color::new(1.0, 1.0, 0.0, 1.0);
 
-#[derive(Debug, Default, Copy, Clone)]
-pub struct Color24 {
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
+pub fn to_rgb(color: &Color) -> Vec3<f32> {
+    Vec3::new(color.r, color.g, color.b)
+}
+
+pub fn from_rgb(rgb: &Vec3<f32>) -> Color {
+    Color::new(rgb.v0, rgb.v1, rgb.v2, 1.0)
 }
@@ -1,45 +1,447 @@
-use std::cmp::Ordering;
+use std::ops::{Index, IndexMut, Mul};
 
-use crate::raytracer::vec3::Vec3;
-#[derive(Debug, Default, Clone)]
-/// 3x3 matrix
+use crate::raytracer::{
+    quat::Quat,
+    vec3::{cross, Point, Vec3},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+/// 3x3 matrix, stored row major
 pub struct Mat3 {
-    pub data: Vec<f32>,
-    rows: usize,
-    cols: usize,
-    expected_size: usize,
+    pub data: [[f32; 3]; 3],
+}
+
+impl Default for Mat3 {
+    fn default() -> Self {
+        Self::identity()
+    }
 }
 
 impl Mat3 {
-    pub fn new(mut data: Vec<f32>) -> Self {
-        let expected_size: usize = 9;
-        let rows: usize = 3;
-        let cols: usize = 3;
-        let len = data.len();
-        match len.cmp(&expected_size) {
-            Ordering::Less => data.resize(expected_size, 0.0),
-            Ordering::Greater => data.shrink_to(expected_size),
-            Ordering::Equal => {}
+    pub fn new(rows: [[f32; 3]; 3]) -> Self {
+        Self { data: rows }
+    }
+
+    pub fn identity() -> Self {
+        Self::new([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]])
+    }
+
+    pub fn from_cols(c0: &Vec3<f32>, c1: &Vec3<f32>, c2: &Vec3<f32>) -> Self {
+        Self::new([
+            [c0.v0, c1.v0, c2.v0],
+            [c0.v1, c1.v1, c2.v1],
+            [c0.v2, c1.v2, c2.v2],
+        ])
+    }
+
+    pub fn col(&self, i: usize) -> Vec3<f32> {
+        Vec3::new(self.data[0][i], self.data[1][i], self.data[2][i])
+    }
+
+    pub fn from_scale(scale: &Vec3<f32>) -> Self {
+        Self::new([
+            [scale.v0, 0.0, 0.0],
+            [0.0, scale.v1, 0.0],
+            [0.0, 0.0, scale.v2],
+        ])
+    }
+
+    /// rotation of `angle` radians around the x axis
+    pub fn from_rotation_x(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self::new([[1.0, 0.0, 0.0], [0.0, cos, -sin], [0.0, sin, cos]])
+    }
+
+    /// rotation of `angle` radians around the y axis
+    pub fn from_rotation_y(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self::new([[cos, 0.0, sin], [0.0, 1.0, 0.0], [-sin, 0.0, cos]])
+    }
+
+    /// rotation of `angle` radians around the z axis
+    pub fn from_rotation_z(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self::new([[cos, -sin, 0.0], [sin, cos, 0.0], [0.0, 0.0, 1.0]])
+    }
+
+    /// rotation of `angle` radians around `axis`, a zero axis gives the identity
+    pub fn from_axis_angle(axis: &Vec3<f32>, angle: f32) -> Self {
+        let Vec3 {
+            v0: x,
+            v1: y,
+            v2: z,
+        } = match axis.try_normalize() {
+            Some(axis) => axis,
+            None => return Self::identity(),
+        };
+        let (sin, cos) = angle.sin_cos();
+        let t = 1.0 - cos;
+
+        Self::new([
+            [t * x * x + cos, t * x * y - sin * z, t * x * z + sin * y],
+            [t * x * y + sin * z, t * y * y + cos, t * y * z - sin * x],
+            [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos],
+        ])
+    }
+
+    /// yaw around y, then pitch around x, then roll around z, all in radians.
+    /// Equivalent to Ry(yaw) * Rx(pitch) * Rz(roll)
+    pub fn from_euler(yaw: f32, pitch: f32, roll: f32) -> Self {
+        Self::from_rotation_y(yaw) * Self::from_rotation_x(pitch) * Self::from_rotation_z(roll)
+    }
+
+    /// the quaternion is normalized first, a zero quaternion gives the identity
+    pub fn from_quat(q: &Quat) -> Self {
+        let Quat { w, x, y, z } = q.normalize();
+        Self::new([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ])
+    }
+
+    /// Camera to world rotation for a camera at `eye` looking at `target`.
+    /// Columns are right, up and forward, so +z in camera space maps to the view direction.
+    /// Falls back to the identity when eye == target, and picks another up
+    /// vector when `up` is parallel to the view direction.
+    pub fn look_at(eye: &Point, target: &Point, up: &Vec3<f32>) -> Self {
+        let forward = match (target - eye).try_normalize() {
+            Some(forward) => forward,
+            None => return Self::identity(),
+        };
+
+        let mut right = cross(up, &forward);
+        if right.length() <= f32::EPSILON {
+            let fallback = if forward.v1.abs() < 0.9 {
+                Vec3::new(0.0, 1.0, 0.0)
+            } else {
+                Vec3::new(0.0, 0.0, 1.0)
+            };
+            right = cross(&fallback, &forward);
         }
+        let right = right.normalize();
+        let up = cross(&forward, &right);
+
+        Self::from_cols(&right, &up, &forward)
+    }
 
-        Self {
-            data,
-            rows,
-            cols,
-            expected_size,
+    pub fn transpose(&self) -> Self {
+        let m = &self.data;
+        Self::new([
+            [m[0][0], m[1][0], m[2][0]],
+            [m[0][1], m[1][1], m[2][1]],
+            [m[0][2], m[1][2], m[2][2]],
+        ])
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let m = &self.data;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /// None when the matrix is singular
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        // |det| is at most the product of the row lengths, so compare against
+        // that to treat small (or large) scales the same as unit ones
+        let bound: f32 = self
+            .data
+            .iter()
+            .map(|row| row.iter().map(|v| v * v).sum::<f32>().sqrt())
+            .product();
+        if det.abs() <= f32::EPSILON * bound {
+            return None;
+        }
+        let m = &self.data;
+        let inv_det = 1.0 / det;
+        let adjugate = [
+            [
+                m[1][1] * m[2][2] - m[1][2] * m[2][1],
+                m[0][2] * m[2][1] - m[0][1] * m[2][2],
+                m[0][1] * m[1][2] - m[0][2] * m[1][1],
+            ],
+            [
+                m[1][2] * m[2][0] - m[1][0] * m[2][2],
+                m[0][0] * m[2][2] - m[0][2] * m[2][0],
+                m[0][2] * m[1][0] - m[0][0] * m[1][2],
+            ],
+            [
+                m[1][0] * m[2][1] - m[1][1] * m[2][0],
+                m[0][1] * m[2][0] - m[0][0] * m[2][1],
+                m[0][0] * m[1][1] - m[0][1] * m[1][0],
+            ],
+        ];
+
+        let mut result = Self::new(adjugate);
+        for row in result.data.iter_mut() {
+            for v in row.iter_mut() {
+                *v *= inv_det;
+            }
         }
+        Some(result)
     }
 
     pub fn mul_vec3(&self, other: &Vec3<f32>) -> Vec3<f32> {
-        let mut result: Vec3<f32> = Vec3::default();
-        let width = self.cols;
-        for y in 0..self.rows {
-            for x in 0..self.cols {
-                let index = (y * width) + x;
-                result[y] += other[x] * self.data[index];
+        let m = &self.data;
+        Vec3::new(
+            m[0][0] * other.v0 + m[0][1] * other.v1 + m[0][2] * other.v2,
+            m[1][0] * other.v0 + m[1][1] * other.v1 + m[1][2] * other.v2,
+            m[2][0] * other.v0 + m[2][1] * other.v1 + m[2][2] * other.v2,
+        )
+    }
+
+    pub fn mul_mat3(&self, other: &Self) -> Self {
+        let mut result = [[0.0; 3]; 3];
+        for (y, row) in result.iter_mut().enumerate() {
+            for (x, v) in row.iter_mut().enumerate() {
+                *v = (0..3).map(|i| self.data[y][i] * other.data[i][x]).sum();
             }
         }
+        Self::new(result)
+    }
+}
+
+impl Index<usize> for Mat3 {
+    type Output = [f32; 3];
+    fn index(&self, row: usize) -> &Self::Output {
+        &self.data[row]
+    }
+}
+
+impl IndexMut<usize> for Mat3 {
+    fn index_mut(&mut self, row: usize) -> &mut [f32; 3] {
+        &mut self.data[row]
+    }
+}
+
+impl Mul for Mat3 {
+    type Output = Mat3;
+    fn mul(self, other: Self) -> Self::Output {
+        self.mul_mat3(&other)
+    }
+}
+
+impl Mul<Vec3<f32>> for Mat3 {
+    type Output = Vec3<f32>;
+    fn mul(self, other: Vec3<f32>) -> Self::Output {
+        self.mul_vec3(&other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    const EPS: f32 = 1e-5;
+
+    fn assert_mat_eq(a: &Mat3, b: &Mat3) {
+        for y in 0..3 {
+            for x in 0..3 {
+                assert!(
+                    (a[y][x] - b[y][x]).abs() < EPS,
+                    "{:?} != {:?} at [{}][{}]",
+                    a,
+                    b,
+                    y,
+                    x
+                );
+            }
+        }
+    }
+
+    fn assert_vec_eq(a: &Vec3<f32>, b: &Vec3<f32>) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < EPS, "{:?} != {:?}", a, b);
+        }
+    }
+
+    fn sample() -> Mat3 {
+        Mat3::new([[2.0, -1.0, 0.5], [0.0, 3.0, 1.0], [4.0, 1.0, -2.0]])
+    }
+
+    #[test]
+    fn identity_is_neutral() {
+        let m = sample();
+        assert_mat_eq(&(m * Mat3::identity()), &m);
+        assert_mat_eq(&(Mat3::identity() * m), &m);
+        assert_eq!(Mat3::default(), Mat3::identity());
+    }
+
+    #[test]
+    fn mul_is_row_by_column() {
+        let a = Mat3::new([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]]);
+        let b = Mat3::new([[9.0, 8.0, 7.0], [6.0, 5.0, 4.0], [3.0, 2.0, 1.0]]);
+        let expected = Mat3::new([[30.0, 24.0, 18.0], [84.0, 69.0, 54.0], [138.0, 114.0, 90.0]]);
+        assert_mat_eq(&(a * b), &expected);
+    }
+
+    #[test]
+    fn mul_vec3() {
+        let v = sample() * Vec3::new(1.0, 2.0, 3.0);
+        assert_vec_eq(&v, &Vec3::new(1.5, 9.0, 0.0));
+    }
+
+    #[test]
+    fn transpose_twice_is_identity_op() {
+        let m = sample();
+        assert_eq!(m.transpose().transpose(), m);
+        assert_eq!(m.transpose()[0][2], m[2][0]);
+    }
+
+    #[test]
+    fn determinant() {
+        assert_eq!(Mat3::identity().determinant(), 1.0);
+        assert!((sample().determinant() - -24.0).abs() < EPS);
+        assert!((sample().transpose().determinant() - -24.0).abs() < EPS);
+    }
+
+    #[test]
+    fn inverse_roundtrip() {
+        let m = sample();
+        let inv = m.inverse().expect("sample is invertible");
+        assert_mat_eq(&(m * inv), &Mat3::identity());
+        assert_mat_eq(&(inv * m), &Mat3::identity());
+    }
+
+    #[test]
+    fn singular_has_no_inverse() {
+        let m = Mat3::new([[1.0, 2.0, 3.0], [2.0, 4.0, 6.0], [0.0, 1.0, 1.0]]);
+        assert_eq!(m.determinant(), 0.0);
+        assert!(m.inverse().is_none());
+        assert!(Mat3::from_scale(&Vec3::new(1.0, 0.0, 1.0))
+            .inverse()
+            .is_none());
+    }
+
+    #[test]
+    fn small_scale_is_invertible() {
+        let m = Mat3::from_scale(&Vec3::new(0.004, 0.004, 0.004));
+        assert!(m.determinant().abs() < f32::EPSILON);
+        let inv = m.inverse().expect("uniform small scale is invertible");
+        assert_mat_eq(&(m * inv), &Mat3::identity());
+        assert!((inv[0][0] - 250.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn rotation_inverse_is_transpose() {
+        let r = Mat3::from_axis_angle(&Vec3::new(1.0, -2.0, 0.5), 1.3);
+        assert_mat_eq(&r.inverse().unwrap(), &r.transpose());
+        assert!((r.determinant() - 1.0).abs() < EPS);
+    }
+
+    #[test]
+    fn axis_angle_matches_principal_rotations() {
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        let y = Vec3::new(0.0, 1.0, 0.0);
+        let z = Vec3::new(0.0, 0.0, 1.0);
+        assert_mat_eq(&Mat3::from_axis_angle(&x, 0.4), &Mat3::from_rotation_x(0.4));
+        assert_mat_eq(&Mat3::from_axis_angle(&y, 0.4), &Mat3::from_rotation_y(0.4));
+        assert_mat_eq(&Mat3::from_axis_angle(&z, 0.4), &Mat3::from_rotation_z(0.4));
+        // axis does not need to be normalized
+        assert_mat_eq(
+            &Mat3::from_axis_angle(&(y * 5.0), 0.4),
+            &Mat3::from_rotation_y(0.4),
+        );
+    }
+
+    #[test]
+    fn axis_angle_degenerate_cases() {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        assert_eq!(Mat3::from_axis_angle(&zero, 1.0), Mat3::identity());
+        let axis = Vec3::new(0.3, 0.4, 0.5);
+        assert_mat_eq(&Mat3::from_axis_angle(&axis, 0.0), &Mat3::identity());
+        assert_mat_eq(&Mat3::from_axis_angle(&axis, 2.0 * PI), &Mat3::identity());
+    }
+
+    #[test]
+    fn rotates_axes_right_handed() {
+        let v = Mat3::from_rotation_z(FRAC_PI_2) * Vec3::new(1.0, 0.0, 0.0);
+        assert_vec_eq(&v, &Vec3::new(0.0, 1.0, 0.0));
+        let v = Mat3::from_rotation_x(FRAC_PI_2) * Vec3::new(0.0, 1.0, 0.0);
+        assert_vec_eq(&v, &Vec3::new(0.0, 0.0, 1.0));
+        let v = Mat3::from_rotation_y(FRAC_PI_2) * Vec3::new(0.0, 0.0, 1.0);
+        assert_vec_eq(&v, &Vec3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn euler_order() {
+        let (yaw, pitch, roll) = (0.3, -0.7, 1.1);
+        let expected =
+            Mat3::from_rotation_y(yaw) * Mat3::from_rotation_x(pitch) * Mat3::from_rotation_z(roll);
+        assert_mat_eq(&Mat3::from_euler(yaw, pitch, roll), &expected);
+        assert_mat_eq(&Mat3::from_euler(0.0, 0.0, 0.0), &Mat3::identity());
+    }
+
+    #[test]
+    fn euler_gimbal_lock_is_still_a_rotation() {
+        let m = Mat3::from_euler(0.5, FRAC_PI_2, 0.25);
+        assert!((m.determinant() - 1.0).abs() < EPS);
+        assert_mat_eq(&(m * m.transpose()), &Mat3::identity());
+    }
+
+    #[test]
+    fn quat_matches_axis_angle() {
+        let axis = Vec3::new(-1.0, 2.0, 0.25);
+        let q = Quat::from_axis_angle(&axis, 2.1);
+        assert_mat_eq(&Mat3::from_quat(&q), &Mat3::from_axis_angle(&axis, 2.1));
+    }
+
+    #[test]
+    fn quat_is_normalized_and_zero_is_identity() {
+        let q = Quat::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), 0.8);
+        let scaled = Quat::new(q.w * 3.0, q.x * 3.0, q.y * 3.0, q.z * 3.0);
+        assert_mat_eq(&Mat3::from_quat(&scaled), &Mat3::from_quat(&q));
+        assert_eq!(
+            Mat3::from_quat(&Quat::new(0.0, 0.0, 0.0, 0.0)),
+            Mat3::identity()
+        );
+    }
+
+    #[test]
+    fn quat_product_matches_matrix_product() {
+        let a = Quat::from_axis_angle(&Vec3::new(1.0, 0.0, 0.0), 0.5);
+        let b = Quat::from_axis_angle(&Vec3::new(0.0, 0.0, 1.0), -1.2);
+        assert_mat_eq(
+            &Mat3::from_quat(&(a * b)),
+            &(Mat3::from_quat(&a) * Mat3::from_quat(&b)),
+        );
+    }
+
+    #[test]
+    fn look_at_points_forward() {
+        let eye = Point::new(1.0, 2.0, 3.0);
+        let target = Point::new(4.0, 2.0, 7.0);
+        let m = Mat3::look_at(&eye, &target, &Vec3::new(0.0, 1.0, 0.0));
+        assert_vec_eq(&(m * Vec3::new(0.0, 0.0, 1.0)), &Vec3::new(0.6, 0.0, 0.8));
+        assert_vec_eq(&m.col(1), &Vec3::new(0.0, 1.0, 0.0));
+        assert!((m.determinant() - 1.0).abs() < EPS);
+    }
+
+    #[test]
+    fn look_at_degenerate_cases() {
+        let eye = Point::new(0.0, 0.0, 0.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        assert_eq!(Mat3::look_at(&eye, &eye, &up), Mat3::identity());
 
-        result
+        // looking straight up, parallel to the up vector
+        let m = Mat3::look_at(&eye, &Point::new(0.0, 5.0, 0.0), &up);
+        assert_vec_eq(&m.col(2), &Vec3::new(0.0, 1.0, 0.0));
+        assert!((m.determinant() - 1.0).abs() < EPS);
+        assert_mat_eq(&(m * m.transpose()), &Mat3::identity());
     }
 }
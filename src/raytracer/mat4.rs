@@ -0,0 +1,378 @@
+use std::ops::{Index, IndexMut, Mul};
+
+use crate::raytracer::{
+    mat3::Mat3,
+    quat::Quat,
+    vec3::{Point, Vec3},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+/// 4x4 affine/projective matrix, stored row major.
+/// Points are treated as (x, y, z, 1) and directions as (x, y, z, 0)
+pub struct Mat4 {
+    pub data: [[f32; 4]; 4],
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Mat4 {
+    pub fn new(rows: [[f32; 4]; 4]) -> Self {
+        Self { data: rows }
+    }
+
+    pub fn identity() -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// rotation/scale from `m`, no translation
+    pub fn from_mat3(m: &Mat3) -> Self {
+        let mut result = Self::identity();
+        for y in 0..3 {
+            result.data[y][..3].copy_from_slice(&m.data[y]);
+        }
+        result
+    }
+
+    /// upper left 3x3 block, i.e. the rotation/scale without translation
+    pub fn to_mat3(self) -> Mat3 {
+        let m = &self.data;
+        Mat3::new([
+            [m[0][0], m[0][1], m[0][2]],
+            [m[1][0], m[1][1], m[1][2]],
+            [m[2][0], m[2][1], m[2][2]],
+        ])
+    }
+
+    pub fn from_translation(t: &Vec3<f32>) -> Self {
+        let mut result = Self::identity();
+        result.data[0][3] = t.v0;
+        result.data[1][3] = t.v1;
+        result.data[2][3] = t.v2;
+        result
+    }
+
+    pub fn from_scale(scale: &Vec3<f32>) -> Self {
+        Self::from_mat3(&Mat3::from_scale(scale))
+    }
+
+    /// see `Mat3::from_axis_angle`
+    pub fn from_axis_angle(axis: &Vec3<f32>, angle: f32) -> Self {
+        Self::from_mat3(&Mat3::from_axis_angle(axis, angle))
+    }
+
+    /// see `Mat3::from_euler`
+    pub fn from_euler(yaw: f32, pitch: f32, roll: f32) -> Self {
+        Self::from_mat3(&Mat3::from_euler(yaw, pitch, roll))
+    }
+
+    /// see `Mat3::from_quat`
+    pub fn from_quat(q: &Quat) -> Self {
+        Self::from_mat3(&Mat3::from_quat(q))
+    }
+
+    /// Camera to world transform for a camera at `eye` looking at `target`, see `Mat3::look_at`.
+    /// The inverse is the world to camera (view) matrix.
+    pub fn look_at(eye: &Point, target: &Point, up: &Vec3<f32>) -> Self {
+        let mut result = Self::from_mat3(&Mat3::look_at(eye, target, up));
+        result.data[0][3] = eye.v0;
+        result.data[1][3] = eye.v1;
+        result.data[2][3] = eye.v2;
+        result
+    }
+
+    /// translation * rotation * scale, the usual object to world transform
+    pub fn from_trs(translation: &Vec3<f32>, rotation: &Quat, scale: &Vec3<f32>) -> Self {
+        Self::from_translation(translation) * Self::from_quat(rotation) * Self::from_scale(scale)
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut result = [[0.0; 4]; 4];
+        for (y, row) in result.iter_mut().enumerate() {
+            for (x, v) in row.iter_mut().enumerate() {
+                *v = self.data[x][y];
+            }
+        }
+        Self::new(result)
+    }
+
+    pub fn determinant(&self) -> f32 {
+        self.cofactors().1
+    }
+
+    /// None when the matrix is singular
+    pub fn inverse(&self) -> Option<Self> {
+        let (cofactors, det) = self.cofactors();
+        // |det| is at most the product of the row lengths, so compare against
+        // that to treat small (or large) scales the same as unit ones
+        let bound: f32 = self
+            .data
+            .iter()
+            .map(|row| row.iter().map(|v| v * v).sum::<f32>().sqrt())
+            .product();
+        if det.abs() <= f32::EPSILON * bound {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        // inverse is the transposed cofactor matrix (adjugate) over the determinant
+        let mut result = [[0.0; 4]; 4];
+        for (y, row) in result.iter_mut().enumerate() {
+            for (x, v) in row.iter_mut().enumerate() {
+                *v = cofactors[x][y] * inv_det;
+            }
+        }
+        Some(Self::new(result))
+    }
+
+    /// cofactor matrix and determinant, computed from 2x2 sub-determinants
+    fn cofactors(&self) -> ([[f32; 4]; 4], f32) {
+        let m = &self.data;
+
+        let s0 = m[0][0] * m[1][1] - m[1][0] * m[0][1];
+        let s1 = m[0][0] * m[1][2] - m[1][0] * m[0][2];
+        let s2 = m[0][0] * m[1][3] - m[1][0] * m[0][3];
+        let s3 = m[0][1] * m[1][2] - m[1][1] * m[0][2];
+        let s4 = m[0][1] * m[1][3] - m[1][1] * m[0][3];
+        let s5 = m[0][2] * m[1][3] - m[1][2] * m[0][3];
+
+        let c5 = m[2][2] * m[3][3] - m[3][2] * m[2][3];
+        let c4 = m[2][1] * m[3][3] - m[3][1] * m[2][3];
+        let c3 = m[2][1] * m[3][2] - m[3][1] * m[2][2];
+        let c2 = m[2][0] * m[3][3] - m[3][0] * m[2][3];
+        let c1 = m[2][0] * m[3][2] - m[3][0] * m[2][2];
+        let c0 = m[2][0] * m[3][1] - m[3][0] * m[2][1];
+
+        let det = s0 * c5 - s1 * c4 + s2 * c3 + s3 * c2 - s4 * c1 + s5 * c0;
+
+        let cofactors = [
+            [
+                m[1][1] * c5 - m[1][2] * c4 + m[1][3] * c3,
+                -(m[1][0] * c5 - m[1][2] * c2 + m[1][3] * c1),
+                m[1][0] * c4 - m[1][1] * c2 + m[1][3] * c0,
+                -(m[1][0] * c3 - m[1][1] * c1 + m[1][2] * c0),
+            ],
+            [
+                -(m[0][1] * c5 - m[0][2] * c4 + m[0][3] * c3),
+                m[0][0] * c5 - m[0][2] * c2 + m[0][3] * c1,
+                -(m[0][0] * c4 - m[0][1] * c2 + m[0][3] * c0),
+                m[0][0] * c3 - m[0][1] * c1 + m[0][2] * c0,
+            ],
+            [
+                m[3][1] * s5 - m[3][2] * s4 + m[3][3] * s3,
+                -(m[3][0] * s5 - m[3][2] * s2 + m[3][3] * s1),
+                m[3][0] * s4 - m[3][1] * s2 + m[3][3] * s0,
+                -(m[3][0] * s3 - m[3][1] * s1 + m[3][2] * s0),
+            ],
+            [
+                -(m[2][1] * s5 - m[2][2] * s4 + m[2][3] * s3),
+                m[2][0] * s5 - m[2][2] * s2 + m[2][3] * s1,
+                -(m[2][0] * s4 - m[2][1] * s2 + m[2][3] * s0),
+                m[2][0] * s3 - m[2][1] * s1 + m[2][2] * s0,
+            ],
+        ];
+
+        (cofactors, det)
+    }
+
+    /// transform (x, y, z, 1), dividing by w when the matrix is projective
+    pub fn transform_point(&self, p: &Point) -> Point {
+        let m = &self.data;
+        let row = |y: usize| m[y][0] * p.v0 + m[y][1] * p.v1 + m[y][2] * p.v2 + m[y][3];
+        let w = row(3);
+        let result = Vec3::new(row(0), row(1), row(2));
+        if w != 1.0 && w != 0.0 {
+            result * (1.0 / w)
+        } else {
+            result
+        }
+    }
+
+    /// transform (x, y, z, 0), translation is ignored
+    pub fn transform_vector(&self, v: &Vec3<f32>) -> Vec3<f32> {
+        let m = &self.data;
+        let row = |y: usize| m[y][0] * v.v0 + m[y][1] * v.v1 + m[y][2] * v.v2;
+        Vec3::new(row(0), row(1), row(2))
+    }
+
+    pub fn mul_mat4(&self, other: &Self) -> Self {
+        let mut result = [[0.0; 4]; 4];
+        for (y, row) in result.iter_mut().enumerate() {
+            for (x, v) in row.iter_mut().enumerate() {
+                *v = (0..4).map(|i| self.data[y][i] * other.data[i][x]).sum();
+            }
+        }
+        Self::new(result)
+    }
+}
+
+impl Index<usize> for Mat4 {
+    type Output = [f32; 4];
+    fn index(&self, row: usize) -> &Self::Output {
+        &self.data[row]
+    }
+}
+
+impl IndexMut<usize> for Mat4 {
+    fn index_mut(&mut self, row: usize) -> &mut [f32; 4] {
+        &mut self.data[row]
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, other: Self) -> Self::Output {
+        self.mul_mat4(&other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    const EPS: f32 = 1e-4;
+
+    fn assert_mat_eq(a: &Mat4, b: &Mat4) {
+        for y in 0..4 {
+            for x in 0..4 {
+                assert!(
+                    (a[y][x] - b[y][x]).abs() < EPS,
+                    "{:?} != {:?} at [{}][{}]",
+                    a,
+                    b,
+                    y,
+                    x
+                );
+            }
+        }
+    }
+
+    fn assert_vec_eq(a: &Vec3<f32>, b: &Vec3<f32>) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < EPS, "{:?} != {:?}", a, b);
+        }
+    }
+
+    fn sample() -> Mat4 {
+        Mat4::new([
+            [1.0, 2.0, 0.0, -1.0],
+            [3.0, 0.0, 1.0, 2.0],
+            [0.0, -2.0, 4.0, 1.0],
+            [1.0, 1.0, 1.0, 1.0],
+        ])
+    }
+
+    #[test]
+    fn identity_is_neutral() {
+        let m = sample();
+        assert_eq!(m * Mat4::identity(), m);
+        assert_eq!(Mat4::identity() * m, m);
+        assert_eq!(Mat4::default(), Mat4::identity());
+    }
+
+    #[test]
+    fn transpose() {
+        let m = sample();
+        assert_eq!(m.transpose().transpose(), m);
+        assert_eq!(m.transpose()[3][0], m[0][3]);
+    }
+
+    #[test]
+    fn determinant() {
+        assert_eq!(Mat4::identity().determinant(), 1.0);
+        assert!((sample().determinant() - -29.0).abs() < EPS);
+        assert!((sample().transpose().determinant() - -29.0).abs() < EPS);
+        let s = Mat4::from_scale(&Vec3::new(2.0, 3.0, 4.0));
+        assert!((s.determinant() - 24.0).abs() < EPS);
+    }
+
+    #[test]
+    fn inverse_roundtrip() {
+        let m = sample();
+        let inv = m.inverse().expect("sample is invertible");
+        assert_mat_eq(&(m * inv), &Mat4::identity());
+        assert_mat_eq(&(inv * m), &Mat4::identity());
+    }
+
+    #[test]
+    fn singular_has_no_inverse() {
+        let mut m = sample();
+        m.data[3] = m.data[0];
+        assert!(m.determinant().abs() < EPS);
+        assert!(m.inverse().is_none());
+        assert!(Mat4::from_scale(&Vec3::new(0.0, 1.0, 1.0))
+            .inverse()
+            .is_none());
+    }
+
+    #[test]
+    fn small_scale_is_invertible() {
+        let m = Mat4::from_scale(&Vec3::new(0.004, 0.004, 0.004));
+        assert!(m.determinant().abs() < f32::EPSILON);
+        let inv = m.inverse().expect("uniform small scale is invertible");
+        assert_mat_eq(&(m * inv), &Mat4::identity());
+        assert!((inv[0][0] - 250.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn translation_affects_points_not_vectors() {
+        let t = Mat4::from_translation(&Vec3::new(1.0, 2.0, 3.0));
+        let p = Point::new(1.0, 1.0, 1.0);
+        assert_vec_eq(&t.transform_point(&p), &Point::new(2.0, 3.0, 4.0));
+        assert_vec_eq(&t.transform_vector(&p), &p);
+        let inv = t.inverse().unwrap();
+        assert_vec_eq(&inv.transform_point(&Point::new(2.0, 3.0, 4.0)), &p);
+    }
+
+    #[test]
+    fn projective_point_is_divided_by_w() {
+        let mut m = Mat4::identity();
+        m.data[3] = [0.0, 0.0, 1.0, 0.0];
+        let p = m.transform_point(&Point::new(2.0, 4.0, 2.0));
+        assert_vec_eq(&p, &Point::new(1.0, 2.0, 1.0));
+    }
+
+    #[test]
+    fn trs_order() {
+        let q = Quat::from_axis_angle(&Vec3::new(0.0, 0.0, 1.0), FRAC_PI_2);
+        let m = Mat4::from_trs(&Vec3::new(10.0, 0.0, 0.0), &q, &Vec3::new(2.0, 2.0, 2.0));
+        // scale, then rotate x onto y, then translate
+        let p = m.transform_point(&Point::new(1.0, 0.0, 0.0));
+        assert_vec_eq(&p, &Point::new(10.0, 2.0, 0.0));
+        let inv = m.inverse().unwrap();
+        assert_vec_eq(&inv.transform_point(&p), &Point::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn rotation_constructors_agree() {
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let a = Mat4::from_axis_angle(&axis, 0.6);
+        let b = Mat4::from_quat(&Quat::from_axis_angle(&axis, 0.6));
+        let c = Mat4::from_euler(0.6, 0.0, 0.0);
+        assert_mat_eq(&a, &b);
+        assert_mat_eq(&a, &c);
+        assert_eq!(a.to_mat3(), Mat3::from_axis_angle(&axis, 0.6));
+    }
+
+    #[test]
+    fn look_at_maps_origin_to_eye() {
+        let eye = Point::new(3.0, 0.0, 1.0);
+        let target = Point::new(0.0, 0.0, 4.0);
+        let m = Mat4::look_at(&eye, &target, &Vec3::new(0.0, 1.0, 0.0));
+        assert_vec_eq(&m.transform_point(&Point::new(0.0, 0.0, 0.0)), &eye);
+
+        // the view matrix puts the target straight ahead on +z
+        let view = m.inverse().unwrap();
+        let t = view.transform_point(&target);
+        assert!(t.v0.abs() < EPS && t.v1.abs() < EPS && t.v2 > 0.0);
+    }
+}
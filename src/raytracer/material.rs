@@ -0,0 +1,104 @@
+use std::f32::consts::PI;
+
+use crate::raytracer::vec3::{dot, Vec3};
+
+/// reflectance at normal incidence for dielectrics, roughly 4% for most non metals
+const DIELECTRIC_F0: f32 = 0.04;
+/// keeps the GGX lobe from collapsing into a delta at roughness 0
+const MIN_ROUGHNESS: f32 = 0.045;
+
+#[derive(Debug, Copy, Clone)]
+pub enum Material {
+    /// The book's model. `specular` is the Phong exponent, -1 for matte surfaces,
+    /// and `reflective` the fraction of the mirror reflection blended in.
+    Phong { specular: f32, reflective: f32 },
+    /// Metallic/roughness model, Cook-Torrance with GGX distribution, Smith geometry
+    /// and Schlick fresnel, plus an energy conserving lambert diffuse.
+    Pbr { metallic: f32, roughness: f32 },
+}
+
+impl Material {
+    pub fn phong(specular: f32, reflective: f32) -> Self {
+        Material::Phong {
+            specular,
+            reflective,
+        }
+    }
+
+    pub fn pbr(metallic: f32, roughness: f32) -> Self {
+        Material::Pbr {
+            metallic: metallic.clamp(0.0, 1.0),
+            roughness: roughness.clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// reflectance at normal incidence, tinted by the albedo for metals
+pub fn base_reflectance(albedo: &Vec3<f32>, metallic: f32) -> Vec3<f32> {
+    Vec3::splat(DIELECTRIC_F0).lerp(albedo, metallic)
+}
+
+/// Outgoing light towards `v` from a light arriving along `l` with the given intensity.
+/// `n`, `v` and `l` must be unit length and point away from the surface.
+/// Intensities use the same scale as the Phong path, so a rough dielectric
+/// lit head on gives back roughly `albedo * intensity`.
+pub fn cook_torrance(
+    albedo: &Vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    n: &Vec3<f32>,
+    v: &Vec3<f32>,
+    l: &Vec3<f32>,
+    intensity: f32,
+) -> Vec3<f32> {
+    let n_dot_l = dot(n, l);
+    if n_dot_l <= 0.0 {
+        return Vec3::default();
+    }
+    let n_dot_v = dot(n, v).max(1e-4);
+    let h = (v + l).normalize();
+    let n_dot_h = dot(n, &h).max(0.0);
+    let v_dot_h = dot(v, &h).max(0.0);
+    let roughness = roughness.max(MIN_ROUGHNESS);
+
+    let f0 = base_reflectance(albedo, metallic);
+    let f = fresnel_schlick(v_dot_h, &f0);
+    let d = distribution_ggx(n_dot_h, roughness);
+    let g = geometry_smith(n_dot_v, n_dot_l, roughness);
+    let specular = f * (d * g / (4.0 * n_dot_v * n_dot_l));
+
+    // whatever is not reflected is refracted and diffused, metals absorb it
+    let kd = (Vec3::splat(1.0) - f) * (1.0 - metallic);
+    let diffuse = kd * *albedo * (1.0 / PI);
+
+    // lights are scaled by pi so the lambert term matches the Phong diffuse
+    (diffuse + specular) * (intensity * PI * n_dot_l)
+}
+
+/// Schlick's approximation of the fresnel term
+pub fn fresnel_schlick(cos_theta: f32, f0: &Vec3<f32>) -> Vec3<f32> {
+    let k = (1.0 - cos_theta).clamp(0.0, 1.0).powi(5);
+    *f0 + (Vec3::splat(1.0) - *f0) * k
+}
+
+/// Fresnel for reflections off rough surfaces, the grazing boost is capped by the roughness
+pub fn fresnel_schlick_roughness(cos_theta: f32, f0: &Vec3<f32>, roughness: f32) -> Vec3<f32> {
+    let k = (1.0 - cos_theta).clamp(0.0, 1.0).powi(5);
+    let grazing = Vec3::splat(1.0 - roughness).max(f0);
+    *f0 + (grazing - *f0) * k
+}
+
+/// GGX/Trowbridge-Reitz normal distribution, alpha = roughness^2
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * denom * denom)
+}
+
+/// Smith shadowing-masking with the Schlick-GGX approximation for direct lighting
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let schlick = |n_dot_x: f32| n_dot_x / (n_dot_x * (1.0 - k) + k);
+    schlick(n_dot_v) * schlick(n_dot_l)
+}
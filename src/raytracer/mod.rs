@@ -1,10 +1,13 @@
-mod camera;
-mod canvas;
-mod color;
-mod light;
-mod mat3;
-mod math;
+pub mod camera;
+pub mod color;
+pub mod light;
+pub mod mat3;
+pub mod mat4;
+pub mod material;
+pub mod math;
+pub mod quat;
 pub mod render;
-mod scene;
-mod sphere;
-mod vec3;
+pub mod scene;
+pub mod sphere;
+pub mod stats;
+pub mod vec3;
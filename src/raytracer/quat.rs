@@ -0,0 +1,99 @@
+use std::ops::Mul;
+
+use crate::raytracer::vec3::Vec3;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+/// Rotation quaternion, w + xi + yj + zk
+pub struct Quat {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Quat {
+    pub fn new(w: f32, x: f32, y: f32, z: f32) -> Self {
+        Self { w, x, y, z }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    /// rotation of `angle` radians around `axis`, a zero axis gives the identity
+    pub fn from_axis_angle(axis: &Vec3<f32>, angle: f32) -> Self {
+        let len = axis.length_squared().sqrt();
+        if len == 0.0 {
+            return Self::identity();
+        }
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        let s = sin / len;
+        Self::new(cos, axis.v0 * s, axis.v1 * s, axis.v2 * s)
+    }
+
+    pub fn length_squared(&self) -> f32 {
+        self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z
+    }
+
+    /// unit length copy of self, a zero quaternion gives the identity
+    pub fn normalize(&self) -> Self {
+        let len = self.length_squared().sqrt();
+        if len == 0.0 {
+            return Self::identity();
+        }
+        Self::new(self.w / len, self.x / len, self.y / len, self.z / len)
+    }
+
+    pub fn conjugate(&self) -> Self {
+        Self::new(self.w, -self.x, -self.y, -self.z)
+    }
+}
+
+impl Mul for Quat {
+    type Output = Quat;
+    fn mul(self, o: Self) -> Self::Output {
+        Quat::new(
+            self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z,
+            self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+            self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+            self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn zero_axis_is_identity() {
+        let q = Quat::from_axis_angle(&Vec3::new(0.0, 0.0, 0.0), 1.0);
+        assert_eq!(q, Quat::identity());
+    }
+
+    #[test]
+    fn zero_quat_normalizes_to_identity() {
+        assert_eq!(Quat::new(0.0, 0.0, 0.0, 0.0).normalize(), Quat::identity());
+    }
+
+    #[test]
+    fn axis_is_normalized() {
+        let q = Quat::from_axis_angle(&Vec3::new(0.0, 10.0, 0.0), FRAC_PI_2);
+        assert!((q.length_squared() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn mul_by_conjugate_is_identity() {
+        let q = Quat::from_axis_angle(&Vec3::new(1.0, 2.0, 3.0), 0.7);
+        let r = q * q.conjugate();
+        assert!((r.w - 1.0).abs() < 1e-6);
+        assert!(r.x.abs() < 1e-6 && r.y.abs() < 1e-6 && r.z.abs() < 1e-6);
+    }
+}
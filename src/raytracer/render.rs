@@ -1,43 +1,74 @@
-use crate::raytracer::{
-    camera::Camera,
-    color::{self, Color},
-    mat3::Mat3,
-    math,
-    scene::Scene,
-    vec3::Point,
+use crate::{
+    framebuffer::Framebuffer,
+    raytracer::{
+        camera::Camera,
+        color::{self, Color},
+        mat3::Mat3,
+        math,
+        scene::Scene,
+        stats::RenderStats,
+        vec3::Point,
+    },
 };
 
-use macroquad::prelude::Image;
+use std::time::Instant;
 
 pub const VIEWPORT_SIZE: f32 = 1.0;
 pub const PROJECTION_PLANE_Z: f32 = 0.50;
 
-pub fn run(image: &mut Image, width: f32, height: f32) {
+/// Ray trace the test scene into `fb`, returns counts and timings for the render
+pub fn run(fb: &mut impl Framebuffer, width: f32, height: f32) -> RenderStats {
+    let setup_start = Instant::now();
     let mut scene = Scene::test_scene();
+    let camera = test_camera();
+    let setup_time = setup_start.elapsed();
+
+    let mut stats = render_scene(fb, &mut scene, &camera, width, height);
+    stats.setup_time = setup_time;
+    stats
+}
+
+/// the camera used by the book's examples
+pub fn test_camera() -> Camera {
+    Camera::new(
+        Point::new(3.0, 0.0, 1.0),
+        Mat3::from_rotation_y(-45f32.to_radians()),
+    )
+}
+
+/// Ray trace `scene` as seen from `camera` into `fb`
+pub fn render_scene(
+    fb: &mut impl Framebuffer,
+    scene: &mut Scene,
+    camera: &Camera,
+    width: f32,
+    height: f32,
+) -> RenderStats {
     let image_width = width;
     let image_height = width;
     let width = width as i32;
     let height = height as i32;
     let recursion_limit = 3;
 
-    let camera = Camera::new(
-        Point::new(3.0, 0.0, 1.0),
-        Mat3::new(vec![
-            0.7071, 0.0, -0.7071, 0.0, 1.0, 0.0, 0.7071, 0.0, 0.7071,
-        ]), // 3x3 matrix flattened to 1d array
-    );
     let subsampling = 1;
     let mut color = color::BLACK;
+    let mut stats = RenderStats {
+        width: width as u32,
+        height: height as u32,
+        ..Default::default()
+    };
 
     // TODO: split scene into quadrants, multithread tracing
     // use rayon, or create 4 smaller images and then stitch them together in a texture after ray tracing finishes
     // TODO: introduce notion of "last hit sphere" to each thread,
     // if it's the same as last iteration use cached dot(camera.pos, sphere.center)
+    let trace_start = Instant::now();
     for y in -height / 2..height / 2 {
         for x in -width / 2..width / 2 {
             let direction = image_to_viewport(x as f32, y as f32, image_width, image_height);
             let direction = camera.rotation.mul_vec3(&direction);
             if x % subsampling == 0 {
+                stats.primary_rays += 1;
                 color = scene.trace_ray(
                     &camera.position,
                     &direction,
@@ -48,18 +79,22 @@ pub fn run(image: &mut Image, width: f32, height: f32) {
             }
 
             let (x_mapped, y_mapped) = map_to_pixels(x, y, width, height);
-            put_pixel(image, x_mapped, y_mapped, color);
+            put_pixel(fb, x_mapped, y_mapped, color);
         }
     }
+    stats.trace_time = trace_start.elapsed();
+
+    scene.counters.drain_into(&mut stats);
+    stats
 }
 
-fn put_pixel(image: &mut Image, x: u32, y: u32, color: Color) {
-    image.set_pixel(x, y, color);
+fn put_pixel(fb: &mut impl Framebuffer, x: usize, y: usize, color: Color) {
+    fb.set_pixel(x, y, color);
 }
 
-fn map_to_pixels(x: i32, y: i32, width: i32, height: i32) -> (u32, u32) {
-    let x_mapped = (x + (width / 2)) as u32;
-    let y_mapped = (y + (height / 2)) as u32;
+fn map_to_pixels(x: i32, y: i32, width: i32, height: i32) -> (usize, usize) {
+    let x_mapped = (x + (width / 2)) as usize;
+    let y_mapped = (y + (height / 2)) as usize;
     (x_mapped, y_mapped)
 }
 
@@ -1,18 +1,22 @@
 use crate::raytracer::{
+    color::{from_rgb, to_rgb},
     color::{BLACK, BLUE, GREEN, RED, YELLOW},
     light::{LightSource, LightType},
+    material::{base_reflectance, cook_torrance, fresnel_schlick_roughness, Material},
     math::{self, vec_length},
     sphere::Sphere,
-    vec3::{dot, neg, Point, Vec3},
+    stats::RayCounters,
+    vec3::{dot, neg, reflect, Point, Vec3},
 };
 
-use macroquad::prelude::Color;
+use crate::color::Color;
 
 #[derive(Debug, Default)]
 pub struct Scene {
     pub spheres: Vec<Sphere>,
     pub lights: Vec<LightSource>,
     pub background_color: Color,
+    pub counters: RayCounters,
 }
 const EPISLON: f32 = 0.001;
 
@@ -22,6 +26,7 @@ impl Scene {
             spheres,
             lights,
             background_color: bg,
+            counters: RayCounters::default(),
         }
     }
 
@@ -49,6 +54,40 @@ impl Scene {
         Scene::new(spheres, lights, bg)
     }
 
+    /// `test_scene` with metallic/roughness materials instead of Phong
+    pub fn pbr_test_scene() -> Self {
+        let gold = Color::new(1.0, 0.78, 0.34, 1.0);
+        let spheres = vec![
+            Sphere::with_material(
+                Point::new(0.0, 1.0, 3.0),
+                1.0,
+                gold,
+                Material::pbr(1.0, 0.3),
+            ),
+            Sphere::with_material(
+                Point::new(2.0, 0.0, 4.0),
+                1.0,
+                BLUE,
+                Material::pbr(0.0, 0.1),
+            ),
+            Sphere::with_material(
+                Point::new(-2.0, 0.0, 4.0),
+                1.0,
+                GREEN,
+                Material::pbr(0.0, 0.8),
+            ),
+            Sphere::with_material(
+                Point::new(0.0, 5001.0, 0.0),
+                5000.0,
+                YELLOW,
+                Material::pbr(0.0, 0.5),
+            ),
+        ];
+
+        let Scene { lights, .. } = Scene::test_scene();
+        Scene::new(spheres, lights, BLACK)
+    }
+
     pub fn trace_ray(
         &mut self,
         origin: &Point,
@@ -63,31 +102,58 @@ impl Scene {
         match closest_sphere {
             Some(sphere) => {
                 let position = origin + &(direction * closest_t); // intersection
+                let normal = (position - sphere.center).normalize();
+                let view = neg(direction);
 
-                let mut normal = position - sphere.center;
-                normal = normal * (1.0 / math::vec_length(&normal));
-
-                let intensity =
-                    self.compute_lighting(&position, &normal, &neg(direction), sphere.specular);
-                let mut local_color = mul_color(&sphere.color, intensity);
-
-                let reflectivity = sphere.reflective;
-                if recursion_depth <= 0 || reflectivity <= 0.0 {
+                let mut local_color = self.compute_lighting(&position, &normal, &view, sphere);
+                if recursion_depth <= 0 {
                     return local_color;
                 }
 
-                let ray = reflect_ray(&-direction, &normal);
-                let mut reflected_color =
-                    self.trace_ray(&position, &ray, t_min, t_max, recursion_depth - 1);
-                reflected_color = mul_color(&reflected_color, reflectivity);
-                local_color = mul_color(&local_color, 1.0 - reflectivity);
-
-                Color::new(
-                    local_color.r + reflected_color.r,
-                    local_color.g + reflected_color.g,
-                    local_color.b + reflected_color.b,
-                    255.0,
-                )
+                match sphere.material {
+                    Material::Phong { reflective, .. } => {
+                        if reflective <= 0.0 {
+                            return local_color;
+                        }
+
+                        let ray = reflect_ray(&view, &normal);
+                        self.counters.count_reflection_ray();
+                        let mut reflected_color =
+                            self.trace_ray(&position, &ray, t_min, t_max, recursion_depth - 1);
+                        reflected_color = mul_color(&reflected_color, reflective);
+                        local_color = mul_color(&local_color, 1.0 - reflective);
+
+                        Color::new(
+                            local_color.r + reflected_color.r,
+                            local_color.g + reflected_color.g,
+                            local_color.b + reflected_color.b,
+                            255.0,
+                        )
+                    }
+                    Material::Pbr {
+                        metallic,
+                        roughness,
+                    } => {
+                        // no glossy sampling, rough surfaces just fade the mirror reflection out
+                        if roughness >= 1.0 {
+                            return local_color;
+                        }
+                        let f0 = base_reflectance(&to_rgb(&sphere.color), metallic);
+                        let n_dot_v = dot(&normal, &view.normalize()).max(0.0);
+                        let weight =
+                            fresnel_schlick_roughness(n_dot_v, &f0, roughness) * (1.0 - roughness);
+
+                        let ray = reflect_ray(&view, &normal);
+                        self.counters.count_reflection_ray();
+                        let reflected_color =
+                            self.trace_ray(&position, &ray, t_min, t_max, recursion_depth - 1);
+
+                        from_rgb(
+                            &(to_rgb(&local_color) * (Vec3::splat(1.0) - weight)
+                                + to_rgb(&reflected_color) * weight),
+                        )
+                    }
+                }
             }
             None => self.background_color,
         }
@@ -103,9 +169,10 @@ impl Scene {
         let mut closest_t = math::INFINITY;
         let mut closest_sphere: Option<&Sphere> = None;
         let spheres = &self.spheres;
-        let dir_dot = dot(&direction, &direction);
+        let dir_dot = dot(direction, direction);
+        self.counters.count_intersection_tests(spheres.len());
 
-        for (_i, sphere) in spheres.iter().enumerate() {
+        for sphere in spheres {
             let (t1, t2) = self.intersect_ray_sphere(origin, direction, sphere, dir_dot);
             let range = t_min..t_max;
             if range.contains(&t1) && t1 < closest_t {
@@ -131,7 +198,7 @@ impl Scene {
         let oc = origin - &sphere.center;
 
         let a = dir_dot;
-        let b = 2.0 * dot(&oc, &direction);
+        let b = 2.0 * dot(&oc, direction);
         let c = dot(&oc, &oc) - radius_sq;
         let discriminant = b * b - 4.0 * a * c;
 
@@ -146,6 +213,48 @@ impl Scene {
     }
 
     fn compute_lighting(
+        &self,
+        position: &Vec3<f32>,
+        normal: &Vec3<f32>,
+        direction: &Vec3<f32>,
+        sphere: &Sphere,
+    ) -> Color {
+        match sphere.material {
+            Material::Phong { specular, .. } => mul_color(
+                &sphere.color,
+                self.compute_phong_intensity(position, normal, direction, specular),
+            ),
+            Material::Pbr {
+                metallic,
+                roughness,
+            } => {
+                let albedo = to_rgb(&sphere.color);
+                let view = direction.normalize();
+                let mut result: Vec3<f32> = Vec3::default();
+
+                for (light, l) in self.unshadowed_lights(position) {
+                    match l {
+                        Some(l) => {
+                            result += cook_torrance(
+                                &albedo,
+                                metallic,
+                                roughness,
+                                normal,
+                                &view,
+                                &l.normalize(),
+                                light.intensity,
+                            )
+                        }
+                        None => result += albedo * light.intensity,
+                    }
+                }
+
+                from_rgb(&result)
+            }
+        }
+    }
+
+    fn compute_phong_intensity(
         &self,
         position: &Vec3<f32>,
         normal: &Vec3<f32>,
@@ -153,58 +262,75 @@ impl Scene {
         specularity: f32,
     ) -> f32 {
         let mut intensity = 0.0;
-        let mut l = None;
-        let mut t_max = math::INFINITY;
-        let lights = &self.lights;
 
-        for light in lights {
-            match light.light_type {
-                LightType::Ambient => intensity += light.intensity,
-                LightType::Point => {
-                    let p = light.position.expect("Point light without position");
-                    l = Some(&p - position);
-                    t_max = 1.0;
-                }
-                LightType::Directional => {
-                    l = light.direction;
-                }
-            }
-            if let Some(l) = l {
-                // Shadow Check
-                let (hit_sphere, _shadow_t) =
-                    self.closest_intersection(position, &l, EPISLON, t_max);
-                if hit_sphere.is_some() {
+        for (light, l) in self.unshadowed_lights(position) {
+            let l = match l {
+                Some(l) => l,
+                None => {
+                    intensity += light.intensity;
                     continue;
                 }
+            };
 
-                // Diffuse
-                let nl = dot(&normal, &l);
-                if nl > 0.0 {
-                    intensity +=
-                        light.intensity * nl / (math::vec_length(&normal) * math::vec_length(&l))
-                }
+            // Diffuse
+            let nl = dot(normal, &l);
+            if nl > 0.0 {
+                intensity +=
+                    light.intensity * nl / (math::vec_length(normal) * math::vec_length(&l))
+            }
 
-                // Specularity
-                if specularity > -1.0 {
-                    let r = reflect_ray(&l, &normal);
-                    let rv = dot(&r, &direction);
-                    if rv > 0.0 {
-                        let r_len = vec_length(&r);
-                        let cam_dir_len = vec_length(&direction);
-                        intensity +=
-                            light.intensity * math::pow(rv / (r_len * cam_dir_len), specularity);
-                    }
+            // Specularity
+            if specularity > -1.0 {
+                let r = reflect_ray(&l, normal);
+                let rv = dot(&r, direction);
+                if rv > 0.0 {
+                    let r_len = vec_length(&r);
+                    let cam_dir_len = vec_length(direction);
+                    intensity +=
+                        light.intensity * math::pow(rv / (r_len * cam_dir_len), specularity);
                 }
             }
         }
 
         intensity
     }
+
+    /// Lights that reach `position`, paired with the vector towards the light.
+    /// Ambient lights always reach and have no vector.
+    fn unshadowed_lights<'a>(
+        &'a self,
+        position: &'a Vec3<f32>,
+    ) -> impl Iterator<Item = (&'a LightSource, Option<Vec3<f32>>)> + 'a {
+        // like the book, directional lights reuse the shadow t_max of the
+        // last point light before them
+        let mut t_max = math::INFINITY;
+        self.lights.iter().filter_map(move |light| {
+            let l = match light.light_type {
+                LightType::Ambient => return Some((light, None)),
+                LightType::Point => {
+                    let p = light.position.expect("Point light without position");
+                    t_max = 1.0;
+                    &p - position
+                }
+                LightType::Directional => light
+                    .direction
+                    .expect("Directional light without direction"),
+            };
+
+            // Shadow Check
+            self.counters.count_shadow_ray();
+            let (hit_sphere, _shadow_t) = self.closest_intersection(position, &l, EPISLON, t_max);
+            match hit_sphere {
+                Some(_) => None,
+                None => Some((light, Some(l))),
+            }
+        })
+    }
 }
 
 /// reflect ray r with respect to the normal of the surface
 fn reflect_ray(r: &Vec3<f32>, n: &Vec3<f32>) -> Vec3<f32> {
-    &((n * 2.0) * dot(n, r)) - r
+    -reflect(r, n)
 }
 
 fn mul_color(color: &Color, n: f32) -> Color {
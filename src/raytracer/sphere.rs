@@ -1,5 +1,6 @@
 use crate::raytracer::{
     color::{Color, RED},
+    material::Material,
     vec3::Point,
 };
 
@@ -9,8 +10,7 @@ pub struct Sphere {
     pub radius: f32,
     pub radius_sq: f32,
     pub color: Color,
-    pub specular: f32,
-    pub reflective: f32,
+    pub material: Material,
 }
 
 impl Default for Sphere {
@@ -21,21 +21,24 @@ impl Default for Sphere {
             radius: 1.0,
             radius_sq: 1.0 * 1.0,
             color: RED,
-            specular: 10.0,
-            reflective: 0.5,
+            material: Material::phong(10.0, 0.5),
         }
     }
 }
 
 impl Sphere {
+    /// sphere with the book's Phong material
     pub fn new(center: Point, radius: f32, color: Color, specular: f32, reflective: f32) -> Self {
+        Self::with_material(center, radius, color, Material::phong(specular, reflective))
+    }
+
+    pub fn with_material(center: Point, radius: f32, color: Color, material: Material) -> Self {
         Self {
             center,
             radius,
             radius_sq: radius * radius,
             color,
-            specular,
-            reflective,
+            material,
         }
     }
 }
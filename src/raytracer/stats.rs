@@ -0,0 +1,161 @@
+use std::{cell::Cell, fmt, time::Duration};
+
+/// What a single raytracer render did and where the time went
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RenderStats {
+    pub width: u32,
+    pub height: u32,
+    pub primary_rays: u64,
+    pub shadow_rays: u64,
+    pub reflection_rays: u64,
+    /// ray/sphere tests, summed over every kind of ray
+    pub intersection_tests: u64,
+    /// building the scene and camera
+    pub setup_time: Duration,
+    /// tracing every pixel and writing it to the framebuffer
+    pub trace_time: Duration,
+}
+
+impl RenderStats {
+    pub fn total_rays(&self) -> u64 {
+        self.primary_rays + self.shadow_rays + self.reflection_rays
+    }
+
+    /// Reflection bounces per primary ray. Every hit spawns at most one reflection,
+    /// so this is how deep the recursion went on average.
+    pub fn average_recursion_depth(&self) -> f32 {
+        if self.primary_rays == 0 {
+            return 0.0;
+        }
+        self.reflection_rays as f32 / self.primary_rays as f32
+    }
+
+    pub fn total_time(&self) -> Duration {
+        self.setup_time + self.trace_time
+    }
+
+    /// one line per stat, used for both the window overlay and `Display`
+    pub fn lines(&self) -> Vec<String> {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        vec![
+            format!("resolution: {}x{}", self.width, self.height),
+            format!(
+                "rays: {} (primary {}, shadow {}, reflection {})",
+                self.total_rays(),
+                self.primary_rays,
+                self.shadow_rays,
+                self.reflection_rays
+            ),
+            format!("intersection tests: {}", self.intersection_tests),
+            format!(
+                "average recursion depth: {:.3}",
+                self.average_recursion_depth()
+            ),
+            format!(
+                "time: {:.2}ms (setup {:.2}ms, trace {:.2}ms)",
+                ms(self.total_time()),
+                ms(self.setup_time),
+                ms(self.trace_time)
+            ),
+        ]
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.lines().join("\n"))
+    }
+}
+
+/// Counters bumped while tracing. Intersection and shadow code only borrows the
+/// scene immutably, so these use interior mutability.
+#[derive(Debug, Default)]
+pub struct RayCounters {
+    shadow_rays: Cell<u64>,
+    reflection_rays: Cell<u64>,
+    intersection_tests: Cell<u64>,
+}
+
+impl RayCounters {
+    pub fn count_shadow_ray(&self) {
+        self.shadow_rays.set(self.shadow_rays.get() + 1);
+    }
+
+    pub fn count_reflection_ray(&self) {
+        self.reflection_rays.set(self.reflection_rays.get() + 1);
+    }
+
+    pub fn count_intersection_tests(&self, n: usize) {
+        self.intersection_tests
+            .set(self.intersection_tests.get() + n as u64);
+    }
+
+    /// copy the counts into `stats` and start counting from zero again
+    pub fn drain_into(&self, stats: &mut RenderStats) {
+        stats.shadow_rays += self.shadow_rays.take();
+        stats.reflection_rays += self.reflection_rays.take();
+        stats.intersection_tests += self.intersection_tests.take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        framebuffer::Rgba8Buffer,
+        raytracer::{
+            camera::Camera,
+            color::{BLACK, RED},
+            light::{LightSource, LightType},
+            mat3::Mat3,
+            render::render_scene,
+            scene::Scene,
+            sphere::Sphere,
+            vec3::Point,
+        },
+    };
+
+    /// one reflective sphere filling the view, lit by an ambient and a point light
+    fn tiny_scene() -> Scene {
+        let spheres = vec![Sphere::new(Point::new(0.0, 0.0, 10.0), 9.0, RED, 10.0, 0.5)];
+        let lights = vec![
+            LightSource::new(LightType::Ambient, 0.2, None, None),
+            LightSource::new(LightType::Point, 0.8, Some(Point::default()), None),
+        ];
+        Scene::new(spheres, lights, BLACK)
+    }
+
+    #[test]
+    fn counts_rays_for_a_tiny_scene() {
+        let mut fb = Rgba8Buffer::new(4, 4, BLACK);
+        let mut scene = tiny_scene();
+        let camera = Camera::new(Point::default(), Mat3::identity());
+        let stats = render_scene(&mut fb, &mut scene, &camera, 4.0, 4.0);
+
+        // every pixel hits the sphere, casts one shadow ray for the point light
+        // and one reflection that escapes into the background
+        assert_eq!((stats.width, stats.height), (4, 4));
+        assert_eq!(stats.primary_rays, 16);
+        assert_eq!(stats.shadow_rays, 16);
+        assert_eq!(stats.reflection_rays, 16);
+        assert_eq!(stats.total_rays(), 48);
+        assert_eq!(stats.intersection_tests, 48);
+        assert_eq!(stats.average_recursion_depth(), 1.0);
+    }
+
+    #[test]
+    fn drain_resets_the_counters() {
+        let counters = RayCounters::default();
+        counters.count_shadow_ray();
+        counters.count_reflection_ray();
+        counters.count_intersection_tests(3);
+
+        let mut stats = RenderStats::default();
+        counters.drain_into(&mut stats);
+        counters.drain_into(&mut stats);
+        assert_eq!(stats.shadow_rays, 1);
+        assert_eq!(stats.reflection_rays, 1);
+        assert_eq!(stats.intersection_tests, 3);
+        assert_eq!(RenderStats::default().average_recursion_depth(), 0.0);
+    }
+}
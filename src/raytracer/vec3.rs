@@ -1,15 +1,17 @@
-use std::ops::{Add, AddAssign, Div, Index, IndexMut, Mul, MulAssign, Neg, Rem, Sub, SubAssign};
+use std::ops::{
+    Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Rem, Sub, SubAssign,
+};
 
-#[derive(Debug, Copy, Clone, Default)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
 pub struct Vec3<T> {
     pub v0: T,
     pub v1: T,
     pub v2: T,
-    sentintel: T,
 }
 
 pub type Point = Vec3<f32>;
 
+/// panics when `i` is not 0, 1 or 2, use `Vec3::get` for checked access
 impl<T: VecData> Index<usize> for Vec3<T> {
     type Output = T;
     fn index(&self, i: usize) -> &Self::Output {
@@ -17,18 +19,19 @@ impl<T: VecData> Index<usize> for Vec3<T> {
             0 => &self.v0,
             1 => &self.v1,
             2 => &self.v2,
-            _ => &self.sentintel,
+            _ => panic!("Vec3 index out of range: {}", i),
         }
     }
 }
 
+/// panics when `i` is not 0, 1 or 2, use `Vec3::get_mut` for checked access
 impl<T: VecData> IndexMut<usize> for Vec3<T> {
     fn index_mut(&mut self, i: usize) -> &mut T {
         match i {
             0 => &mut self.v0,
             1 => &mut self.v1,
             2 => &mut self.v2,
-            _ => &mut self.sentintel,
+            _ => panic!("Vec3 index out of range: {}", i),
         }
     }
 }
@@ -71,40 +74,34 @@ impl<T> VecData for T where
 {
 }
 
-/*
-    impl operations will mutate self when applicable
-    allocating versions of these operations are implemented below
-    for various operators
-*/
 impl<T> Vec3<T>
 where
     T: VecData,
 {
     pub fn new(v0: T, v1: T, v2: T) -> Self {
-        Self {
-            v0,
-            v1,
-            v2,
-            sentintel: T::default(),
-        }
+        Self { v0, v1, v2 }
     }
 
-    pub fn sub(&mut self, other: &Self) {
-        self.v0 -= other.v0;
-        self.v1 -= other.v2;
-        self.v2 -= other.v2;
+    pub fn splat(v: T) -> Self {
+        Self::new(v, v, v)
     }
 
-    pub fn add(&mut self, other: &Self) {
-        self.v0 += other.v0;
-        self.v1 += other.v1;
-        self.v2 += other.v2;
+    pub fn get(&self, i: usize) -> Option<&T> {
+        match i {
+            0 => Some(&self.v0),
+            1 => Some(&self.v1),
+            2 => Some(&self.v2),
+            _ => None,
+        }
     }
 
-    pub fn mul(&mut self, other: &Self) {
-        self.v0 *= other.v0;
-        self.v1 *= other.v1;
-        self.v2 *= other.v2;
+    pub fn get_mut(&mut self, i: usize) -> Option<&mut T> {
+        match i {
+            0 => Some(&mut self.v0),
+            1 => Some(&mut self.v1),
+            2 => Some(&mut self.v2),
+            _ => None,
+        }
     }
 
     pub fn scale(&mut self, scalar: T) {
@@ -116,6 +113,57 @@ where
     pub fn length_squared(&self) -> T {
         (self.v0 * self.v0) + (self.v1 * self.v1) + (self.v2 * self.v2)
     }
+
+    /// self at t = 0, other at t = 1
+    pub fn lerp(&self, other: &Self, t: T) -> Self {
+        self + &((other - self) * t)
+    }
+
+    /// component-wise minimum
+    pub fn min(&self, other: &Self) -> Self {
+        let pick = |a: T, b: T| if b < a { b } else { a };
+        Vec3::new(
+            pick(self.v0, other.v0),
+            pick(self.v1, other.v1),
+            pick(self.v2, other.v2),
+        )
+    }
+
+    /// component-wise maximum
+    pub fn max(&self, other: &Self) -> Self {
+        let pick = |a: T, b: T| if b > a { b } else { a };
+        Vec3::new(
+            pick(self.v0, other.v0),
+            pick(self.v1, other.v1),
+            pick(self.v2, other.v2),
+        )
+    }
+
+    pub fn abs(&self) -> Self {
+        let abs = |a: T| if a < T::default() { -a } else { a };
+        Vec3::new(abs(self.v0), abs(self.v1), abs(self.v2))
+    }
+}
+
+impl Vec3<f32> {
+    pub fn length(&self) -> f32 {
+        self.length_squared().sqrt()
+    }
+
+    /// unit length copy of self, a zero vector stays zero
+    pub fn normalize(&self) -> Self {
+        self.try_normalize().unwrap_or_default()
+    }
+
+    /// None for zero length or non finite vectors
+    pub fn try_normalize(&self) -> Option<Self> {
+        let len = self.length();
+        if len > 0.0 && len.is_finite() {
+            Some(self * (1.0 / len))
+        } else {
+            None
+        }
+    }
 }
 
 pub fn neg<T: VecData>(u: &Vec3<T>) -> Vec3<T> {
@@ -126,7 +174,7 @@ pub fn dot<T: VecData>(u: &Vec3<T>, v: &Vec3<T>) -> T {
     (u.v0 * v.v0) + (u.v1 * v.v1) + (u.v2 * v.v2)
 }
 
-pub fn _cross<T: VecData>(u: &Vec3<T>, v: &Vec3<T>) -> Vec3<T> {
+pub fn cross<T: VecData>(u: &Vec3<T>, v: &Vec3<T>) -> Vec3<T> {
     Vec3::new(
         (u.v1 * v.v2) - (u.v2 * v.v1),
         (u.v2 * v.v0) - (u.v0 * v.v2),
@@ -134,6 +182,23 @@ pub fn _cross<T: VecData>(u: &Vec3<T>, v: &Vec3<T>) -> Vec3<T> {
     )
 }
 
+/// reflect direction `v` about the surface normal `n`, `n` must be unit length
+pub fn reflect(v: &Vec3<f32>, n: &Vec3<f32>) -> Vec3<f32> {
+    v - &(n * (2.0 * dot(v, n)))
+}
+
+/// Refract unit direction `v` through a surface with unit normal `n` facing against `v`.
+/// `eta` is the ratio of refractive indices, outside over inside.
+/// None on total internal reflection.
+pub fn refract(v: &Vec3<f32>, n: &Vec3<f32>, eta: f32) -> Option<Vec3<f32>> {
+    let cos_i = -dot(v, n);
+    let k = 1.0 - eta * eta * (1.0 - cos_i * cos_i);
+    if k < 0.0 {
+        return None;
+    }
+    Some((v * eta) + (n * (eta * cos_i - k.sqrt())))
+}
+
 impl<T: VecData> Add for Vec3<T> {
     type Output = Vec3<T>;
     fn add(self, other: Self) -> Self::Output {
@@ -148,6 +213,14 @@ impl<T: VecData> Add for &Vec3<T> {
     }
 }
 
+impl<T: VecData> AddAssign for Vec3<T> {
+    fn add_assign(&mut self, other: Self) {
+        self.v0 += other.v0;
+        self.v1 += other.v1;
+        self.v2 += other.v2;
+    }
+}
+
 impl<T: VecData> Sub for Vec3<T> {
     type Output = Vec3<T>;
     fn sub(self, other: Self) -> Self::Output {
@@ -162,6 +235,14 @@ impl<T: VecData> Sub for &Vec3<T> {
     }
 }
 
+impl<T: VecData> SubAssign for Vec3<T> {
+    fn sub_assign(&mut self, other: Self) {
+        self.v0 -= other.v0;
+        self.v1 -= other.v1;
+        self.v2 -= other.v2;
+    }
+}
+
 impl<T: VecData> Neg for Vec3<T> {
     type Output = Vec3<T>;
     fn neg(self) -> Self::Output {
@@ -189,3 +270,251 @@ impl<T: VecData> Mul<T> for &Vec3<T> {
         Vec3::new(self.v0 * other, self.v1 * other, self.v2 * other)
     }
 }
+
+/// component-wise product
+impl<T: VecData> Mul for Vec3<T> {
+    type Output = Vec3<T>;
+    fn mul(self, other: Self) -> Self::Output {
+        Vec3::new(self.v0 * other.v0, self.v1 * other.v1, self.v2 * other.v2)
+    }
+}
+
+/// component-wise product
+impl<T: VecData> Mul for &Vec3<T> {
+    type Output = Vec3<T>;
+    fn mul(self, other: Self) -> Self::Output {
+        Vec3::new(self.v0 * other.v0, self.v1 * other.v1, self.v2 * other.v2)
+    }
+}
+
+impl Mul<Vec3<f32>> for f32 {
+    type Output = Vec3<f32>;
+    fn mul(self, other: Vec3<f32>) -> Self::Output {
+        other * self
+    }
+}
+
+impl<T: VecData> MulAssign<T> for Vec3<T> {
+    fn mul_assign(&mut self, other: T) {
+        self.scale(other);
+    }
+}
+
+impl<T: VecData> MulAssign for Vec3<T> {
+    fn mul_assign(&mut self, other: Self) {
+        self.v0 *= other.v0;
+        self.v1 *= other.v1;
+        self.v2 *= other.v2;
+    }
+}
+
+impl<T: VecData> Div<T> for Vec3<T> {
+    type Output = Vec3<T>;
+    fn div(self, other: T) -> Self::Output {
+        Vec3::new(self.v0 / other, self.v1 / other, self.v2 / other)
+    }
+}
+
+impl<T: VecData> Div<T> for &Vec3<T> {
+    type Output = Vec3<T>;
+    fn div(self, other: T) -> Self::Output {
+        Vec3::new(self.v0 / other, self.v1 / other, self.v2 / other)
+    }
+}
+
+/// component-wise quotient
+impl<T: VecData> Div for Vec3<T> {
+    type Output = Vec3<T>;
+    fn div(self, other: Self) -> Self::Output {
+        Vec3::new(self.v0 / other.v0, self.v1 / other.v1, self.v2 / other.v2)
+    }
+}
+
+impl<T: VecData> DivAssign<T> for Vec3<T> {
+    fn div_assign(&mut self, other: T) {
+        *self = *self / other;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f32 = 1e-6;
+
+    fn assert_vec_eq(a: &Vec3<f32>, b: &Vec3<f32>) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < EPS, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn indexing() {
+        let mut v = Vec3::new(1, 2, 3);
+        assert_eq!((v[0], v[1], v[2]), (1, 2, 3));
+        v[1] = 5;
+        assert_eq!(v.v1, 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn index_out_of_range_panics() {
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        let _ = v[3];
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn index_mut_out_of_range_panics() {
+        let mut v = Vec3::new(1.0, 2.0, 3.0);
+        v[3] = 0.0;
+    }
+
+    #[test]
+    fn checked_indexing() {
+        let mut v = Vec3::new(1, 2, 3);
+        assert_eq!(v.get(2), Some(&3));
+        assert_eq!(v.get(3), None);
+        *v.get_mut(0).unwrap() = 7;
+        assert_eq!(v.v0, 7);
+        assert!(v.get_mut(usize::MAX).is_none());
+    }
+
+    #[test]
+    fn operators() {
+        let a = Vec3::new(1.0, 2.0, 3.0);
+        let b = Vec3::new(4.0, -5.0, 6.0);
+        assert_eq!(a + b, Vec3::new(5.0, -3.0, 9.0));
+        assert_eq!(Add::add(&a, &b), a + b);
+        assert_eq!(a - b, Vec3::new(-3.0, 7.0, -3.0));
+        assert_eq!(Sub::sub(&a, &b), a - b);
+        assert_eq!(-a, Vec3::new(-1.0, -2.0, -3.0));
+        assert_eq!(-&a, neg(&a));
+        assert_eq!(a * 2.0, Vec3::new(2.0, 4.0, 6.0));
+        assert_eq!(2.0 * a, a * 2.0);
+        assert_eq!(&a * 2.0, a * 2.0);
+        assert_eq!(a * b, Vec3::new(4.0, -10.0, 18.0));
+        assert_eq!(Mul::mul(&a, &b), a * b);
+        assert_eq!(b / 2.0, Vec3::new(2.0, -2.5, 3.0));
+        assert_eq!(&b / 2.0, b / 2.0);
+        assert_eq!(b / a, Vec3::new(4.0, -2.5, 2.0));
+    }
+
+    #[test]
+    fn assign_operators() {
+        let mut v = Vec3::new(10, 20, 30);
+        v -= Vec3::new(1, 2, 3);
+        assert_eq!(v, Vec3::new(9, 18, 27));
+        v += Vec3::new(1, 2, 3);
+        assert_eq!(v, Vec3::new(10, 20, 30));
+        v *= Vec3::new(2, 3, 4);
+        assert_eq!(v, Vec3::new(20, 60, 120));
+        v *= 2;
+        assert_eq!(v, Vec3::new(40, 120, 240));
+        v /= 2;
+        assert_eq!(v, Vec3::new(20, 60, 120));
+        v.scale(-1);
+        assert_eq!(v, Vec3::new(-20, -60, -120));
+
+        let mut f = Vec3::new(1.0, 2.0, 3.0);
+        f *= 3.0;
+        assert_eq!(f, Vec3::new(3.0, 6.0, 9.0));
+        f /= 3.0;
+        assert_eq!(f, Vec3::new(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn dot_and_cross() {
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        let y = Vec3::new(0.0, 1.0, 0.0);
+        let z = Vec3::new(0.0, 0.0, 1.0);
+        assert_eq!(dot(&x, &y), 0.0);
+        assert_eq!(dot(&Vec3::new(1, 2, 3), &Vec3::new(4, 5, 6)), 32);
+        assert_eq!(cross(&x, &y), z);
+        assert_eq!(cross(&y, &z), x);
+        assert_eq!(cross(&z, &x), y);
+        assert_eq!(cross(&y, &x), -z);
+        assert_eq!(cross(&x, &x), Vec3::default());
+    }
+
+    #[test]
+    fn length_and_normalize() {
+        let v = Vec3::new(3.0, 0.0, 4.0);
+        assert_eq!(v.length_squared(), 25.0);
+        assert_eq!(v.length(), 5.0);
+        assert_vec_eq(&v.normalize(), &Vec3::new(0.6, 0.0, 0.8));
+        assert!((v.normalize().length() - 1.0).abs() < EPS);
+    }
+
+    #[test]
+    fn normalize_degenerate() {
+        let zero: Vec3<f32> = Vec3::default();
+        assert_eq!(zero.normalize(), zero);
+        assert_eq!(zero.try_normalize(), None);
+        assert_eq!(Vec3::new(f32::INFINITY, 0.0, 0.0).try_normalize(), None);
+        assert_eq!(Vec3::new(f32::NAN, 0.0, 0.0).try_normalize(), None);
+        // tiny but non zero vectors still normalize
+        let tiny = Vec3::new(1e-15, 0.0, 0.0);
+        assert_vec_eq(&tiny.normalize(), &Vec3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn lerp() {
+        let a = Vec3::new(0.0, 10.0, -2.0);
+        let b = Vec3::new(4.0, 20.0, 2.0);
+        assert_eq!(a.lerp(&b, 0.0), a);
+        assert_eq!(a.lerp(&b, 1.0), b);
+        assert_eq!(a.lerp(&b, 0.25), Vec3::new(1.0, 12.5, -1.0));
+        assert_eq!(a.lerp(&b, 2.0), Vec3::new(8.0, 30.0, 6.0));
+    }
+
+    #[test]
+    fn min_max_abs() {
+        let a = Vec3::new(1.0, -5.0, 3.0);
+        let b = Vec3::new(-2.0, 4.0, 3.0);
+        assert_eq!(a.min(&b), Vec3::new(-2.0, -5.0, 3.0));
+        assert_eq!(a.max(&b), Vec3::new(1.0, 4.0, 3.0));
+        assert_eq!(a.abs(), Vec3::new(1.0, 5.0, 3.0));
+        assert_eq!(Vec3::new(-1, 0, 1).abs(), Vec3::new(1, 0, 1));
+        assert_eq!(Vec3::splat(2), Vec3::new(2, 2, 2));
+    }
+
+    #[test]
+    fn reflection() {
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let v = Vec3::new(1.0, -1.0, 0.0);
+        assert_vec_eq(&reflect(&v, &n), &Vec3::new(1.0, 1.0, 0.0));
+        // grazing and head on rays
+        assert_vec_eq(
+            &reflect(&Vec3::new(1.0, 0.0, 0.0), &n),
+            &Vec3::new(1.0, 0.0, 0.0),
+        );
+        assert_vec_eq(&reflect(&-n, &n), &n);
+    }
+
+    #[test]
+    fn refraction() {
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        // head on rays pass straight through
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        assert_vec_eq(&refract(&down, &n, 1.0 / 1.5).unwrap(), &down);
+
+        // matched indices do not bend
+        let v = Vec3::new(1.0, -1.0, 0.0).normalize();
+        assert_vec_eq(&refract(&v, &n, 1.0).unwrap(), &v);
+
+        // snell's law, sin(t) = eta * sin(i)
+        let eta = 1.0 / 1.5;
+        let t = refract(&v, &n, eta).unwrap();
+        assert!((t.length() - 1.0).abs() < 1e-5);
+        assert!((t.v0 - eta * v.v0).abs() < 1e-5);
+        assert!(t.v1 < 0.0);
+    }
+
+    #[test]
+    fn total_internal_reflection() {
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let v = Vec3::new(1.0, -0.2, 0.0).normalize();
+        assert_eq!(refract(&v, &n, 1.5), None);
+    }
+}
@@ -0,0 +1,1134 @@
+//! Golden image regression tests.
+//!
+//! Every test renders a reference scene offscreen and compares it against the
+//! image checked in under `tests/golden`, using a per-pixel tolerance and PSNR.
+//! On failure the actual render and a diff image (failing pixels in red) are
+//! written to cargo's target tmp dir and their paths are printed.
+//!
+//! Run `UPDATE_GOLDEN=1 cargo test --test golden` to rewrite the references
+//! after an intentional change in output.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use graphics_from_scratch::{
+    color::{Color, BLACK, BLUE, GRAY, GREEN, ORANGE, PURPLE, RED, YELLOW},
+    framebuffer::{Framebuffer, Rgba8Buffer},
+    rasterizer::{
+        self,
+        antialiasing::{AntiAliasing, SampleCount},
+        blending::BlendMode,
+        camera::{Camera, Projection},
+        culling::{CullStats, InstanceBvh},
+        data_types::{Cube, Instance, Mat4x4, Model, Quaternion, Triangle, Vertex3},
+        light::{Light, LightType, ShadingModel},
+        main::RenderContext,
+        primitives,
+        scene_graph::SceneGraph,
+        shader::{self, Shader, Uniforms, Varyings, VertexInput, VertexOutput},
+        shadows::ShadowSettings,
+        texture::Texture,
+        wireframe::RenderMode,
+    },
+    raytracer::{render, scene::Scene},
+};
+
+const WIDTH: usize = 128;
+const HEIGHT: usize = 128;
+
+#[derive(Debug, Clone, Copy)]
+struct Tolerance {
+    /// largest allowed difference of a single channel before a pixel counts as bad
+    per_channel: u8,
+    /// fraction of pixels allowed to be bad, absorbs libm differences on shadow edges
+    bad_pixel_fraction: f64,
+    /// lowest allowed PSNR over the whole image, in dB
+    min_psnr: f64,
+}
+
+const DEFAULT_TOLERANCE: Tolerance = Tolerance {
+    per_channel: 2,
+    bad_pixel_fraction: 0.001,
+    min_psnr: 40.0,
+};
+
+/// RGB8 image, the alpha channel of renders is ignored
+#[derive(Debug, PartialEq)]
+struct Rgb {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl Rgb {
+    fn from_buffer(buffer: &Rgba8Buffer) -> Self {
+        let data = buffer
+            .pixels()
+            .iter()
+            .flat_map(|p| [p[0], p[1], p[2]])
+            .collect();
+        Self {
+            width: buffer.width(),
+            height: buffer.height(),
+            data,
+        }
+    }
+
+    /// binary PPM (P6), small enough to check in and readable by most image viewers
+    fn write_ppm(&self, path: &Path) {
+        let mut bytes = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        bytes.extend_from_slice(&self.data);
+        fs::write(path, bytes).unwrap_or_else(|e| panic!("writing {}: {}", path.display(), e));
+    }
+
+    fn read_ppm(path: &Path) -> Option<Self> {
+        let bytes = fs::read(path).ok()?;
+        // header is "P6", width, height and max value separated by whitespace
+        let mut fields = Vec::new();
+        let mut pos = 0;
+        while fields.len() < 4 {
+            while bytes.get(pos)?.is_ascii_whitespace() {
+                pos += 1;
+            }
+            let start = pos;
+            while !bytes.get(pos)?.is_ascii_whitespace() {
+                pos += 1;
+            }
+            fields.push(std::str::from_utf8(&bytes[start..pos]).ok()?.to_owned());
+        }
+        pos += 1;
+
+        if fields[0] != "P6" || fields[3] != "255" {
+            panic!("{} is not an 8 bit binary PPM", path.display());
+        }
+        let width: usize = fields[1].parse().ok()?;
+        let height: usize = fields[2].parse().ok()?;
+        let data = bytes.get(pos..pos + width * height * 3)?.to_vec();
+        Some(Self {
+            width,
+            height,
+            data,
+        })
+    }
+}
+
+struct Comparison {
+    bad_pixels: usize,
+    psnr: f64,
+    diff: Rgb,
+}
+
+fn compare(expected: &Rgb, actual: &Rgb, per_channel: u8) -> Comparison {
+    let mut bad_pixels = 0;
+    let mut squared_error = 0.0;
+    let mut diff = Vec::with_capacity(expected.data.len());
+
+    for (e, a) in expected
+        .data
+        .chunks_exact(3)
+        .zip(actual.data.chunks_exact(3))
+    {
+        let mut worst = 0;
+        for c in 0..3 {
+            let d = e[c].abs_diff(a[c]);
+            worst = worst.max(d);
+            squared_error += f64::from(d) * f64::from(d);
+        }
+        if worst > per_channel {
+            bad_pixels += 1;
+            diff.extend_from_slice(&[255, 0, 0]);
+        } else {
+            // dimmed grey copy of the reference so the red pixels can be placed
+            let grey = ((u16::from(e[0]) + u16::from(e[1]) + u16::from(e[2])) / 9) as u8;
+            diff.extend_from_slice(&[grey, grey, grey]);
+        }
+    }
+
+    let mse = squared_error / expected.data.len() as f64;
+    let psnr = if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    };
+
+    Comparison {
+        bad_pixels,
+        psnr,
+        diff: Rgb {
+            width: expected.width,
+            height: expected.height,
+            data: diff,
+        },
+    }
+}
+
+fn reference_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.ppm", name))
+}
+
+fn output_path(name: &str, kind: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(format!("{}.{}.ppm", name, kind))
+}
+
+fn updating() -> bool {
+    std::env::var("UPDATE_GOLDEN").is_ok_and(|v| !v.is_empty() && v != "0")
+}
+
+fn check(name: &str, buffer: &Rgba8Buffer, tolerance: Tolerance) {
+    let actual = Rgb::from_buffer(buffer);
+    let reference = reference_path(name);
+
+    if updating() {
+        fs::create_dir_all(reference.parent().unwrap()).unwrap();
+        actual.write_ppm(&reference);
+        println!("updated {}", reference.display());
+        return;
+    }
+
+    let expected = Rgb::read_ppm(&reference).unwrap_or_else(|| {
+        panic!(
+            "missing or unreadable reference {}, run with UPDATE_GOLDEN=1 to create it",
+            reference.display()
+        )
+    });
+    assert_eq!(
+        (expected.width, expected.height),
+        (actual.width, actual.height),
+        "{}: reference size differs from the render",
+        name
+    );
+
+    let result = compare(&expected, &actual, tolerance.per_channel);
+    let max_bad = (tolerance.bad_pixel_fraction * (actual.width * actual.height) as f64) as usize;
+    if result.bad_pixels > max_bad || result.psnr < tolerance.min_psnr {
+        let actual_path = output_path(name, "actual");
+        let diff_path = output_path(name, "diff");
+        actual.write_ppm(&actual_path);
+        result.diff.write_ppm(&diff_path);
+        panic!(
+            "{}: {} pixels off by more than {} (max {}), PSNR {:.2}dB (min {:.2}dB)\n\
+             actual: {}\ndiff: {}",
+            name,
+            result.bad_pixels,
+            tolerance.per_channel,
+            max_bad,
+            result.psnr,
+            tolerance.min_psnr,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+fn blank_buffer() -> Rgba8Buffer {
+    Rgba8Buffer::new(WIDTH, HEIGHT, BLACK)
+}
+
+/// ambient plus a directional light from the top left, shared by most rasterizer scenes
+fn key_lights() -> [Light; 2] {
+    [
+        Light::new(LightType::Ambient, 0.3, None, None),
+        Light::new(
+            LightType::Directional,
+            0.7,
+            None,
+            Some(Vertex3::new(-1., -2., -3.)),
+        ),
+    ]
+}
+
+/// `instances` seen from `camera` through `ctx`, into a fresh buffer and depth buffer
+fn render_golden(
+    ctx: &RenderContext,
+    camera: &Camera,
+    instances: &[Instance],
+    lights: &[Light],
+) -> Rgba8Buffer {
+    let mut buffer = Rgba8Buffer::new(ctx.width, ctx.height, BLACK);
+    let mut depth_buf = ctx.depth_buffer();
+    rasterizer::render::render_scene(&mut buffer, ctx, camera, instances, lights, &mut depth_buf);
+    buffer
+}
+
+#[test]
+fn raytracer_test_scene() {
+    let mut buffer = blank_buffer();
+    render::run(&mut buffer, WIDTH as f32, HEIGHT as f32);
+    check("raytracer_test_scene", &buffer, DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn raytracer_pbr_scene() {
+    let mut buffer = blank_buffer();
+    let mut scene = Scene::pbr_test_scene();
+    let camera = render::test_camera();
+    render::render_scene(
+        &mut buffer,
+        &mut scene,
+        &camera,
+        WIDTH as f32,
+        HEIGHT as f32,
+    );
+    check("raytracer_pbr_scene", &buffer, DEFAULT_TOLERANCE);
+}
+
+fn render_cube_scene(shading: ShadingModel) -> Rgba8Buffer {
+    let mut buffer = blank_buffer();
+    let ctx = RenderContext::new(WIDTH, HEIGHT).with_shading_model(shading);
+    rasterizer::main::run(&mut buffer, &ctx);
+    buffer
+}
+
+#[test]
+fn rasterizer_cube_scene() {
+    let buffer = render_cube_scene(ShadingModel::Phong);
+    check("rasterizer_cube_scene", &buffer, DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn rasterizer_flat_shading() {
+    let buffer = render_cube_scene(ShadingModel::Flat);
+    check("rasterizer_flat_shading", &buffer, DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn rasterizer_gouraud_shading() {
+    let buffer = render_cube_scene(ShadingModel::Gouraud);
+    check("rasterizer_gouraud_shading", &buffer, DEFAULT_TOLERANCE);
+}
+
+fn render_anti_aliased(ctx: RenderContext, anti_aliasing: AntiAliasing) -> Rgba8Buffer {
+    let mut buffer = blank_buffer();
+    let ctx = ctx
+        .with_render_mode(RenderMode::Filled)
+        .with_anti_aliasing(anti_aliasing);
+    rasterizer::main::run(&mut buffer, &ctx);
+    buffer
+}
+
+#[test]
+fn rasterizer_anti_aliasing() {
+    let ctx = RenderContext::new(WIDTH, HEIGHT);
+    let msaa = render_anti_aliased(ctx, AntiAliasing::Multisample(SampleCount::X4));
+    check("rasterizer_msaa_4x", &msaa, DEFAULT_TOLERANCE);
+    let ssaa = render_anti_aliased(ctx, AntiAliasing::Supersample(SampleCount::X8));
+    check("rasterizer_ssaa_8x", &ssaa, DEFAULT_TOLERANCE);
+
+    // flat shading is the same all over a triangle, so shading once per pixel
+    // gives the same edges as shading every sample
+    let flat = ctx.with_shading_model(ShadingModel::Flat);
+    for count in [SampleCount::X2, SampleCount::X4, SampleCount::X8] {
+        let msaa = render_anti_aliased(flat, AntiAliasing::Multisample(count));
+        assert!(msaa == render_anti_aliased(flat, AntiAliasing::Supersample(count)));
+        assert!(msaa != render_anti_aliased(flat, AntiAliasing::Off));
+    }
+}
+
+#[test]
+fn rasterizer_textured_scene() {
+    let mut buffer = blank_buffer();
+    rasterizer::main::run_textured(&mut buffer, &RenderContext::new(WIDTH, HEIGHT));
+    check("rasterizer_textured_scene", &buffer, DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn rasterizer_concurrent_renders() {
+    // two viewports of different sizes and settings rendered at the same time
+    let contexts = [
+        RenderContext::new(WIDTH, HEIGHT),
+        RenderContext::new(WIDTH / 2, HEIGHT / 2).with_shading_model(ShadingModel::Flat),
+    ];
+    let render = |ctx: &RenderContext| {
+        let mut buffer = Rgba8Buffer::new(ctx.width, ctx.height, BLACK);
+        rasterizer::main::run(&mut buffer, ctx);
+        buffer
+    };
+
+    let concurrent: Vec<Rgba8Buffer> = std::thread::scope(|s| {
+        let handles: Vec<_> = contexts
+            .iter()
+            .map(|ctx| s.spawn(move || render(ctx)))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    for (ctx, buffer) in contexts.iter().zip(&concurrent) {
+        assert!(*buffer == render(ctx));
+    }
+    check("rasterizer_cube_scene", &concurrent[0], DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn rasterizer_clipped_scene() {
+    let ctx = RenderContext::new(WIDTH, HEIGHT);
+
+    let camera = Camera::new(
+        Vertex3::new(0., 0., 0.),
+        rasterizer::utils::make_rotation_mat(0.),
+    );
+    let cube = Model::from(&Cube::debug_cube());
+    let instances = vec![
+        // crosses the left edge of the screen
+        Instance::new(&cube, Vertex3::new(-3., 0., 4.), None, None),
+        // crosses the near plane
+        Instance::new(
+            &cube,
+            Vertex3::new(1.5, 1., 1.5),
+            Some(rasterizer::utils::make_rotation_mat(30.)),
+            None,
+        ),
+    ];
+    let buffer = render_golden(&ctx, &camera, &instances, &key_lights());
+    check("rasterizer_clipped_scene", &buffer, DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn rasterizer_primitives_scene() {
+    let ctx = RenderContext::new(WIDTH, HEIGHT);
+
+    let camera = Camera::new(
+        Vertex3::new(0., -3.5, 0.),
+        // looking down at the scene
+        Mat4x4::from_rotation_x(-0.35),
+    );
+    let tilt = Some(Mat4x4::from_rotation_x(-0.4));
+    let models = [
+        primitives::uv_sphere(0.8, 16, 8, RED),
+        primitives::icosphere(0.8, 2, GREEN),
+        primitives::capsule(0.5, 0.8, 16, 4, BLUE),
+        primitives::cylinder(0.7, 1.4, 16, YELLOW),
+        primitives::cone(0.8, 1.6, 16, ORANGE),
+        primitives::torus(0.6, 0.25, 24, 12, PURPLE),
+    ];
+    let floor = primitives::plane(10., 8., 5, 4, GRAY);
+    let mut instances = vec![Instance::new(&floor, Vertex3::new(0., 0.9, 9.), None, None)];
+    for (i, model) in models.iter().enumerate() {
+        let (row, col) = ((i / 3) as f32, (i % 3) as f32);
+        let position = Vertex3::new(col * 2.4 - 2.4 + row * 0.6, -row * 1.2, 7. + row * 3.);
+        instances.push(Instance::new(model, position, tilt, None));
+    }
+    let buffer = render_golden(
+        &ctx.with_render_mode(RenderMode::Filled),
+        &camera,
+        &instances,
+        &key_lights(),
+    );
+    check("rasterizer_primitives_scene", &buffer, DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn rasterizer_scene_graph() {
+    let ctx = RenderContext::new(WIDTH, HEIGHT).with_render_mode(RenderMode::Filled);
+    let camera = Camera::new(Vertex3::new(0., -1.5, 0.), Mat4x4::from_rotation_x(-0.2));
+
+    let base = primitives::cylinder(0.8, 0.4, 16, GRAY);
+    let joint = primitives::uv_sphere(0.3, 12, 6, YELLOW);
+    let segment = primitives::capsule(0.2, 1.2, 12, 3, BLUE);
+    let hand = primitives::cone(0.3, 0.5, 12, RED);
+    let translation =
+        |x: f32, y: f32, z: f32| rasterizer::utils::make_translation_mat(Vertex3::new(x, y, z));
+
+    // a two segment arm, each joint bends everything further along it
+    let mut graph = SceneGraph::new();
+    let root = graph.add(None, Some(&base), translation(0., 1., 6.));
+    let shoulder = graph.add(
+        Some(root),
+        Some(&joint),
+        rasterizer::utils::mul_mm(translation(0., -0.5, 0.), Mat4x4::from_rotation_z(0.6)),
+    );
+    let upper = graph.add(Some(shoulder), Some(&segment), translation(0., -0.9, 0.));
+    let elbow = graph.add(
+        Some(upper),
+        Some(&joint),
+        rasterizer::utils::mul_mm(translation(0., -0.9, 0.), Mat4x4::from_rotation_z(-1.4)),
+    );
+    let fore = graph.add(Some(elbow), Some(&segment), translation(0., -0.9, 0.));
+    graph.add(Some(fore), Some(&hand), translation(0., -1., 0.));
+
+    let buffer = render_golden(&ctx, &camera, &graph.instances(), &key_lights());
+    check("rasterizer_scene_graph", &buffer, DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn rasterizer_orthographic() {
+    let mut buffer = blank_buffer();
+    let ctx = RenderContext::new(WIDTH, HEIGHT).with_render_mode(RenderMode::Filled);
+    // looking down at the cubes, which stay the same size however far away they are
+    let camera = Camera::new(Vertex3::new(0., -6., 0.), Mat4x4::from_rotation_x(-0.6))
+        .with_projection(Projection::Orthographic {
+            width: 6.,
+            height: 6.,
+            near: 1.,
+            far: 20.,
+        });
+    let cube = Model::from(&Cube::debug_cube());
+    let instances: Vec<Instance> = (0..3)
+        .map(|i| {
+            let position = Vertex3::new(i as f32 * 2. - 2., 0., 6. + i as f32 * 3.);
+            Instance::new(
+                &cube,
+                position,
+                Some(rasterizer::utils::make_rotation_mat(30.)),
+                Some(0.5),
+            )
+        })
+        .collect();
+    let mut depth_buf = vec![0.; WIDTH * HEIGHT];
+    rasterizer::render::render_scene(
+        &mut buffer,
+        &ctx,
+        &camera,
+        &instances,
+        &key_lights(),
+        &mut depth_buf,
+    );
+    check("rasterizer_orthographic", &buffer, DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn rasterizer_wide_canvas_keeps_proportions() {
+    let (width, height) = (WIDTH * 2, HEIGHT);
+    let mut buffer = Rgba8Buffer::new(width, height, BLACK);
+    let ctx = RenderContext::new(width, height).with_render_mode(RenderMode::Filled);
+    let camera = Camera::new(Vertex3::ZERO, Mat4x4::IDENTITY).with_aspect(ctx.aspect());
+    let sphere = primitives::uv_sphere(1., 32, 16, RED);
+    let instances = vec![Instance::new(&sphere, Vertex3::new(0., 0., 5.), None, None)];
+    let mut depth_buf = vec![0.; width * height];
+    rasterizer::render::render_scene(
+        &mut buffer,
+        &ctx,
+        &camera,
+        &instances,
+        &key_lights(),
+        &mut depth_buf,
+    );
+
+    let covered: Vec<(usize, usize)> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .filter(|&(x, y)| buffer.get_pixel(x, y).unwrap() != BLACK)
+        .collect();
+    let extent = |axis: fn(&(usize, usize)) -> usize| {
+        covered.iter().map(axis).max().unwrap() - covered.iter().map(axis).min().unwrap()
+    };
+    let (across, down) = (extent(|p| p.0), extent(|p| p.1));
+    assert!(
+        across.abs_diff(down) <= 1,
+        "sphere is {}x{} pixels",
+        across,
+        down
+    );
+}
+
+#[test]
+fn rasterizer_transforms() {
+    use rasterizer::utils::{
+        make_axis_rotation_mat, make_euler_rotation_mat, make_quaternion_rotation_mat,
+    };
+
+    let mut buffer = blank_buffer();
+    let ctx = RenderContext::new(WIDTH, HEIGHT).with_render_mode(RenderMode::Filled);
+    // above the scene, pitched down and rolled a little
+    let camera = Camera::new(
+        Vertex3::new(0., -4., 1.),
+        make_euler_rotation_mat(0., -32., 5.),
+    );
+
+    let sphere = primitives::uv_sphere(1., 24, 12, RED);
+    let torus = primitives::torus(0.8, 0.3, 24, 12, PURPLE);
+    let cube = Model::from(&Cube::debug_cube());
+    let instances = vec![
+        // squashed, the lighting has to follow the flattened shape
+        Instance::new(&sphere, Vertex3::new(-1.5, 0., 6.), None, None)
+            .with_scale(Vertex3::new(1.2, 0.4, 1.2)),
+        // stood on its edge
+        Instance::new(
+            &torus,
+            Vertex3::new(1.5, -0.5, 6.),
+            Some(make_quaternion_rotation_mat(Quaternion::from_rotation_x(
+                1.2,
+            ))),
+            None,
+        ),
+        // balanced on a corner
+        Instance::new(
+            &cube,
+            Vertex3::new(0.5, 0., 9.),
+            Some(make_axis_rotation_mat(Vertex3::new(1., 0., 1.), 45.)),
+            Some(0.7),
+        ),
+    ];
+    let mut depth_buf = vec![0.; WIDTH * HEIGHT];
+    rasterizer::render::render_scene(
+        &mut buffer,
+        &ctx,
+        &camera,
+        &instances,
+        &key_lights(),
+        &mut depth_buf,
+    );
+    check("rasterizer_transforms", &buffer, DEFAULT_TOLERANCE);
+}
+
+/// a torus in front of a sphere, so part of the sphere's edges are hidden
+fn render_modes_scene(ctx: &RenderContext) -> Rgba8Buffer {
+    let mut buffer = blank_buffer();
+    let camera = Camera::new(Vertex3::new(0., -1., 0.), Mat4x4::from_rotation_x(-0.15));
+    let sphere = primitives::icosphere(1.2, 1, BLUE);
+    let torus = primitives::torus(0.9, 0.3, 16, 8, ORANGE);
+    let instances = vec![
+        Instance::new(&sphere, Vertex3::new(0.4, 0., 7.), None, None),
+        Instance::new(
+            &torus,
+            Vertex3::new(-0.6, 0., 5.),
+            Some(Mat4x4::from_rotation_x(1.2)),
+            None,
+        ),
+    ];
+    let mut depth_buf = ctx.depth_buffer();
+    rasterizer::render::render_scene(
+        &mut buffer,
+        ctx,
+        &camera,
+        &instances,
+        &key_lights(),
+        &mut depth_buf,
+    );
+    buffer
+}
+
+#[test]
+fn rasterizer_render_modes() {
+    let ctx = RenderContext::new(WIDTH, HEIGHT);
+    let wireframe = render_modes_scene(&ctx.with_render_mode(RenderMode::Wireframe));
+    check("rasterizer_wireframe", &wireframe, DEFAULT_TOLERANCE);
+    let overlay = render_modes_scene(
+        &ctx.with_render_mode(RenderMode::FilledWireframe)
+            .with_anti_aliased_lines(true),
+    );
+    check("rasterizer_wireframe_overlay", &overlay, DEFAULT_TOLERANCE);
+
+    // the overlay only adds edges to the filled render, where they aren't hidden
+    let filled = render_modes_scene(&ctx.with_render_mode(RenderMode::Filled));
+    let changed = |a: &Rgba8Buffer, b: &Rgba8Buffer| {
+        a.pixels()
+            .iter()
+            .zip(b.pixels())
+            .filter(|(a, b)| a != b)
+            .count()
+    };
+    let edges = changed(&filled, &overlay);
+    assert!(edges > 0);
+    assert!(changed(&filled, &wireframe) > edges);
+}
+
+/// a thin box in one color, both sides of it face the camera somewhere
+fn pane(color: Color, blend: BlendMode) -> Model {
+    let mut pane = Model::from(&Cube::debug_cube()).with_blend(blend);
+    for t in pane.triangles.iter_mut() {
+        t.color = color;
+    }
+    pane
+}
+
+/// An opaque sphere on a floor behind a glass pane, with a tinted pane across the
+/// glass and a glowing one partly hidden behind the sphere. `reversed` submits the
+/// panes in the opposite order.
+fn render_transparency_scene(ctx: &RenderContext, reversed: bool) -> Rgba8Buffer {
+    let mut buffer = blank_buffer();
+    let camera = Camera::new(Vertex3::new(0., -1., 0.), Mat4x4::from_rotation_x(-0.15));
+    let sphere = primitives::icosphere(1.2, 2, RED);
+    let floor = primitives::plane(12., 12., 4, 4, GRAY);
+    let glass = pane(Color::new(0.6, 0.9, 1., 0.35), BlendMode::Alpha);
+    let tint = pane(Color::new(1., 0.8, 0.2, 0.8), BlendMode::Multiply);
+    let glow = pane(Color::new(0.2, 0.4, 1., 0.7), BlendMode::Additive);
+    let mut panes = vec![
+        Instance::new(&glass, Vertex3::new(-0.8, 0., 5.), None, None)
+            .with_scale(Vertex3::new(1.4, 1.2, 0.02)),
+        Instance::new(&tint, Vertex3::new(-1.2, 0.6, 4.), None, None)
+            .with_scale(Vertex3::new(0.8, 0.5, 0.02)),
+        Instance::new(&glow, Vertex3::new(1.2, -0.3, 9.5), None, None)
+            .with_scale(Vertex3::new(1.5, 1., 0.02)),
+    ];
+    if reversed {
+        panes.reverse();
+    }
+    let mut instances = vec![
+        Instance::new(&floor, Vertex3::new(0., 1.2, 8.), None, None),
+        Instance::new(&sphere, Vertex3::new(0.5, 0., 8.), None, None),
+    ];
+    instances.extend(panes);
+    let mut depth_buf = ctx.depth_buffer();
+    rasterizer::render::render_scene(
+        &mut buffer,
+        ctx,
+        &camera,
+        &instances,
+        &key_lights(),
+        &mut depth_buf,
+    );
+    buffer
+}
+
+#[test]
+fn rasterizer_transparency() {
+    let ctx = RenderContext::new(WIDTH, HEIGHT).with_render_mode(RenderMode::Filled);
+    let image = render_transparency_scene(&ctx, false);
+    check("rasterizer_transparency", &image, DEFAULT_TOLERANCE);
+
+    // sorted back to front, whatever order they come in
+    assert!(render_transparency_scene(&ctx, true) == image);
+    let ctx = ctx.with_anti_aliasing(AntiAliasing::Multisample(SampleCount::X4));
+    let serial = render_transparency_scene(&ctx, false);
+    assert!(render_transparency_scene(&ctx.with_threads(3), true) == serial);
+}
+
+/// A sphere, a box and a torus on a floor, lit by `lights`.
+fn render_shadow_scene(ctx: &RenderContext, lights: &[Light]) -> Rgba8Buffer {
+    let mut buffer = blank_buffer();
+    let camera = Camera::new(Vertex3::new(0., -3., -1.), Mat4x4::from_rotation_x(-0.45));
+    let floor = primitives::plane(10., 10., 8, 8, GRAY);
+    let sphere = primitives::icosphere(0.8, 2, RED);
+    let cube = Model::from(&Cube::debug_cube());
+    let torus = primitives::torus(0.7, 0.25, 24, 12, ORANGE);
+    let instances = vec![
+        Instance::new(&floor, Vertex3::new(0., 1., 6.), None, None),
+        Instance::new(&sphere, Vertex3::new(-1.2, 0.2, 6.), None, None),
+        Instance::new(&cube, Vertex3::new(1.3, 0.4, 7.), None, Some(0.6)),
+        Instance::new(
+            &torus,
+            Vertex3::new(0.6, 0.2, 4.5),
+            Some(Mat4x4::from_rotation_z(0.5)),
+            None,
+        ),
+    ];
+    let mut depth_buf = ctx.depth_buffer();
+    rasterizer::render::render_scene(
+        &mut buffer,
+        ctx,
+        &camera,
+        &instances,
+        lights,
+        &mut depth_buf,
+    );
+    buffer
+}
+
+#[test]
+fn rasterizer_shadows() {
+    let ctx = RenderContext::new(WIDTH, HEIGHT).with_render_mode(RenderMode::Filled);
+    let sun = vec![
+        Light::new(LightType::Ambient, 0.25, None, None),
+        Light::new(
+            LightType::Directional,
+            0.75,
+            None,
+            Some(Vertex3::new(1., -3., 2.)),
+        ),
+    ];
+    let lamps = vec![
+        Light::new(LightType::Ambient, 0.15, None, None),
+        Light::new(
+            LightType::Spot,
+            0.8,
+            Some(Vertex3::new(2., -4., 4.)),
+            Some(Vertex3::new(1.5, -4., -2.)),
+        )
+        .with_spot_angle(40.),
+        Light::new(
+            LightType::Point,
+            0.5,
+            Some(Vertex3::new(-0.5, -2.5, 5.5)),
+            None,
+        ),
+    ];
+    let shadows = ctx.with_shadows(Some(ShadowSettings::default()));
+    let directional = render_shadow_scene(&shadows, &sun);
+    check(
+        "rasterizer_shadows_directional",
+        &directional,
+        DEFAULT_TOLERANCE,
+    );
+    let spot_and_point = render_shadow_scene(&shadows, &lamps);
+    check(
+        "rasterizer_shadows_spot_point",
+        &spot_and_point,
+        DEFAULT_TOLERANCE,
+    );
+
+    // shadows only ever take light away
+    let lit = render_shadow_scene(&ctx, &sun);
+    let darker = lit
+        .pixels()
+        .iter()
+        .zip(directional.pixels())
+        .filter(|(lit, shadowed)| lit != shadowed)
+        .count();
+    assert!(darker > 100);
+    assert!(lit
+        .pixels()
+        .iter()
+        .zip(directional.pixels())
+        .all(|(lit, shadowed)| (0..3).all(|c| shadowed[c] <= lit[c])));
+    assert!(render_shadow_scene(&shadows.with_threads(3), &lamps) == spot_and_point);
+}
+
+/// Phong lighting rounded up to quarters.
+#[derive(Debug)]
+struct Toon;
+
+impl Shader for Toon {
+    fn vertex(&self, uniforms: &Uniforms, input: &VertexInput) -> VertexOutput {
+        shader::Phong.vertex(uniforms, input)
+    }
+
+    fn fragment(&self, uniforms: &Uniforms, varyings: &Varyings) -> Option<Color> {
+        let lit = uniforms
+            .view
+            .illumination(varyings.vec3(0), varyings.vec3(3), 0.);
+        let band = (lit * 4.).ceil().min(4.) / 4.;
+        let c = uniforms.triangle.color;
+        Some(Color::new(c.r * band, c.g * band, c.b * band, c.a))
+    }
+}
+
+/// Flat colored stripes along the uvs with the gaps between them discarded.
+#[derive(Debug)]
+struct Stripes;
+
+impl Shader for Stripes {
+    fn vertex(&self, uniforms: &Uniforms, input: &VertexInput) -> VertexOutput {
+        VertexOutput {
+            position: uniforms.view.projection.to_clip(input.position),
+            varyings: Varyings::new(&[input.uv.x]),
+        }
+    }
+
+    fn fragment(&self, uniforms: &Uniforms, varyings: &Varyings) -> Option<Color> {
+        ((varyings.get(0) * 4.).fract() < 0.5).then_some(uniforms.triangle.color)
+    }
+}
+
+/// A toon shaded sphere and a striped box you can see through, on an unlit
+/// checkerboard floor.
+fn render_shader_scene(ctx: &RenderContext) -> Rgba8Buffer {
+    let mut buffer = blank_buffer();
+    let camera = Camera::new(Vertex3::new(0., -2., 0.), Mat4x4::from_rotation_x(-0.3));
+    let checkers = Arc::new(Texture::checkerboard(64, 4, YELLOW, PURPLE));
+    let floor = primitives::plane(8., 8., 1, 1, GRAY)
+        .with_texture(checkers)
+        .with_shader(Arc::new(shader::Textured));
+    let sphere = primitives::icosphere(0.9, 2, RED).with_shader(Arc::new(Toon));
+    let cube = Model::from(&Cube::debug_cube()).with_shader(Arc::new(Stripes));
+    let instances = vec![
+        Instance::new(&floor, Vertex3::new(0., 1., 6.), None, None),
+        Instance::new(&sphere, Vertex3::new(-1., 0.1, 6.), None, None),
+        Instance::new(
+            &cube,
+            Vertex3::new(1.2, 0.2, 5.5),
+            Some(Mat4x4::from_rotation_y(0.6)),
+            Some(0.7),
+        ),
+    ];
+    let mut depth_buf = ctx.depth_buffer();
+    rasterizer::render::render_scene(
+        &mut buffer,
+        ctx,
+        &camera,
+        &instances,
+        &key_lights(),
+        &mut depth_buf,
+    );
+    buffer
+}
+
+#[test]
+fn rasterizer_custom_shaders() {
+    let ctx = RenderContext::new(WIDTH, HEIGHT).with_render_mode(RenderMode::Filled);
+    let image = render_shader_scene(&ctx);
+    check("rasterizer_custom_shaders", &image, DEFAULT_TOLERANCE);
+    assert!(render_shader_scene(&ctx.with_threads(3)) == image);
+
+    // a model with the built-in shader draws like one without
+    let render_sphere = |sphere: &Model| {
+        let mut buffer = blank_buffer();
+        let instances = vec![Instance::new(sphere, Vertex3::new(0., 0., 4.), None, None)];
+        let mut depth_buf = ctx.depth_buffer();
+        rasterizer::render::render_scene(
+            &mut buffer,
+            &ctx.with_shading_model(ShadingModel::Gouraud),
+            &Camera::default(),
+            &instances,
+            &key_lights(),
+            &mut depth_buf,
+        );
+        buffer
+    };
+    let sphere = primitives::icosphere(1., 2, BLUE);
+    let built_in = render_sphere(&sphere);
+    assert!(
+        render_sphere(&primitives::icosphere(1., 2, BLUE).with_shader(Arc::new(shader::Gouraud)))
+            == built_in
+    );
+}
+
+/// The book's gradient triangle with a red, a green and a blue corner, unlit and
+/// reaching past the left edge so it's clipped, and a lit sphere with a color per
+/// vertex fading from top to bottom.
+fn render_vertex_color_scene(ctx: &RenderContext) -> Rgba8Buffer {
+    let mut buffer = blank_buffer();
+    let corners = vec![
+        Vertex3::new(-3., -1.5, 0.),
+        Vertex3::new(1., 0., 0.),
+        Vertex3::new(-0.8, 1.5, 0.),
+    ];
+    let gradient = Model::from_mesh(vec![Triangle::new(0, 2, 1, GRAY)], corners)
+        .with_vertex_colors(vec![RED, GREEN, BLUE])
+        .with_shader(Arc::new(shader::Textured));
+    let sphere = primitives::icosphere(0.9, 2, GRAY);
+    let colors = sphere
+        .verticies
+        .iter()
+        .map(|v| {
+            let t = (v.y / 0.9 + 1.) / 2.;
+            Color::new(1. - t, 0.6, t, 1.)
+        })
+        .collect();
+    let sphere = sphere.with_vertex_colors(colors);
+    let instances = vec![
+        Instance::new(&gradient, Vertex3::new(0., 0., 4.), None, None),
+        Instance::new(&sphere, Vertex3::new(1.2, 0., 4.5), None, None),
+    ];
+    let mut depth_buf = ctx.depth_buffer();
+    rasterizer::render::render_scene(
+        &mut buffer,
+        ctx,
+        &Camera::default(),
+        &instances,
+        &key_lights(),
+        &mut depth_buf,
+    );
+    buffer
+}
+
+#[test]
+fn rasterizer_vertex_colors() {
+    let ctx = RenderContext::new(WIDTH, HEIGHT).with_render_mode(RenderMode::Filled);
+    let image = render_vertex_color_scene(&ctx);
+    check("rasterizer_vertex_colors", &image, DEFAULT_TOLERANCE);
+    assert!(render_vertex_color_scene(&ctx.with_threads(3)) == image);
+    for shading in [ShadingModel::Flat, ShadingModel::Gouraud] {
+        let shaded = render_vertex_color_scene(&ctx.with_shading_model(shading));
+        assert!(
+            render_vertex_color_scene(&ctx.with_shading_model(shading).with_threads(3)) == shaded
+        );
+    }
+}
+
+/// A grid of cubes of different heights seen down one of the streets between
+/// them, most of them behind the camera or off to the sides.
+fn city(cube: &Model) -> Vec<Instance<'_>> {
+    let mut instances = vec![];
+    for row in 0..40 {
+        for col in 0..40 {
+            let height = 1. + ((row * 7 + col * 13) % 5) as f32;
+            let position = Vertex3::new(
+                col as f32 * 3. - 60.,
+                1. - height / 2.,
+                row as f32 * 3. - 60.,
+            );
+            let instance = Instance::new(cube, position, None, None);
+            instances.push(instance.with_scale(Vertex3::new(1., height / 2., 1.)));
+        }
+    }
+    instances
+}
+
+#[test]
+fn rasterizer_culled_city() {
+    let ctx = RenderContext::new(WIDTH, HEIGHT);
+    let camera = Camera::new(
+        Vertex3::new(1.5, -6., -40.),
+        Mat4x4::from_rotation_y(0.25) * Mat4x4::from_rotation_x(-0.35),
+    );
+    let cube = Model::from(&Cube::debug_cube());
+    let instances = city(&cube);
+    let bvh = InstanceBvh::new(&instances);
+    let render_lit = |ctx: &RenderContext, culled: bool, lights: &[Light]| {
+        let mut buffer = blank_buffer();
+        let mut depth_buf = ctx.depth_buffer();
+        let stats = if culled {
+            rasterizer::render::render_scene_culled(
+                &mut buffer,
+                ctx,
+                &camera,
+                &instances,
+                &bvh,
+                lights,
+                &mut depth_buf,
+            )
+        } else {
+            rasterizer::render::render_scene(
+                &mut buffer,
+                ctx,
+                &camera,
+                &instances,
+                lights,
+                &mut depth_buf,
+            );
+            CullStats::default()
+        };
+        (buffer, depth_buf, stats)
+    };
+    let render = |ctx: &RenderContext, culled: bool| render_lit(ctx, culled, &key_lights());
+
+    let (image, depth, stats) = render(&ctx, true);
+    check("rasterizer_culled_city", &image, DEFAULT_TOLERANCE);
+    assert_eq!(stats.instances, 1600);
+    assert_eq!(stats.drawn + stats.culled, 1600);
+    assert!(stats.culled > 1000 && stats.drawn > 100, "{}", stats);
+    assert!(stats.tests < 1600, "{}", stats);
+    // culling only skips what wouldn't be drawn
+    let (all, all_depth, _) = render(&ctx, false);
+    assert!(all == image && all_depth == depth);
+    let threaded = ctx.with_threads(3);
+    assert!(render(&threaded, true).0 == render(&threaded, false).0);
+    // neither do the shadow casters it leaves out of each light's frustum
+    let shadowed = ctx.with_shadows(Some(ShadowSettings::default().with_resolution(256)));
+    let spot = Light::new(
+        LightType::Spot,
+        0.7,
+        Some(Vertex3::new(0., -15., -25.)),
+        Some(Vertex3::new(0., -1., -1.)),
+    )
+    .with_spot_angle(25.);
+    let lights = [key_lights()[0], spot];
+    let (image, depth, _) = render_lit(&shadowed, true, &lights);
+    let (all, all_depth, _) = render_lit(&shadowed, false, &lights);
+    assert!(all == image && all_depth == depth);
+}
+
+/// Tens of thousands of small triangles on a canvas that doesn't divide into
+/// whole tiles, some crossing the screen edges and the near plane.
+fn render_dense_scene(ctx: &RenderContext) -> (Rgba8Buffer, Vec<f32>) {
+    let mut buffer = Rgba8Buffer::new(ctx.width, ctx.height, BLACK);
+    let camera = Camera::new(Vertex3::new(0., -2., 0.), Mat4x4::from_rotation_x(-0.3))
+        .with_aspect(ctx.aspect());
+    let colors = [RED, GREEN, BLUE, YELLOW, ORANGE, PURPLE];
+    let spheres: Vec<Model> = colors
+        .iter()
+        .map(|color| primitives::icosphere(0.7, 3, *color))
+        .collect();
+    let floor = primitives::plane(30., 30., 30, 30, GRAY);
+    let mut instances = vec![Instance::new(&floor, Vertex3::new(0., 1., 10.), None, None)];
+    for row in 0..4 {
+        for col in 0..6 {
+            let position = Vertex3::new(col as f32 * 1.6 - 4.4, 0., 1.5 + row as f32 * 2.5);
+            instances.push(Instance::new(
+                &spheres[(row + col) % spheres.len()],
+                position,
+                None,
+                None,
+            ));
+        }
+    }
+    let mut depth_buf = ctx.depth_buffer();
+    rasterizer::render::render_scene(
+        &mut buffer,
+        ctx,
+        &camera,
+        &instances,
+        &key_lights(),
+        &mut depth_buf,
+    );
+    (buffer, depth_buf)
+}
+
+#[test]
+fn rasterizer_tiled_matches_serial() {
+    let ctx = RenderContext::new(150, 100);
+    for ctx in [
+        ctx,
+        ctx.with_render_mode(RenderMode::Filled)
+            .with_shading_model(ShadingModel::Gouraud),
+        ctx.with_shading_model(ShadingModel::Flat),
+        ctx.with_anti_aliasing(AntiAliasing::Multisample(SampleCount::X4)),
+        ctx.with_anti_aliasing(AntiAliasing::Supersample(SampleCount::X2)),
+        ctx.with_render_mode(RenderMode::Wireframe),
+        ctx.with_anti_aliased_lines(true)
+            .with_anti_aliasing(AntiAliasing::Multisample(SampleCount::X4)),
+    ] {
+        let serial = render_dense_scene(&ctx);
+        for threads in [2, 5] {
+            let tiled = render_dense_scene(&ctx.with_threads(threads));
+            assert!(
+                tiled.0 == serial.0,
+                "image differs with {} threads",
+                threads
+            );
+            // compare bits, the empty parts are 0
+            let bits = |depth: &[f32]| depth.iter().map(|d| d.to_bits()).collect::<Vec<_>>();
+            assert_eq!(
+                bits(&tiled.1),
+                bits(&serial.1),
+                "depth differs with {} threads",
+                threads
+            );
+        }
+    }
+
+    // the reference scenes come out the same as well, textures included
+    for textured in [false, true] {
+        let render = |ctx: &RenderContext| {
+            let mut buffer = blank_buffer();
+            if textured {
+                rasterizer::main::run_textured(&mut buffer, ctx);
+            } else {
+                rasterizer::main::run(&mut buffer, ctx);
+            }
+            buffer
+        };
+        let ctx = RenderContext::new(WIDTH, HEIGHT);
+        assert!(render(&ctx.with_threads(3)) == render(&ctx));
+    }
+}
+
+#[test]
+fn comparison_flags_changed_pixels() {
+    let expected = Rgb {
+        width: 2,
+        height: 1,
+        data: vec![10, 20, 30, 200, 200, 200],
+    };
+    let same = compare(&expected, &expected, 0);
+    assert_eq!(same.bad_pixels, 0);
+    assert_eq!(same.psnr, f64::INFINITY);
+
+    let actual = Rgb {
+        width: 2,
+        height: 1,
+        data: vec![11, 20, 30, 200, 100, 200],
+    };
+    let result = compare(&expected, &actual, 1);
+    assert_eq!(result.bad_pixels, 1);
+    assert_eq!(&result.diff.data[3..], &[255, 0, 0]);
+    assert!(result.psnr < 20.0);
+}
+
+#[test]
+fn ppm_roundtrip() {
+    let image = Rgb {
+        width: 3,
+        height: 2,
+        data: (0..18).collect(),
+    };
+    let path = output_path("ppm_roundtrip", "test");
+    image.write_ppm(&path);
+    assert_eq!(Rgb::read_ppm(&path), Some(image));
+}